@@ -1,6 +1,4 @@
-mod openbook;
-
-use openbook::{Order, OrderBook, Side};
+use step01_minimal_market::openbook::{Order, OrderBook, Side};
 
 fn main() {
     println!("=== 最小化订单簿演示 ===");
@@ -17,6 +17,12 @@ pub struct OrderBook {
     asks: Vec<Order>, // 卖单簿（价格升序）
 }
 
+impl Default for OrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl OrderBook {
     pub fn new() -> Self {
         Self {
@@ -53,7 +59,7 @@ impl OrderBook {
                 // 如果有剩余未成交，插入买单簿
                 if order.quantity > 0 {
                     self.bids.push(order);
-                    self.bids.sort_by(|a, b| b.price.cmp(&a.price)); // 价格降序
+                    self.bids.sort_by_key(|o| std::cmp::Reverse(o.price)); // 价格降序
                 }
             }
             Side::Ask => {
@@ -79,7 +85,7 @@ impl OrderBook {
                 }
                 if order.quantity > 0 {
                     self.asks.push(order);
-                    self.asks.sort_by(|a, b| a.price.cmp(&b.price)); // 价格升序
+                    self.asks.sort_by_key(|o| o.price); // 价格升序
                 }
             }
         }
@@ -1,4 +1,37 @@
 use std::collections::HashMap;
+use std::fmt;
+
+// 错误类型：下单、撤单失败的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DexError {
+    Overflow,            // 数值溢出（价格 × 数量、余额累加超出 u64）
+    InsufficientBalance, // 余额不足
+    OrderNotFound,       // 订单不存在或不属于该用户
+}
+
+impl fmt::Display for DexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DexError::Overflow => write!(f, "数值溢出"),
+            DexError::InsufficientBalance => write!(f, "余额不足"),
+            DexError::OrderNotFound => write!(f, "订单不存在"),
+        }
+    }
+}
+
+// 账本算术：余额和金额的加减乘都走 checked 运算，溢出返回 DexError::Overflow，
+// 而不是在 debug 下 panic、在 release 下静默回绕
+pub fn checked_add(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_add(b).ok_or(DexError::Overflow)
+}
+
+pub fn checked_sub(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_sub(b).ok_or(DexError::Overflow)
+}
+
+pub fn checked_mul(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_mul(b).ok_or(DexError::Overflow)
+}
 
 // 订单方向：买单 or 卖单
 #[derive(Debug, Clone)]
@@ -39,6 +72,12 @@ pub struct OrderBook {
 - 这里用HashMap仅做本地模拟，方便理解流程，实际部署应严格依赖区块链账户模型。
 */
 
+impl Default for OrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl OrderBook {
     pub fn new() -> Self {
         Self {
@@ -49,46 +88,140 @@ impl OrderBook {
         }
     }
 
-    // 用户充值（模拟现实中链上转账到合约或账户）
-    pub fn deposit(&mut self, user: &str, base: u64, quote: u64) {
+    // 用户充值（模拟现实中链上转账到合约或账户），余额溢出时拒绝充值，余额不变
+    pub fn deposit(&mut self, user: &str, base: u64, quote: u64) -> Result<(), DexError> {
         let bal = self.balances.entry(user.to_string()).or_default();
-        bal.base += base;
-        bal.quote += quote;
+        let (Ok(new_base), Ok(new_quote)) =
+            (checked_add(bal.base, base), checked_add(bal.quote, quote))
+        else {
+            println!("充值失败，用户 {} 余额溢出", user);
+            return Err(DexError::Overflow);
+        };
+        bal.base = new_base;
+        bal.quote = new_quote;
         println!("用户 {} 充值：主币 {}，报价币 {}", user, base, quote);
+        Ok(())
+    }
+
+    // 查询用户余额（主币，报价币）
+    pub fn balance(&self, user: &str) -> (u64, u64) {
+        self.balances
+            .get(user)
+            .map(|bal| (bal.base, bal.quote))
+            .unwrap_or((0, 0))
+    }
+
+    // 所有挂单（先买单后卖单），买单冻结 价格 × 数量 的报价币，卖单冻结数量等额的主币
+    pub fn orders(&self) -> impl Iterator<Item = &Order> {
+        self.bids.iter().chain(self.asks.iter())
+    }
+
+    // 成交结算：买家得主币，卖家得报价币（成交价 × 数量）
+    // 先算出所有新余额，任一溢出则返回错误、不做任何变更
+    fn settle_fill(
+        &mut self,
+        buyer: &str,
+        seller: &str,
+        price: u64,
+        qty: u64,
+    ) -> Result<(), DexError> {
+        let (buyer_base, _) = self.balance(buyer);
+        let (_, seller_quote) = self.balance(seller);
+        let buyer_base = checked_add(buyer_base, qty)?;
+        let seller_quote = checked_add(seller_quote, checked_mul(price, qty)?)?;
+        self.balances.entry(buyer.to_string()).or_default().base = buyer_base;
+        self.balances.entry(seller.to_string()).or_default().quote = seller_quote;
+        Ok(())
     }
 
-    // 下单：校验余额 -> 撮合 -> 未成交部分入订单簿 -> 冻结余额
+    // 返还冻结的资产（买单价差/撤单），溢出时返回错误、余额不变
+    fn refund(&mut self, user: &str, side: &Side, amount: u64) -> Result<(), DexError> {
+        let bal = self.balances.entry(user.to_string()).or_default();
+        match side {
+            Side::Bid => bal.quote = checked_add(bal.quote, amount)?,
+            Side::Ask => bal.base = checked_add(bal.base, amount)?,
+        }
+        Ok(())
+    }
+
+    // 下单前的结算预检：按当前订单簿模拟整笔订单的冻结、每一笔成交和买单的价差返还，
+    // 任一步余额不足或溢出都返回错误。预检不修改任何状态，通过后实际撮合不会再失败
+    fn check_settlement(
+        &self,
+        owner: &str,
+        side: &Side,
+        price: u64,
+        quantity: u64,
+    ) -> Result<(), DexError> {
+        // 只记录本单涉及用户的（主币，报价币）余额
+        let mut balances: HashMap<&str, (u64, u64)> = HashMap::new();
+        let (base, quote) = self.balance(owner);
+        let locked = match side {
+            Side::Bid => checked_sub(quote, checked_mul(price, quantity)?).map(|q| (base, q)),
+            Side::Ask => checked_sub(base, quantity).map(|b| (b, quote)),
+        };
+        let (base, quote) = locked.map_err(|_| DexError::InsufficientBalance)?;
+        balances.insert(owner, (base, quote));
+
+        let mut remaining = quantity;
+        let makers = match side {
+            Side::Bid => &self.asks,
+            Side::Ask => &self.bids,
+        };
+        for maker in makers {
+            let crossed = match side {
+                Side::Bid => price >= maker.price,
+                Side::Ask => price <= maker.price,
+            };
+            if remaining == 0 || !crossed {
+                break;
+            }
+            let qty = remaining.min(maker.quantity);
+            let (buyer, seller) = match side {
+                Side::Bid => (owner, maker.owner.as_str()),
+                Side::Ask => (maker.owner.as_str(), owner),
+            };
+            let buyer_bal = balances.get(buyer).copied();
+            let buyer_bal = buyer_bal.unwrap_or_else(|| self.balance(buyer));
+            // 买单吃单按卖单价成交，限价与成交价之差返还给买家
+            let improvement = match side {
+                Side::Bid => checked_mul(price - maker.price, qty)?,
+                Side::Ask => 0,
+            };
+            let buyer_quote = checked_add(buyer_bal.1, improvement)?;
+            balances.insert(buyer, (checked_add(buyer_bal.0, qty)?, buyer_quote));
+            let seller_bal = balances.get(seller).copied();
+            let seller_bal = seller_bal.unwrap_or_else(|| self.balance(seller));
+            let notional = checked_mul(maker.price, qty)?;
+            balances.insert(seller, (seller_bal.0, checked_add(seller_bal.1, notional)?));
+            remaining -= qty;
+        }
+        Ok(())
+    }
+
+    // 下单：结算预检 -> 冻结余额 -> 撮合 -> 未成交部分入订单簿
+    // 余额不足或任一笔结算溢出时拒绝整笔订单，订单簿和余额保持不变
     pub fn place_order(
         &mut self,
         owner: &str,
         side: Side,
         price: u64,
         quantity: u64,
-    ) -> Option<u64> {
-        let mut quantity = quantity;
-        // 1. 校验余额
+    ) -> Result<u64, DexError> {
+        // 1. 结算预检
+        if let Err(err) = self.check_settlement(owner, &side, price, quantity) {
+            println!("下单失败，用户 {}：{}", owner, err);
+            return Err(err);
+        }
+
+        // 2. 冻结余额（买单冻结报价币，卖单冻结主币；未成交部分挂单后保持冻结，撤单时返还）
         let bal = self.balances.entry(owner.to_string()).or_default();
         match side {
-            Side::Bid => {
-                // 买单：需要冻结报价币
-                let needed_quote = price * quantity;
-                if bal.quote < needed_quote {
-                    println!("下单失败，用户 {} 报价币余额不足", owner);
-                    return None;
-                }
-                bal.quote -= needed_quote; // 先全部冻结，未成交部分后返还
-            }
-            Side::Ask => {
-                // 卖单：需要冻结主币
-                if bal.base < quantity {
-                    println!("下单失败，用户 {} 主币余额不足", owner);
-                    return None;
-                }
-                bal.base -= quantity;
-            }
+            Side::Bid => bal.quote = checked_sub(bal.quote, checked_mul(price, quantity)?)?,
+            Side::Ask => bal.base = checked_sub(bal.base, quantity)?,
         }
 
-        // 2. 创建订单
+        // 3. 创建订单
         let order_id = self.next_order_id;
         self.next_order_id += 1;
         let mut order = Order {
@@ -99,17 +232,18 @@ impl OrderBook {
             quantity,
         };
 
-        // 3. 尝试撮合
+        // 4. 尝试撮合
         match side {
             Side::Bid => {
                 while let Some(mut best_ask) = self.asks.first().cloned() {
                     if order.price >= best_ask.price && order.quantity > 0 {
                         let qty = order.quantity.min(best_ask.quantity);
                         // 结算：买家付报价币，卖家得报价币；卖家付主币，买家得主币
-                        self.balances.get_mut(&order.owner).unwrap().base += qty;
-                        self.balances.get_mut(&best_ask.owner).unwrap().quote +=
-                            best_ask.price * qty;
-                        self.balances.get_mut(&best_ask.owner).unwrap().base += 0; // 这里可以扣减已锁定主币，但已在挂单时扣除了
+                        // （卖家的主币已在挂单时扣除）
+                        self.settle_fill(&order.owner, &best_ask.owner, best_ask.price, qty)?;
+                        // 按限价冻结、按卖单价成交，价差返还给买家
+                        let improvement = checked_mul(order.price - best_ask.price, qty)?;
+                        self.refund(&order.owner, &Side::Bid, improvement)?;
 
                         println!(
                             "撮合成交: 买家:{} 卖家:{} 价:{} 数量:{}",
@@ -128,12 +262,9 @@ impl OrderBook {
                     }
                 }
                 if order.quantity > 0 {
-                    // 未成交部分，返还部分报价币
-                    let refund = (order.price * order.quantity) as u64;
-                    self.balances.get_mut(&order.owner).unwrap().quote += refund;
-                    // 挂入订单簿
+                    // 未成交部分挂入订单簿，对应的报价币保持冻结
                     self.bids.push(order.clone());
-                    self.bids.sort_by(|a, b| b.price.cmp(&a.price));
+                    self.bids.sort_by_key(|o| std::cmp::Reverse(o.price));
                     println!(
                         "买单部分未成交，剩余数量 {} 进入订单簿，订单ID={}",
                         order.quantity, order.id
@@ -145,8 +276,7 @@ impl OrderBook {
                     if order.price <= best_bid.price && order.quantity > 0 {
                         let qty = order.quantity.min(best_bid.quantity);
                         // 结算：卖家得报价币，买家得主币
-                        self.balances.get_mut(&order.owner).unwrap().quote += best_bid.price * qty;
-                        self.balances.get_mut(&best_bid.owner).unwrap().base += qty;
+                        self.settle_fill(&best_bid.owner, &order.owner, best_bid.price, qty)?;
 
                         println!(
                             "撮合成交: 卖家:{} 买家:{} 价:{} 数量:{}",
@@ -165,11 +295,9 @@ impl OrderBook {
                     }
                 }
                 if order.quantity > 0 {
-                    // 未成交部分，返还主币
-                    self.balances.get_mut(&order.owner).unwrap().base += order.quantity;
-                    // 挂入订单簿
+                    // 未成交部分挂入订单簿，对应的主币保持冻结
                     self.asks.push(order.clone());
-                    self.asks.sort_by(|a, b| a.price.cmp(&b.price));
+                    self.asks.sort_by_key(|o| o.price);
                     println!(
                         "卖单部分未成交，剩余数量 {} 进入订单簿，订单ID={}",
                         order.quantity, order.id
@@ -177,23 +305,31 @@ impl OrderBook {
                 }
             }
         }
-        Some(order_id)
+        Ok(order_id)
     }
 
-    // 撤单：指定订单ID撤销挂单
-    pub fn cancel_order(&mut self, user: &str, order_id: u64) -> bool {
+    // 撤单：指定订单ID撤销挂单，返还溢出时返回错误、订单保留
+    pub fn cancel_order(&mut self, user: &str, order_id: u64) -> Result<(), DexError> {
         // 买单
         if let Some(pos) = self
             .bids
             .iter()
             .position(|o| o.id == order_id && o.owner == user)
         {
-            let order = self.bids.remove(pos);
             // 返还未成交部分的报价币
-            let refund = order.price * order.quantity;
-            self.balances.get_mut(user).unwrap().quote += refund;
+            let order = &self.bids[pos];
+            let refund = checked_mul(order.price, order.quantity)
+                .and_then(|refund| self.refund(user, &Side::Bid, refund).map(|_| refund));
+            let refund = match refund {
+                Ok(refund) => refund,
+                Err(err) => {
+                    println!("撤单失败：{}，订单ID={}", err, order_id);
+                    return Err(err);
+                }
+            };
+            self.bids.remove(pos);
             println!("撤销买单，返还报价币 {}，订单ID={}", refund, order_id);
-            return true;
+            return Ok(());
         }
         // 卖单
         if let Some(pos) = self
@@ -201,14 +337,18 @@ impl OrderBook {
             .iter()
             .position(|o| o.id == order_id && o.owner == user)
         {
-            let order = self.asks.remove(pos);
             // 返还未成交部分的主币
-            self.balances.get_mut(user).unwrap().base += order.quantity;
-            println!("撤销卖单，返还主币 {}，订单ID={}", order.quantity, order_id);
-            return true;
+            let quantity = self.asks[pos].quantity;
+            if let Err(err) = self.refund(user, &Side::Ask, quantity) {
+                println!("撤单失败：{}，订单ID={}", err, order_id);
+                return Err(err);
+            }
+            self.asks.remove(pos);
+            println!("撤销卖单，返还主币 {}，订单ID={}", quantity, order_id);
+            return Ok(());
         }
         println!("撤单失败，未找到属于用户 {} 的订单ID={}", user, order_id);
-        false
+        Err(DexError::OrderNotFound)
     }
 
     pub fn print_book(&self) {
@@ -0,0 +1,169 @@
+use step02_orderbook_balance_cancel::openbook::{
+    checked_add, checked_mul, checked_sub, DexError, OrderBook, Side,
+};
+
+/// 简单的线性同余随机数（不引入外部依赖，失败时可按 seed 复现）
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// 偏向边界的随机数：小数、2 的幂、u32/u64 上限附近
+    fn extreme(&mut self) -> u64 {
+        match self.below(5) {
+            0 => self.below(1_000) + 1,
+            1 => 1u64 << self.below(64),
+            2 => u32::MAX as u64 - self.below(1_000),
+            3 => u64::MAX - self.below(1_000),
+            _ => (self.next() << 31) | self.next(),
+        }
+    }
+}
+
+#[test]
+fn test_checked_helpers() {
+    assert_eq!(checked_add(u64::MAX - 1, 1), Ok(u64::MAX));
+    assert_eq!(checked_add(u64::MAX, 1), Err(DexError::Overflow));
+    assert_eq!(checked_sub(0, 1), Err(DexError::Overflow));
+    assert_eq!(checked_mul(u64::MAX, 2), Err(DexError::Overflow));
+    assert_eq!(checked_mul(u32::MAX as u64, 2), Ok(u32::MAX as u64 * 2));
+}
+
+#[test]
+fn test_extreme_values_are_rejected() {
+    let mut book = OrderBook::new();
+
+    // 充值溢出：拒绝，余额不变
+    book.deposit("A", 0, u64::MAX).unwrap();
+    assert_eq!(book.deposit("A", 1, 1), Err(DexError::Overflow));
+    assert_eq!(book.balance("A"), (0, u64::MAX));
+
+    // 价格 × 数量溢出：拒绝下单，余额不变
+    assert_eq!(
+        book.place_order("A", Side::Bid, u64::MAX, 2),
+        Err(DexError::Overflow)
+    );
+    assert_eq!(book.balance("A"), (0, u64::MAX));
+
+    // 成交时卖家报价币余额会溢出：整笔卖单被拒绝，订单簿和双方余额都不变
+    let bid = book.place_order("A", Side::Bid, 2, 10).unwrap();
+    assert_eq!(book.balance("A"), (0, u64::MAX - 20));
+    book.deposit("B", 10, u64::MAX).unwrap();
+    assert_eq!(
+        book.place_order("B", Side::Ask, 2, 10),
+        Err(DexError::Overflow)
+    );
+    assert_eq!(book.balance("A"), (0, u64::MAX - 20));
+    assert_eq!(book.balance("B"), (10, u64::MAX));
+
+    // 买单仍在簿上且未被穿价：同价卖单由不会溢出的用户提交时正常成交
+    book.deposit("C", 10, 0).unwrap();
+    assert!(book.place_order("C", Side::Ask, 2, 10).is_ok());
+    assert_eq!(book.balance("A").0, 10);
+    assert_eq!(book.balance("C"), (0, 20));
+    assert_eq!(book.cancel_order("A", bid), Err(DexError::OrderNotFound));
+}
+
+#[test]
+fn test_cancel_refund_overflow_keeps_order() {
+    let mut book = OrderBook::new();
+    book.deposit("A", 10, 0).unwrap();
+    let ask = book.place_order("A", Side::Ask, 2, 10).unwrap();
+
+    // 撤单返还溢出：返回错误，订单保留、余额不变，之后仍可被成交
+    assert_eq!(book.balance("A"), (0, 0));
+    book.deposit("A", u64::MAX, 0).unwrap();
+    assert_eq!(book.cancel_order("A", ask), Err(DexError::Overflow));
+    assert_eq!(book.balance("A"), (u64::MAX, 0));
+    book.deposit("B", 0, 20).unwrap();
+    assert!(book.place_order("B", Side::Bid, 2, 10).is_ok());
+    assert_eq!(book.balance("B"), (10, 0));
+}
+
+#[test]
+fn test_resting_remainder_stays_locked_and_price_improvement_is_refunded() {
+    let mut book = OrderBook::new();
+    book.deposit("A", 10, 0).unwrap();
+    book.deposit("B", 0, 100).unwrap();
+    book.place_order("A", Side::Ask, 2, 4).unwrap();
+
+    // 限价 5 买 10 个：4 个按卖单价 2 成交，价差 (5 - 2) × 4 返还，剩余 6 个挂单冻结 30
+    let bid = book.place_order("B", Side::Bid, 5, 10).unwrap();
+    assert_eq!(book.balance("A"), (6, 8));
+    assert_eq!(book.balance("B"), (4, 100 - 8 - 30));
+
+    // 撤单只返还一次冻结的 30
+    book.cancel_order("B", bid).unwrap();
+    assert_eq!(book.balance("B"), (4, 92));
+    assert_conserved(&book, &["A", "B"], (10, 100));
+}
+
+/// 总量守恒：所有用户余额 + 挂单冻结的资金 = 累计充值（用 u128 计算，不会溢出）
+fn assert_conserved(book: &OrderBook, users: &[&str], deposited: (u128, u128)) {
+    let mut held = (0u128, 0u128);
+    for user in users {
+        let (base, quote) = book.balance(user);
+        held.0 += base as u128;
+        held.1 += quote as u128;
+    }
+    for order in book.orders() {
+        match order.side {
+            Side::Bid => held.1 += order.price as u128 * order.quantity as u128,
+            Side::Ask => held.0 += order.quantity as u128,
+        }
+    }
+    assert_eq!(held, deposited);
+}
+
+/// 随机充值/下单/撤单，价格和数量偏向边界值：任何操作都不能 panic，每一步之后总量守恒
+#[test]
+fn test_fuzz_extreme_prices_and_quantities() {
+    let users = ["A", "B", "C"];
+    for seed in 0..16 {
+        let mut rng = Lcg(seed);
+        let mut book = OrderBook::new();
+        let mut ids = vec![];
+        let mut deposited = (0u128, 0u128);
+        for user in users {
+            book.deposit(user, u64::MAX / 4, u64::MAX / 4).unwrap();
+            deposited.0 += (u64::MAX / 4) as u128;
+            deposited.1 += (u64::MAX / 4) as u128;
+        }
+        for _ in 0..300 {
+            let user = users[rng.below(3) as usize];
+            match rng.below(6) {
+                0 => {
+                    let (base, quote) = (rng.extreme(), rng.extreme());
+                    if book.deposit(user, base, quote).is_ok() {
+                        deposited.0 += base as u128;
+                        deposited.1 += quote as u128;
+                    }
+                }
+                1 if !ids.is_empty() => {
+                    let id = ids[rng.below(ids.len() as u64) as usize];
+                    let _ = book.cancel_order(user, id);
+                }
+                _ => {
+                    let side = match rng.below(2) {
+                        0 => Side::Bid,
+                        _ => Side::Ask,
+                    };
+                    if let Ok(id) = book.place_order(user, side, rng.extreme(), rng.extreme()) {
+                        ids.push(id);
+                    }
+                }
+            }
+            assert_conserved(&book, &users, deposited);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::fmt;
 
 /*
 注释说明：
@@ -7,6 +8,45 @@ use std::collections::HashMap;
 - 订单ID每市场自增，防止不同市场订单ID冲突。
 */
 
+/// 错误类型：下单、撤单失败的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DexError {
+    /// 数值溢出（价格 × 数量、余额累加超出 u64）
+    Overflow,
+    /// 余额不足
+    InsufficientBalance,
+    /// 订单不存在或不属于该用户
+    OrderNotFound,
+    /// 市场不存在
+    MarketNotFound,
+}
+
+impl fmt::Display for DexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DexError::Overflow => write!(f, "数值溢出"),
+            DexError::InsufficientBalance => write!(f, "余额不足"),
+            DexError::OrderNotFound => write!(f, "订单不存在"),
+            DexError::MarketNotFound => write!(f, "市场不存在"),
+        }
+    }
+}
+
+/// 账本加法，溢出返回 Overflow（不在 debug 下 panic、不在 release 下回绕）
+pub fn checked_add(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_add(b).ok_or(DexError::Overflow)
+}
+
+/// 账本减法，不足返回 Overflow
+pub fn checked_sub(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_sub(b).ok_or(DexError::Overflow)
+}
+
+/// 账本乘法（价格 × 数量），溢出返回 Overflow
+pub fn checked_mul(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_mul(b).ok_or(DexError::Overflow)
+}
+
 /// 订单方向
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Side {
@@ -41,43 +81,138 @@ pub struct MarketState {
 }
 
 impl MarketState {
-    /// 用户充值（模拟链上充值，实际链上应为账户转账）
-    pub fn deposit(&mut self, user: &str, base: u64, quote: u64) {
+    /// 用户充值（模拟链上充值，实际链上应为账户转账），余额溢出时拒绝充值，余额不变
+    pub fn deposit(&mut self, user: &str, base: u64, quote: u64) -> Result<(), DexError> {
         let bal = self.balances.entry(user.to_string()).or_default();
-        bal.base += base;
-        bal.quote += quote;
+        let (Ok(new_base), Ok(new_quote)) =
+            (checked_add(bal.base, base), checked_add(bal.quote, quote))
+        else {
+            println!("充值失败，用户 {} 余额溢出", user);
+            return Err(DexError::Overflow);
+        };
+        bal.base = new_base;
+        bal.quote = new_quote;
         println!(
             "用户 {} 在本市场充值：主币 {}，报价币 {}",
             user, base, quote
         );
+        Ok(())
+    }
+
+    /// 成交结算：买家得主币，卖家得报价币（成交价 × 数量）
+    /// 先算出所有新余额，任一溢出则返回错误、不做任何变更
+    fn settle_fill(
+        &mut self,
+        buyer: &str,
+        seller: &str,
+        price: u64,
+        qty: u64,
+    ) -> Result<(), DexError> {
+        let buyer_base = self.balances.get(buyer).map(|b| b.base).unwrap_or(0);
+        let seller_quote = self.balances.get(seller).map(|b| b.quote).unwrap_or(0);
+        let buyer_base = checked_add(buyer_base, qty)?;
+        let seller_quote = checked_add(seller_quote, checked_mul(price, qty)?)?;
+        self.balances.entry(buyer.to_string()).or_default().base = buyer_base;
+        self.balances.entry(seller.to_string()).or_default().quote = seller_quote;
+        Ok(())
+    }
+
+    /// 返还冻结的资产（买单价差/撤单），溢出时返回错误、余额不变
+    fn refund(&mut self, user: &str, side: &Side, amount: u64) -> Result<(), DexError> {
+        let bal = self.balances.entry(user.to_string()).or_default();
+        match side {
+            Side::Bid => bal.quote = checked_add(bal.quote, amount)?,
+            Side::Ask => bal.base = checked_add(bal.base, amount)?,
+        }
+        Ok(())
+    }
+
+    /// 下单前的结算预检：按当前订单簿模拟整笔订单的冻结、每一笔成交和买单的价差返还，
+    /// 任一步余额不足或溢出都返回错误。预检不修改任何状态，通过后实际撮合不会再失败
+    fn check_settlement(
+        &self,
+        owner: &str,
+        side: &Side,
+        price: u64,
+        quantity: u64,
+    ) -> Result<(), DexError> {
+        let balance_of = |user: &str| {
+            self.balances
+                .get(user)
+                .map(|b| (b.base, b.quote))
+                .unwrap_or((0, 0))
+        };
+        // 只记录本单涉及用户的（主币，报价币）余额
+        let mut balances: HashMap<&str, (u64, u64)> = HashMap::new();
+        let (base, quote) = balance_of(owner);
+        let locked = match side {
+            Side::Bid => checked_sub(quote, checked_mul(price, quantity)?).map(|q| (base, q)),
+            Side::Ask => checked_sub(base, quantity).map(|b| (b, quote)),
+        };
+        let (base, quote) = locked.map_err(|_| DexError::InsufficientBalance)?;
+        balances.insert(owner, (base, quote));
+
+        let mut remaining = quantity;
+        let makers = match side {
+            Side::Bid => &self.asks,
+            Side::Ask => &self.bids,
+        };
+        for maker in makers {
+            let crossed = match side {
+                Side::Bid => price >= maker.price,
+                Side::Ask => price <= maker.price,
+            };
+            if remaining == 0 || !crossed {
+                break;
+            }
+            let qty = remaining.min(maker.quantity);
+            let (buyer, seller) = match side {
+                Side::Bid => (owner, maker.owner.as_str()),
+                Side::Ask => (maker.owner.as_str(), owner),
+            };
+            let (buyer_base, buyer_quote) = balances
+                .get(buyer)
+                .copied()
+                .unwrap_or_else(|| balance_of(buyer));
+            // 买单吃单按卖单价成交，限价与成交价之差返还给买家
+            let improvement = match side {
+                Side::Bid => checked_mul(price - maker.price, qty)?,
+                Side::Ask => 0,
+            };
+            let buyer_quote = checked_add(buyer_quote, improvement)?;
+            balances.insert(buyer, (checked_add(buyer_base, qty)?, buyer_quote));
+            let (seller_base, seller_quote) = balances
+                .get(seller)
+                .copied()
+                .unwrap_or_else(|| balance_of(seller));
+            let notional = checked_mul(maker.price, qty)?;
+            balances.insert(seller, (seller_base, checked_add(seller_quote, notional)?));
+            remaining -= qty;
+        }
+
+        Ok(())
     }
 
     /// 下单，自动撮合，余额校验与变更
+    /// 余额不足或任一笔结算溢出时拒绝整笔订单，订单簿和余额保持不变
     pub fn place_order(
         &mut self,
         owner: &str,
         side: Side,
         price: u64,
-        mut quantity: u64,
-    ) -> Option<u64> {
-        // 余额校验
+        quantity: u64,
+    ) -> Result<u64, DexError> {
+        // 结算预检
+        if let Err(err) = self.check_settlement(owner, &side, price, quantity) {
+            println!("下单失败，用户 {}：{}", owner, err);
+            return Err(err);
+        }
+
+        // 冻结余额：未成交部分挂单后保持冻结
         let bal = self.balances.entry(owner.to_string()).or_default();
         match side {
-            Side::Bid => {
-                let needed_quote = price * quantity;
-                if bal.quote < needed_quote {
-                    println!("下单失败，用户 {} 报价币余额不足", owner);
-                    return None;
-                }
-                bal.quote -= needed_quote; // 挂单先全部冻结，未成交部分后返还
-            }
-            Side::Ask => {
-                if bal.base < quantity {
-                    println!("下单失败，用户 {} 主币余额不足", owner);
-                    return None;
-                }
-                bal.base -= quantity; // 挂单先全部冻结，未成交部分后返还
-            }
+            Side::Bid => bal.quote = checked_sub(bal.quote, checked_mul(price, quantity)?)?,
+            Side::Ask => bal.base = checked_sub(bal.base, quantity)?,
         }
 
         // 新订单
@@ -98,10 +233,11 @@ impl MarketState {
                 while let Some(mut best_ask) = self.asks.first().cloned() {
                     if order.price >= best_ask.price && order.quantity > 0 {
                         let qty = order.quantity.min(best_ask.quantity);
-                        // 结算
-                        self.balances.get_mut(&order.owner).unwrap().base += qty;
-                        self.balances.get_mut(&best_ask.owner).unwrap().quote +=
-                            best_ask.price * qty;
+                        // 结算（已预检，不会溢出）
+                        self.settle_fill(&order.owner, &best_ask.owner, best_ask.price, qty)?;
+                        // 按限价冻结、按卖单价成交，价差返还给买家
+                        let improvement = checked_mul(order.price - best_ask.price, qty)?;
+                        self.refund(&order.owner, &Side::Bid, improvement)?;
                         println!(
                             "撮合成交: 买家:{} 卖家:{} 价格:{} 数量:{}",
                             order.owner, best_ask.owner, best_ask.price, qty
@@ -119,12 +255,9 @@ impl MarketState {
                     }
                 }
                 if order.quantity > 0 {
-                    // 未成交部分返还报价币
-                    let refund = order.price * order.quantity;
-                    self.balances.get_mut(&order.owner).unwrap().quote += refund;
-                    // 剩余部分入订单簿
+                    // 剩余部分入订单簿，对应的报价币保持冻结，撤单时返还
                     self.bids.push(order.clone());
-                    self.bids.sort_by(|a, b| b.price.cmp(&a.price));
+                    self.bids.sort_by_key(|o| std::cmp::Reverse(o.price));
                     println!(
                         "买单部分未成交，剩余 {} 进入买单簿，订单ID={}",
                         order.quantity, order.id
@@ -135,8 +268,8 @@ impl MarketState {
                 while let Some(mut best_bid) = self.bids.first().cloned() {
                     if order.price <= best_bid.price && order.quantity > 0 {
                         let qty = order.quantity.min(best_bid.quantity);
-                        self.balances.get_mut(&order.owner).unwrap().quote += best_bid.price * qty;
-                        self.balances.get_mut(&best_bid.owner).unwrap().base += qty;
+                        // 结算（已预检，不会溢出）
+                        self.settle_fill(&best_bid.owner, &order.owner, best_bid.price, qty)?;
                         println!(
                             "撮合成交: 卖家:{} 买家:{} 价格:{} 数量:{}",
                             order.owner, best_bid.owner, best_bid.price, qty
@@ -154,10 +287,9 @@ impl MarketState {
                     }
                 }
                 if order.quantity > 0 {
-                    // 未成交部分返还主币
-                    self.balances.get_mut(&order.owner).unwrap().base += order.quantity;
+                    // 剩余部分入订单簿，对应的主币保持冻结，撤单时返还
                     self.asks.push(order.clone());
-                    self.asks.sort_by(|a, b| a.price.cmp(&b.price));
+                    self.asks.sort_by_key(|o| o.price);
                     println!(
                         "卖单部分未成交，剩余 {} 进入卖单簿，订单ID={}",
                         order.quantity, order.id
@@ -165,22 +297,31 @@ impl MarketState {
                 }
             }
         }
-        Some(order_id)
+        Ok(order_id)
     }
 
-    /// 撤销订单
-    pub fn cancel_order(&mut self, user: &str, order_id: u64) -> bool {
+    /// 撤销订单，返还溢出时返回错误、订单保留
+    pub fn cancel_order(&mut self, user: &str, order_id: u64) -> Result<(), DexError> {
         // 买单
         if let Some(pos) = self
             .bids
             .iter()
             .position(|o| o.id == order_id && o.owner == user)
         {
-            let order = self.bids.remove(pos);
-            let refund = order.price * order.quantity;
-            self.balances.get_mut(user).unwrap().quote += refund;
+            // 返还冻结的报价币
+            let order = &self.bids[pos];
+            let refund = match checked_mul(order.price, order.quantity)
+                .and_then(|refund| self.refund(user, &Side::Bid, refund).map(|_| refund))
+            {
+                Ok(refund) => refund,
+                Err(err) => {
+                    println!("撤单失败：{}，订单ID={}", err, order_id);
+                    return Err(err);
+                }
+            };
+            self.bids.remove(pos);
             println!("撤销买单，返还报价币 {}，订单ID={}", refund, order_id);
-            return true;
+            return Ok(());
         }
         // 卖单
         if let Some(pos) = self
@@ -188,13 +329,18 @@ impl MarketState {
             .iter()
             .position(|o| o.id == order_id && o.owner == user)
         {
-            let order = self.asks.remove(pos);
-            self.balances.get_mut(user).unwrap().base += order.quantity;
-            println!("撤销卖单，返还主币 {}，订单ID={}", order.quantity, order_id);
-            return true;
+            // 返还冻结的主币
+            let quantity = self.asks[pos].quantity;
+            if let Err(err) = self.refund(user, &Side::Ask, quantity) {
+                println!("撤单失败：{}，订单ID={}", err, order_id);
+                return Err(err);
+            }
+            self.asks.remove(pos);
+            println!("撤销卖单，返还主币 {}，订单ID={}", quantity, order_id);
+            return Ok(());
         }
         println!("撤单失败，未找到属于用户 {} 的订单ID={}", user, order_id);
-        false
+        Err(DexError::OrderNotFound)
     }
 
     /// 打印订单簿
@@ -216,6 +362,12 @@ pub struct Markets {
     pub markets: HashMap<String, MarketState>, // key: market symbol，如 "SOL/USDC"
 }
 
+impl Default for Markets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Markets {
     pub fn new() -> Self {
         Self {
@@ -225,18 +377,23 @@ impl Markets {
 
     /// 创建市场
     pub fn create_market(&mut self, market: &str) {
-        self.markets
-            .entry(market.to_string())
-            .or_insert_with(MarketState::default);
+        self.markets.entry(market.to_string()).or_default();
         println!("新市场已创建: {}", market);
     }
 
     /// 用户充值到指定市场
-    pub fn deposit(&mut self, market: &str, user: &str, base: u64, quote: u64) {
+    pub fn deposit(
+        &mut self,
+        market: &str,
+        user: &str,
+        base: u64,
+        quote: u64,
+    ) -> Result<(), DexError> {
         if let Some(state) = self.markets.get_mut(market) {
-            state.deposit(user, base, quote);
+            state.deposit(user, base, quote)
         } else {
             println!("市场 {} 不存在", market);
+            Err(DexError::MarketNotFound)
         }
     }
 
@@ -248,22 +405,27 @@ impl Markets {
         side: Side,
         price: u64,
         quantity: u64,
-    ) -> Option<u64> {
+    ) -> Result<u64, DexError> {
         if let Some(state) = self.markets.get_mut(market) {
             state.place_order(owner, side, price, quantity)
         } else {
             println!("市场 {} 不存在", market);
-            None
+            Err(DexError::MarketNotFound)
         }
     }
 
     /// 撤销订单
-    pub fn cancel_order(&mut self, market: &str, user: &str, order_id: u64) -> bool {
+    pub fn cancel_order(
+        &mut self,
+        market: &str,
+        user: &str,
+        order_id: u64,
+    ) -> Result<(), DexError> {
         if let Some(state) = self.markets.get_mut(market) {
             state.cancel_order(user, order_id)
         } else {
             println!("市场 {} 不存在", market);
-            false
+            Err(DexError::MarketNotFound)
         }
     }
 
@@ -296,28 +458,29 @@ fn main() {
     markets.create_market("BTC/USDT");
 
     // 用户A、B在SOL/USDC市场充值
-    markets.deposit("SOL/USDC", "Alice", 100, 2000);
-    markets.deposit("SOL/USDC", "Bob", 50, 1000);
+    markets.deposit("SOL/USDC", "Alice", 100, 2000).unwrap();
+    markets.deposit("SOL/USDC", "Bob", 50, 1000).unwrap();
 
     // 用户C、D在BTC/USDT市场充值
-    markets.deposit("BTC/USDT", "Carol", 10, 100000);
-    markets.deposit("BTC/USDT", "Dave", 5, 80000);
+    markets.deposit("BTC/USDT", "Carol", 10, 100000).unwrap();
+    markets.deposit("BTC/USDT", "Dave", 5, 80000).unwrap();
 
     // Alice在SOL/USDC挂买单
     let alice_bid = markets.place_order("SOL/USDC", "Alice", Side::Bid, 10, 10);
 
     // Bob在SOL/USDC挂卖单，触发撮合
-    let bob_ask = markets.place_order("SOL/USDC", "Bob", Side::Ask, 10, 5);
+    let _bob_ask = markets.place_order("SOL/USDC", "Bob", Side::Ask, 10, 5);
 
     // Carol在BTC/USDT挂买单
-    let carol_bid = markets.place_order("BTC/USDT", "Carol", Side::Bid, 20000, 2);
+    let _carol_bid = markets.place_order("BTC/USDT", "Carol", Side::Bid, 20000, 2);
 
     // Dave在BTC/USDT挂卖单，部分撮合
-    let dave_ask = markets.place_order("BTC/USDT", "Dave", Side::Ask, 19500, 3);
+    let _dave_ask = markets.place_order("BTC/USDT", "Dave", Side::Ask, 19500, 3);
 
     // Alice尝试撤销剩余买单（如果有）
-    if let Some(id) = alice_bid {
-        markets.cancel_order("SOL/USDC", "Alice", id);
+    if let Ok(id) = alice_bid {
+        // 撤单失败时 cancel_order 已打印原因，订单保留
+        let _ = markets.cancel_order("SOL/USDC", "Alice", id);
     }
 
     // 打印订单簿和余额
@@ -326,3 +489,228 @@ fn main() {
     markets.print_market_book("BTC/USDT");
     markets.print_market_balances("BTC/USDT");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MARKET: &str = "SOL/USDC";
+
+    /// 简单的线性同余随机数（不引入外部依赖，失败时可按 seed 复现）
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            self.0 >> 33
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+
+        /// 偏向边界的随机数：小数、2 的幂、u32/u64 上限附近
+        fn extreme(&mut self) -> u64 {
+            match self.below(5) {
+                0 => self.below(1_000) + 1,
+                1 => 1u64 << self.below(64),
+                2 => u32::MAX as u64 - self.below(1_000),
+                3 => u64::MAX - self.below(1_000),
+                _ => (self.next() << 31) | self.next(),
+            }
+        }
+    }
+
+    fn balance(state: &MarketState, user: &str) -> (u64, u64) {
+        state
+            .balances
+            .get(user)
+            .map(|b| (b.base, b.quote))
+            .unwrap_or((0, 0))
+    }
+
+    /// 订单簿和按用户名排序的余额，用于比较操作前后状态是否完全一致
+    fn snapshot(state: &MarketState) -> String {
+        let mut balances: Vec<_> = state.balances.iter().collect();
+        balances.sort_by_key(|(user, _)| user.as_str());
+        format!("{:?} {:?} {:?}", state.bids, state.asks, balances)
+    }
+
+    /// 总量守恒：所有用户余额 + 挂单冻结的资金 = 累计充值（用 u128 计算，不会溢出）
+    fn assert_conserved(state: &MarketState, deposited: (u128, u128)) {
+        let mut held = (0u128, 0u128);
+        for bal in state.balances.values() {
+            held.0 += bal.base as u128;
+            held.1 += bal.quote as u128;
+        }
+        for order in state.bids.iter().chain(state.asks.iter()) {
+            match order.side {
+                Side::Bid => held.1 += order.price as u128 * order.quantity as u128,
+                Side::Ask => held.0 += order.quantity as u128,
+            }
+        }
+        assert_eq!(held, deposited);
+    }
+
+    #[test]
+    fn test_deposit_overflow_and_unknown_market() {
+        let mut markets = Markets::new();
+        markets.create_market(MARKET);
+        markets.deposit(MARKET, "A", u64::MAX, 0).unwrap();
+        assert_eq!(markets.deposit(MARKET, "A", 1, 1), Err(DexError::Overflow));
+        assert_eq!(balance(&markets.markets[MARKET], "A"), (u64::MAX, 0));
+
+        assert_eq!(
+            markets.deposit("BTC/USDT", "A", 1, 1),
+            Err(DexError::MarketNotFound)
+        );
+        assert_eq!(
+            markets.place_order("BTC/USDT", "A", Side::Bid, 1, 1),
+            Err(DexError::MarketNotFound)
+        );
+        assert_eq!(
+            markets.cancel_order("BTC/USDT", "A", 0),
+            Err(DexError::MarketNotFound)
+        );
+    }
+
+    #[test]
+    fn test_check_settlement_rejects_without_changes() {
+        let mut state = MarketState::default();
+        state.deposit("A", 0, u64::MAX).unwrap();
+
+        // 价格 × 数量溢出、余额不足：拒绝下单，状态不变
+        let before = snapshot(&state);
+        assert_eq!(
+            state.place_order("A", Side::Bid, u64::MAX, 2),
+            Err(DexError::Overflow)
+        );
+        assert_eq!(
+            state.place_order("A", Side::Ask, 1, 1),
+            Err(DexError::InsufficientBalance)
+        );
+        assert_eq!(snapshot(&state), before);
+
+        // 成交时卖家报价币余额会溢出：整笔卖单被拒绝，订单簿和双方余额都不变
+        state.place_order("A", Side::Bid, 2, 10).unwrap();
+        state.deposit("B", 10, u64::MAX).unwrap();
+        let before = snapshot(&state);
+        assert_eq!(
+            state.place_order("B", Side::Ask, 2, 10),
+            Err(DexError::Overflow)
+        );
+        assert_eq!(snapshot(&state), before);
+
+        // 买家主币余额会溢出：同样整笔拒绝
+        state.deposit("A", u64::MAX, 0).unwrap();
+        state.deposit("C", 10, 0).unwrap();
+        let before = snapshot(&state);
+        assert_eq!(
+            state.place_order("C", Side::Ask, 2, 10),
+            Err(DexError::Overflow)
+        );
+        assert_eq!(snapshot(&state), before);
+    }
+
+    #[test]
+    fn test_resting_remainder_stays_locked_and_price_improvement_is_refunded() {
+        let mut state = MarketState::default();
+        state.deposit("A", 10, 0).unwrap();
+        state.deposit("B", 0, 100).unwrap();
+        state.place_order("A", Side::Ask, 2, 4).unwrap();
+
+        // 限价 5 买 10 个：4 个按卖单价 2 成交，价差 (5 - 2) × 4 返还，剩余 6 个挂单冻结 30
+        let bid = state.place_order("B", Side::Bid, 5, 10).unwrap();
+        assert_eq!(balance(&state, "A"), (6, 8));
+        assert_eq!(balance(&state, "B"), (4, 100 - 8 - 30));
+
+        // 撤单只返还一次冻结的 30
+        state.cancel_order("B", bid).unwrap();
+        assert_eq!(balance(&state, "B"), (4, 92));
+        assert_conserved(&state, (10, 100));
+    }
+
+    #[test]
+    fn test_cancel_refund_overflow_keeps_order() {
+        let mut state = MarketState::default();
+        state.deposit("A", 10, 0).unwrap();
+        let ask = state.place_order("A", Side::Ask, 2, 10).unwrap();
+
+        // 撤单返还溢出：返回错误，订单保留、余额不变，之后仍可被成交
+        state.deposit("A", u64::MAX, 0).unwrap();
+        let before = snapshot(&state);
+        assert_eq!(state.cancel_order("A", ask), Err(DexError::Overflow));
+        assert_eq!(snapshot(&state), before);
+        assert_eq!(state.cancel_order("B", ask), Err(DexError::OrderNotFound));
+
+        state.deposit("B", 0, 20).unwrap();
+        state.place_order("B", Side::Bid, 2, 10).unwrap();
+        assert_eq!(balance(&state, "B"), (10, 0));
+        assert_eq!(balance(&state, "A"), (u64::MAX, 20));
+    }
+
+    /// 随机充值/下单/撤单，价格和数量偏向边界值：任何操作都不能 panic，
+    /// 被拒绝的操作不改变状态，每一步之后每个市场总量守恒
+    #[test]
+    fn test_fuzz_extreme_prices_and_quantities() {
+        let markets_names = [MARKET, "BTC/USDT"];
+        let users = ["A", "B", "C"];
+        for seed in 0..200 {
+            let mut rng = Lcg(seed);
+            let mut markets = Markets::new();
+            let mut deposited = [(0u128, 0u128); 2];
+            for market in markets_names {
+                markets.create_market(market);
+            }
+            for (m, market) in markets_names.iter().enumerate() {
+                for user in users {
+                    markets
+                        .deposit(market, user, u64::MAX / 4, u64::MAX / 4)
+                        .unwrap();
+                    deposited[m].0 += (u64::MAX / 4) as u128;
+                    deposited[m].1 += (u64::MAX / 4) as u128;
+                }
+            }
+            let mut order_ids: Vec<(usize, u64)> = Vec::new();
+            for _ in 0..100 {
+                let m = rng.below(2) as usize;
+                let market = markets_names[m];
+                let user = users[rng.below(3) as usize];
+                let before = snapshot(&markets.markets[market]);
+                let result = match rng.below(4) {
+                    0 => {
+                        let (base, quote) = (rng.extreme(), rng.extreme());
+                        let result = markets.deposit(market, user, base, quote);
+                        if result.is_ok() {
+                            deposited[m].0 += base as u128;
+                            deposited[m].1 += quote as u128;
+                        }
+                        result
+                    }
+                    1 if !order_ids.is_empty() => {
+                        let (om, id) = order_ids[rng.below(order_ids.len() as u64) as usize];
+                        markets
+                            .cancel_order(markets_names[om], user, id)
+                            .map(|_| ())
+                    }
+                    k => {
+                        let side = if k == 2 { Side::Bid } else { Side::Ask };
+                        let (price, quantity) = (rng.extreme(), rng.extreme());
+                        markets
+                            .place_order(market, user, side, price, quantity)
+                            .map(|id| order_ids.push((m, id)))
+                    }
+                };
+                if result.is_err() {
+                    assert_eq!(snapshot(&markets.markets[market]), before);
+                }
+                for (m, market) in markets_names.iter().enumerate() {
+                    assert_conserved(&markets.markets[*market], deposited[m]);
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,43 @@
 use std::collections::HashMap;
+use std::fmt;
+
+// ========== 错误类型 ==========
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DexError {
+    Overflow,            // 数值溢出（价格 × 数量、余额/手续费累加超出 u64）
+    InsufficientBalance, // 余额不足
+    OrderNotFound,       // 订单不存在或不属于该用户
+    MarketNotFound,      // 市场不存在
+}
+
+impl fmt::Display for DexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DexError::Overflow => write!(f, "数值溢出"),
+            DexError::InsufficientBalance => write!(f, "余额不足"),
+            DexError::OrderNotFound => write!(f, "订单不存在"),
+            DexError::MarketNotFound => write!(f, "市场不存在"),
+        }
+    }
+}
+
+// ========== 账本算术（溢出返回 Overflow，不在 debug 下 panic、不在 release 下回绕） ==========
+pub fn checked_add(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_add(b).ok_or(DexError::Overflow)
+}
+
+pub fn checked_sub(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_sub(b).ok_or(DexError::Overflow)
+}
+
+pub fn checked_mul(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_mul(b).ok_or(DexError::Overflow)
+}
+
+// 手续费 = 金额 × fee_bps / 10000，中间结果用 u128
+pub fn checked_fee(amount: u64, fee_bps: u64) -> Result<u64, DexError> {
+    u64::try_from(amount as u128 * fee_bps as u128 / 10_000).map_err(|_| DexError::Overflow)
+}
 
 // ========== 订单方向 ==========
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -69,46 +108,151 @@ pub struct MarketState {
 */
 
 impl MarketState {
-    /// 用户充值
-    pub fn deposit(&mut self, user: &str, base: u64, quote: u64) {
+    /// 用户充值，余额溢出时拒绝充值，余额不变
+    pub fn deposit(&mut self, user: &str, base: u64, quote: u64) -> Result<(), DexError> {
         let bal = self.balances.entry(user.to_string()).or_default();
-        bal.base += base;
-        bal.quote += quote;
+        let (Ok(new_base), Ok(new_quote)) =
+            (checked_add(bal.base, base), checked_add(bal.quote, quote))
+        else {
+            println!("充值失败，用户 {} 余额溢出", user);
+            return Err(DexError::Overflow);
+        };
+        bal.base = new_base;
+        bal.quote = new_quote;
         println!(
             "用户 {} 在本市场充值：主币 {}，报价币 {}",
             user, base, quote
         );
+        Ok(())
+    }
+
+    /// 成交结算：买家得主币，卖家得报价币（成交金额扣除手续费），手续费归 fee_receiver
+    /// 先算出所有新值，任一溢出则返回错误、不做任何变更；成功返回手续费
+    fn settle_fill(
+        &mut self,
+        buyer: &str,
+        seller: &str,
+        price: u64,
+        qty: u64,
+        fee_bps: u64,
+    ) -> Result<u64, DexError> {
+        let notional = checked_mul(price, qty)?;
+        let fee = checked_fee(notional, fee_bps)?;
+        let buyer_base = self.balances.get(buyer).map(|b| b.base).unwrap_or(0);
+        let seller_quote = self.balances.get(seller).map(|b| b.quote).unwrap_or(0);
+        let buyer_base = checked_add(buyer_base, qty)?;
+        let seller_quote = checked_add(seller_quote, checked_sub(notional, fee)?)?;
+        let collected_fee = checked_add(self.fee_receiver.collected_fee, fee)?;
+        self.balances.entry(buyer.to_string()).or_default().base = buyer_base;
+        self.balances.entry(seller.to_string()).or_default().quote = seller_quote;
+        self.fee_receiver.collected_fee = collected_fee;
+        Ok(fee)
+    }
+
+    /// 返还冻结的资产（买单价差/撤单），溢出时返回错误、余额不变
+    fn refund(&mut self, user: &str, side: &Side, amount: u64) -> Result<(), DexError> {
+        let bal = self.balances.entry(user.to_string()).or_default();
+        match side {
+            Side::Bid => bal.quote = checked_add(bal.quote, amount)?,
+            Side::Ask => bal.base = checked_add(bal.base, amount)?,
+        }
+        Ok(())
+    }
+
+    /// 下单前的结算预检：按当前订单簿模拟整笔订单的冻结、每一笔成交（含手续费）和买单的价差返还，
+    /// 任一步余额不足或溢出都返回错误。预检不修改任何状态，通过后实际撮合不会再失败
+    fn check_settlement(
+        &self,
+        owner: &str,
+        side: &Side,
+        price: u64,
+        quantity: u64,
+        fee_bps: u64,
+    ) -> Result<(), DexError> {
+        let balance_of = |user: &str| {
+            self.balances
+                .get(user)
+                .map(|b| (b.base, b.quote))
+                .unwrap_or((0, 0))
+        };
+        // 只记录本单涉及用户的（主币，报价币）余额
+        let mut balances: HashMap<&str, (u64, u64)> = HashMap::new();
+        let (base, quote) = balance_of(owner);
+        let locked = match side {
+            Side::Bid => checked_sub(quote, checked_mul(price, quantity)?).map(|q| (base, q)),
+            Side::Ask => checked_sub(base, quantity).map(|b| (b, quote)),
+        };
+        let (base, quote) = locked.map_err(|_| DexError::InsufficientBalance)?;
+        balances.insert(owner, (base, quote));
+
+        let mut collected_fee = self.fee_receiver.collected_fee;
+        let mut remaining = quantity;
+        let makers = match side {
+            Side::Bid => &self.asks,
+            Side::Ask => &self.bids,
+        };
+        for maker in makers {
+            let crossed = match side {
+                Side::Bid => price >= maker.price,
+                Side::Ask => price <= maker.price,
+            };
+            if remaining == 0 || !crossed {
+                break;
+            }
+            let qty = remaining.min(maker.quantity);
+            let (buyer, seller) = match side {
+                Side::Bid => (owner, maker.owner.as_str()),
+                Side::Ask => (maker.owner.as_str(), owner),
+            };
+            let notional = checked_mul(maker.price, qty)?;
+            let fee = checked_fee(notional, fee_bps)?;
+            let (buyer_base, buyer_quote) = balances
+                .get(buyer)
+                .copied()
+                .unwrap_or_else(|| balance_of(buyer));
+            // 买单吃单按卖单价成交，限价与成交价之差返还给买家
+            let improvement = match side {
+                Side::Bid => checked_mul(price - maker.price, qty)?,
+                Side::Ask => 0,
+            };
+            let buyer_quote = checked_add(buyer_quote, improvement)?;
+            balances.insert(buyer, (checked_add(buyer_base, qty)?, buyer_quote));
+            let (seller_base, seller_quote) = balances
+                .get(seller)
+                .copied()
+                .unwrap_or_else(|| balance_of(seller));
+            let seller_quote = checked_add(seller_quote, checked_sub(notional, fee)?)?;
+            balances.insert(seller, (seller_base, seller_quote));
+            collected_fee = checked_add(collected_fee, fee)?;
+            remaining -= qty;
+        }
+
+        Ok(())
     }
 
     /// 在指定市场下单（买/卖），支持手续费和时间戳
+    /// 余额不足或任一笔结算溢出时拒绝整笔订单，订单簿、余额和手续费池保持不变
+    #[allow(clippy::too_many_arguments)]
     pub fn place_order(
         &mut self,
-        market: &str,      // 市场名，如 "SOL/USDC"
-        owner: &str,       // 下单用户
-        side: Side,        // 订单方向：买单(Bid) 或 卖单(Ask)
-        price: u64,        // 下单价格（以报价币计价）
-        mut quantity: u64, // 下单数量（主币数量，函数内会被多次修改）
-        now: u64,          // 当前时间戳（如区块时间，撮合/历史用）
-        fee_bps: u64,      // 手续费，单位为基点（1 bps = 0.01%）
-    ) -> Option<u64> {
+        market: &str,  // 市场名，如 "SOL/USDC"
+        owner: &str,   // 下单用户
+        side: Side,    // 订单方向：买单(Bid) 或 卖单(Ask)
+        price: u64,    // 下单价格（以报价币计价）
+        quantity: u64, // 下单数量（主币数量）
+        now: u64,      // 当前时间戳（如区块时间，撮合/历史用）
+        fee_bps: u64,  // 手续费，单位为基点（1 bps = 0.01%）
+    ) -> Result<u64, DexError> {
+        if let Err(err) = self.check_settlement(owner, &side, price, quantity, fee_bps) {
+            println!("下单失败，用户 {}：{}", owner, err);
+            return Err(err);
+        }
+
+        // 链上不用担心，整个交易都是事务
         let bal = self.balances.entry(owner.to_string()).or_default();
         match side {
-            Side::Bid => {
-                let needed_quote = price * quantity;
-                if bal.quote < needed_quote {
-                    println!("下单失败，用户 {} 报价币余额不足", owner);
-                    return None;
-                }
-                // 链上不用担心，整个交易都是事务
-                bal.quote -= needed_quote;
-            }
-            Side::Ask => {
-                if bal.base < quantity {
-                    println!("下单失败，用户 {} 主币余额不足", owner);
-                    return None;
-                }
-                bal.base -= quantity;
-            }
+            Side::Bid => bal.quote = checked_sub(bal.quote, checked_mul(price, quantity)?)?,
+            Side::Ask => bal.base = checked_sub(bal.base, quantity)?,
         }
 
         let order_id = self.next_order_id;
@@ -130,13 +274,17 @@ impl MarketState {
                         let deal_price = best_ask.price;
 
                         // 手续费，taker收（即发起撮合方）
-                        let fee = deal_price * deal_qty * fee_bps / 10_000;
-                        self.fee_receiver.collected_fee += fee;
-
                         // 买家（taker，当前order.owner）获得主币；卖家（maker）获得报价币
-                        self.balances.get_mut(&order.owner).unwrap().base += deal_qty;
-                        self.balances.get_mut(&best_ask.owner).unwrap().quote +=
-                            deal_price * deal_qty - fee;
+                        let fee = self.settle_fill(
+                            &order.owner,
+                            &best_ask.owner,
+                            deal_price,
+                            deal_qty,
+                            fee_bps,
+                        )?;
+                        // 按限价冻结、按卖单价成交，价差返还给买家
+                        let improvement = checked_mul(order.price - deal_price, deal_qty)?;
+                        self.refund(&order.owner, &Side::Bid, improvement)?;
 
                         // 记录Fill Event
                         self.event_queue.push(Event {
@@ -168,10 +316,9 @@ impl MarketState {
                     }
                 }
                 if order.quantity > 0 {
-                    let refund = order.price * order.quantity;
-                    self.balances.get_mut(&order.owner).unwrap().quote += refund;
+                    // 剩余部分入订单簿，对应的报价币保持冻结，撤单时返还
                     self.bids.push(order.clone());
-                    self.bids.sort_by(|a, b| b.price.cmp(&a.price));
+                    self.bids.sort_by_key(|o| std::cmp::Reverse(o.price));
                     println!(
                         "买单部分未成交，剩余 {} 进入买单簿，订单ID={}",
                         order.quantity, order.id
@@ -184,13 +331,14 @@ impl MarketState {
                         let deal_qty = order.quantity.min(best_bid.quantity);
                         let deal_price = best_bid.price;
 
-                        let fee = deal_price * deal_qty * fee_bps / 10_000;
-                        self.fee_receiver.collected_fee += fee;
-
                         // 卖家（taker，当前order.owner）获得报价币；买家（maker）获得主币
-                        self.balances.get_mut(&order.owner).unwrap().quote +=
-                            deal_price * deal_qty - fee;
-                        self.balances.get_mut(&best_bid.owner).unwrap().base += deal_qty;
+                        let fee = self.settle_fill(
+                            &best_bid.owner,
+                            &order.owner,
+                            deal_price,
+                            deal_qty,
+                            fee_bps,
+                        )?;
 
                         self.event_queue.push(Event {
                             event_type: EventType::Fill,
@@ -221,9 +369,9 @@ impl MarketState {
                     }
                 }
                 if order.quantity > 0 {
-                    self.balances.get_mut(&order.owner).unwrap().base += order.quantity;
+                    // 剩余部分入订单簿，对应的主币保持冻结，撤单时返还
                     self.asks.push(order.clone());
-                    self.asks.sort_by(|a, b| a.price.cmp(&b.price));
+                    self.asks.sort_by_key(|o| o.price);
                     println!(
                         "卖单部分未成交，剩余 {} 进入卖单簿，订单ID={}",
                         order.quantity, order.id
@@ -231,20 +379,35 @@ impl MarketState {
                 }
             }
         }
-        Some(order_id)
+        Ok(order_id)
     }
 
-    /// 撤单
-    pub fn cancel_order(&mut self, market: &str, user: &str, order_id: u64, now: u64) -> bool {
+    /// 撤单，返还溢出时返回错误、订单保留
+    pub fn cancel_order(
+        &mut self,
+        market: &str,
+        user: &str,
+        order_id: u64,
+        now: u64,
+    ) -> Result<(), DexError> {
         // 买单
         if let Some(pos) = self
             .bids
             .iter()
             .position(|o| o.id == order_id && o.owner == user)
         {
+            // 返还冻结的报价币
+            let order = &self.bids[pos];
+            let refund = match checked_mul(order.price, order.quantity)
+                .and_then(|refund| self.refund(user, &Side::Bid, refund).map(|_| refund))
+            {
+                Ok(refund) => refund,
+                Err(err) => {
+                    println!("撤单失败：{}，订单ID={}", err, order_id);
+                    return Err(err);
+                }
+            };
             let order = self.bids.remove(pos);
-            let refund = order.price * order.quantity;
-            self.balances.get_mut(user).unwrap().quote += refund;
             self.event_queue.push(Event {
                 event_type: EventType::Cancel,
                 market: market.to_string(),
@@ -253,11 +416,11 @@ impl MarketState {
                 price: Some(order.price),
                 quantity: order.quantity,
                 fee: 0,
-                order_id: order_id,
+                order_id,
                 timestamp: now,
             });
             println!("撤销买单，返还报价币 {}，订单ID={}", refund, order_id);
-            return true;
+            return Ok(());
         }
         // 卖单
         if let Some(pos) = self
@@ -265,8 +428,12 @@ impl MarketState {
             .iter()
             .position(|o| o.id == order_id && o.owner == user)
         {
+            // 返还冻结的主币
+            if let Err(err) = self.refund(user, &Side::Ask, self.asks[pos].quantity) {
+                println!("撤单失败：{}，订单ID={}", err, order_id);
+                return Err(err);
+            }
             let order = self.asks.remove(pos);
-            self.balances.get_mut(user).unwrap().base += order.quantity;
             self.event_queue.push(Event {
                 event_type: EventType::Cancel,
                 market: market.to_string(),
@@ -275,14 +442,14 @@ impl MarketState {
                 price: Some(order.price),
                 quantity: order.quantity,
                 fee: 0,
-                order_id: order_id,
+                order_id,
                 timestamp: now,
             });
             println!("撤销卖单，返还主币 {}，订单ID={}", order.quantity, order_id);
-            return true;
+            return Ok(());
         }
         println!("撤单失败，未找到属于用户 {} 的订单ID={}", user, order_id);
-        false
+        Err(DexError::OrderNotFound)
     }
 
     pub fn print_book(&self) {
@@ -316,6 +483,12 @@ pub struct Markets {
     pub markets: HashMap<String, MarketState>,
 }
 
+impl Default for Markets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Markets {
     pub fn new() -> Self {
         Self {
@@ -324,20 +497,26 @@ impl Markets {
     }
 
     pub fn create_market(&mut self, market: &str) {
-        self.markets
-            .entry(market.to_string())
-            .or_insert_with(MarketState::default);
+        self.markets.entry(market.to_string()).or_default();
         println!("新市场已创建: {}", market);
     }
 
-    pub fn deposit(&mut self, market: &str, user: &str, base: u64, quote: u64) {
+    pub fn deposit(
+        &mut self,
+        market: &str,
+        user: &str,
+        base: u64,
+        quote: u64,
+    ) -> Result<(), DexError> {
         if let Some(state) = self.markets.get_mut(market) {
-            state.deposit(user, base, quote);
+            state.deposit(user, base, quote)
         } else {
             println!("市场 {} 不存在", market);
+            Err(DexError::MarketNotFound)
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn place_order(
         &mut self,
         market: &str,
@@ -347,21 +526,27 @@ impl Markets {
         quantity: u64,
         now: u64,
         fee_bps: u64,
-    ) -> Option<u64> {
+    ) -> Result<u64, DexError> {
         if let Some(state) = self.markets.get_mut(market) {
             state.place_order(market, owner, side, price, quantity, now, fee_bps)
         } else {
             println!("市场 {} 不存在", market);
-            None
+            Err(DexError::MarketNotFound)
         }
     }
 
-    pub fn cancel_order(&mut self, market: &str, user: &str, order_id: u64, now: u64) -> bool {
+    pub fn cancel_order(
+        &mut self,
+        market: &str,
+        user: &str,
+        order_id: u64,
+        now: u64,
+    ) -> Result<(), DexError> {
         if let Some(state) = self.markets.get_mut(market) {
             state.cancel_order(market, user, order_id, now)
         } else {
             println!("市场 {} 不存在", market);
-            false
+            Err(DexError::MarketNotFound)
         }
     }
 
@@ -409,27 +594,27 @@ fn main() {
     markets.create_market("BTC/USDT");
 
     // 充值
-    markets.deposit("SOL/USDC", "Alice", 100, 2000);
-    markets.deposit("SOL/USDC", "Bob", 50, 1000);
+    markets.deposit("SOL/USDC", "Alice", 100, 2000).unwrap();
+    markets.deposit("SOL/USDC", "Bob", 50, 1000).unwrap();
 
-    markets.deposit("BTC/USDT", "Carol", 10, 100000);
-    markets.deposit("BTC/USDT", "Dave", 5, 80000);
+    markets.deposit("BTC/USDT", "Carol", 10, 100000).unwrap();
+    markets.deposit("BTC/USDT", "Dave", 5, 80000).unwrap();
 
     // 下单&撮合
     let alice_bid = markets.place_order("SOL/USDC", "Alice", Side::Bid, 10, 10, now, fee_bps);
     now += 1;
-    let bob_ask = markets.place_order("SOL/USDC", "Bob", Side::Ask, 10, 5, now, fee_bps);
+    let _bob_ask = markets.place_order("SOL/USDC", "Bob", Side::Ask, 10, 5, now, fee_bps);
     now += 1;
 
-    let carol_bid = markets.place_order("BTC/USDT", "Carol", Side::Bid, 20000, 2, now, fee_bps);
+    let _carol_bid = markets.place_order("BTC/USDT", "Carol", Side::Bid, 20000, 2, now, fee_bps);
     now += 1;
-    let dave_ask = markets.place_order("BTC/USDT", "Dave", Side::Ask, 19500, 3, now, fee_bps);
+    let _dave_ask = markets.place_order("BTC/USDT", "Dave", Side::Ask, 19500, 3, now, fee_bps);
     now += 1;
 
     // 撤销剩余买单
-    if let Some(id) = alice_bid {
-        markets.cancel_order("SOL/USDC", "Alice", id, now);
-        now += 1;
+    if let Ok(id) = alice_bid {
+        // 撤单失败时 cancel_order 已打印原因，订单保留
+        let _ = markets.cancel_order("SOL/USDC", "Alice", id, now);
     }
 
     // 打印订单簿、余额、手续费池、历史事件
@@ -443,3 +628,266 @@ fn main() {
     markets.print_market_fee_receiver("BTC/USDT");
     markets.print_market_events("BTC/USDT");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MARKET: &str = "SOL/USDC";
+    const NOW: u64 = 1000;
+    const FEE_BPS: u64 = 30;
+
+    /// 简单的线性同余随机数（不引入外部依赖，失败时可按 seed 复现）
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            self.0 >> 33
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+
+        /// 偏向边界的随机数：小数、2 的幂、u32/u64 上限附近
+        fn extreme(&mut self) -> u64 {
+            match self.below(5) {
+                0 => self.below(1_000) + 1,
+                1 => 1u64 << self.below(64),
+                2 => u32::MAX as u64 - self.below(1_000),
+                3 => u64::MAX - self.below(1_000),
+                _ => (self.next() << 31) | self.next(),
+            }
+        }
+    }
+
+    fn balance(state: &MarketState, user: &str) -> (u64, u64) {
+        state
+            .balances
+            .get(user)
+            .map(|b| (b.base, b.quote))
+            .unwrap_or((0, 0))
+    }
+
+    /// 订单簿、按用户名排序的余额、手续费池和事件数，用于比较操作前后状态是否完全一致
+    fn snapshot(state: &MarketState) -> String {
+        let mut balances: Vec<_> = state.balances.iter().collect();
+        balances.sort_by_key(|(user, _)| user.as_str());
+        format!(
+            "{:?} {:?} {:?} {} {}",
+            state.bids,
+            state.asks,
+            balances,
+            state.fee_receiver.collected_fee,
+            state.event_queue.len()
+        )
+    }
+
+    /// 总量守恒：所有用户余额 + 挂单冻结的资金 + 手续费池 = 累计充值（用 u128 计算，不会溢出）
+    fn assert_conserved(state: &MarketState, deposited: (u128, u128)) {
+        let mut held = (0u128, state.fee_receiver.collected_fee as u128);
+        for bal in state.balances.values() {
+            held.0 += bal.base as u128;
+            held.1 += bal.quote as u128;
+        }
+        for order in state.bids.iter().chain(state.asks.iter()) {
+            match order.side {
+                Side::Bid => held.1 += order.price as u128 * order.quantity as u128,
+                Side::Ask => held.0 += order.quantity as u128,
+            }
+        }
+        assert_eq!(held, deposited);
+    }
+
+    #[test]
+    fn test_checked_fee() {
+        assert_eq!(checked_fee(10_000, FEE_BPS), Ok(30));
+        assert_eq!(checked_fee(u64::MAX, 10_000), Ok(u64::MAX));
+        assert_eq!(checked_fee(u64::MAX, 20_000), Err(DexError::Overflow));
+    }
+
+    #[test]
+    fn test_deposit_overflow_and_unknown_market() {
+        let mut markets = Markets::new();
+        markets.create_market(MARKET);
+        markets.deposit(MARKET, "A", u64::MAX, 0).unwrap();
+        assert_eq!(markets.deposit(MARKET, "A", 1, 1), Err(DexError::Overflow));
+        assert_eq!(balance(&markets.markets[MARKET], "A"), (u64::MAX, 0));
+
+        assert_eq!(
+            markets.deposit("BTC/USDT", "A", 1, 1),
+            Err(DexError::MarketNotFound)
+        );
+        assert_eq!(
+            markets.place_order("BTC/USDT", "A", Side::Bid, 1, 1, NOW, FEE_BPS),
+            Err(DexError::MarketNotFound)
+        );
+        assert_eq!(
+            markets.cancel_order("BTC/USDT", "A", 0, NOW),
+            Err(DexError::MarketNotFound)
+        );
+    }
+
+    #[test]
+    fn test_check_settlement_rejects_without_changes() {
+        let mut state = MarketState::default();
+        state.deposit("A", 0, u64::MAX).unwrap();
+
+        // 价格 × 数量溢出、余额不足：拒绝下单，状态不变
+        let before = snapshot(&state);
+        assert_eq!(
+            state.place_order(MARKET, "A", Side::Bid, u64::MAX, 2, NOW, FEE_BPS),
+            Err(DexError::Overflow)
+        );
+        assert_eq!(
+            state.place_order(MARKET, "A", Side::Ask, 1, 1, NOW, FEE_BPS),
+            Err(DexError::InsufficientBalance)
+        );
+        assert_eq!(snapshot(&state), before);
+
+        // 成交时卖家报价币余额会溢出：整笔卖单被拒绝，订单簿、余额、手续费池和事件都不变
+        state
+            .place_order(MARKET, "A", Side::Bid, 2, 10, NOW, FEE_BPS)
+            .unwrap();
+        state.deposit("B", 10, u64::MAX).unwrap();
+        let before = snapshot(&state);
+        assert_eq!(
+            state.place_order(MARKET, "B", Side::Ask, 2, 10, NOW, 0),
+            Err(DexError::Overflow)
+        );
+        assert_eq!(snapshot(&state), before);
+
+        // 手续费池会溢出：同样整笔拒绝
+        state.fee_receiver.collected_fee = u64::MAX;
+        state.deposit("C", 10, 0).unwrap();
+        let before = snapshot(&state);
+        assert_eq!(
+            state.place_order(MARKET, "C", Side::Ask, 2, 10, NOW, 10_000),
+            Err(DexError::Overflow)
+        );
+        assert_eq!(snapshot(&state), before);
+
+        // 费率超过 100% 时手续费大于成交额：拒绝而不是下溢
+        state.fee_receiver.collected_fee = 0;
+        let before = snapshot(&state);
+        assert_eq!(
+            state.place_order(MARKET, "C", Side::Ask, 2, 10, NOW, 20_000),
+            Err(DexError::Overflow)
+        );
+        assert_eq!(snapshot(&state), before);
+    }
+
+    #[test]
+    fn test_resting_remainder_stays_locked_and_price_improvement_is_refunded() {
+        let mut state = MarketState::default();
+        state.deposit("A", 10, 0).unwrap();
+        state.deposit("B", 0, 100_000).unwrap();
+        state
+            .place_order(MARKET, "A", Side::Ask, 2_000, 4, NOW, FEE_BPS)
+            .unwrap();
+
+        // 限价 5000 买 10 个：4 个按卖单价 2000 成交（手续费 8000 × 0.3% = 24 由卖家承担），
+        // 价差 (5000 - 2000) × 4 返还，剩余 6 个挂单冻结 30000
+        let bid = state
+            .place_order(MARKET, "B", Side::Bid, 5_000, 10, NOW, FEE_BPS)
+            .unwrap();
+        assert_eq!(balance(&state, "A"), (6, 8_000 - 24));
+        assert_eq!(balance(&state, "B"), (4, 100_000 - 8_000 - 30_000));
+        assert_eq!(state.fee_receiver.collected_fee, 24);
+
+        // 撤单只返还一次冻结的 30000
+        state.cancel_order(MARKET, "B", bid, NOW).unwrap();
+        assert_eq!(balance(&state, "B"), (4, 92_000));
+        assert_eq!(state.event_queue.len(), 2);
+        assert_conserved(&state, (10, 100_000));
+    }
+
+    #[test]
+    fn test_cancel_refund_overflow_keeps_order() {
+        let mut state = MarketState::default();
+        state.deposit("A", 10, 0).unwrap();
+        let ask = state
+            .place_order(MARKET, "A", Side::Ask, 2, 10, NOW, FEE_BPS)
+            .unwrap();
+
+        // 撤单返还溢出：返回错误，订单保留、余额不变、不记录撤单事件
+        state.deposit("A", u64::MAX, 0).unwrap();
+        let before = snapshot(&state);
+        assert_eq!(
+            state.cancel_order(MARKET, "A", ask, NOW),
+            Err(DexError::Overflow)
+        );
+        assert_eq!(snapshot(&state), before);
+        assert_eq!(
+            state.cancel_order(MARKET, "B", ask, NOW),
+            Err(DexError::OrderNotFound)
+        );
+    }
+
+    /// 随机充值/下单/撤单，价格、数量和费率偏向边界值：任何操作都不能 panic，
+    /// 被拒绝的操作不改变状态，每一步之后每个市场总量守恒（含手续费池）
+    #[test]
+    fn test_fuzz_extreme_prices_and_quantities() {
+        let markets_names = [MARKET, "BTC/USDT"];
+        let users = ["A", "B", "C"];
+        for seed in 0..200 {
+            let mut rng = Lcg(seed);
+            let mut markets = Markets::new();
+            let mut deposited = [(0u128, 0u128); 2];
+            for market in markets_names {
+                markets.create_market(market);
+            }
+            for (m, market) in markets_names.iter().enumerate() {
+                for user in users {
+                    markets
+                        .deposit(market, user, u64::MAX / 4, u64::MAX / 4)
+                        .unwrap();
+                    deposited[m].0 += (u64::MAX / 4) as u128;
+                    deposited[m].1 += (u64::MAX / 4) as u128;
+                }
+            }
+            let mut order_ids: Vec<(usize, u64)> = Vec::new();
+            for step in 0..100 {
+                let m = rng.below(2) as usize;
+                let market = markets_names[m];
+                let user = users[rng.below(3) as usize];
+                let before = snapshot(&markets.markets[market]);
+                let result = match rng.below(4) {
+                    0 => {
+                        let (base, quote) = (rng.extreme(), rng.extreme());
+                        let result = markets.deposit(market, user, base, quote);
+                        if result.is_ok() {
+                            deposited[m].0 += base as u128;
+                            deposited[m].1 += quote as u128;
+                        }
+                        result
+                    }
+                    1 if !order_ids.is_empty() => {
+                        let (om, id) = order_ids[rng.below(order_ids.len() as u64) as usize];
+                        markets
+                            .cancel_order(markets_names[om], user, id, NOW + step)
+                            .map(|_| ())
+                    }
+                    k => {
+                        let side = if k == 2 { Side::Bid } else { Side::Ask };
+                        let (price, quantity) = (rng.extreme(), rng.extreme());
+                        let fee_bps = [0, FEE_BPS, 10_000, 20_000][rng.below(4) as usize];
+                        markets
+                            .place_order(market, user, side, price, quantity, NOW + step, fee_bps)
+                            .map(|id| order_ids.push((m, id)))
+                    }
+                };
+                if result.is_err() {
+                    assert_eq!(snapshot(&markets.markets[market]), before);
+                }
+                for (m, market) in markets_names.iter().enumerate() {
+                    assert_conserved(&markets.markets[*market], deposited[m]);
+                }
+            }
+        }
+    }
+}
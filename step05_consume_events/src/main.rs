@@ -1,4 +1,46 @@
 use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+/// 错误类型：下单、撤单、清理过期订单失败的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DexError {
+    /// 数值溢出（价格 × 数量、余额/手续费累加超出 u64）
+    Overflow,
+    /// 余额不足
+    InsufficientBalance,
+    /// 市场不存在
+    MarketNotFound,
+}
+
+impl fmt::Display for DexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DexError::Overflow => write!(f, "数值溢出"),
+            DexError::InsufficientBalance => write!(f, "余额不足"),
+            DexError::MarketNotFound => write!(f, "市场不存在"),
+        }
+    }
+}
+
+/// 账本加法，溢出返回 Overflow（不在 debug 下 panic、不在 release 下回绕）
+pub fn checked_add(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_add(b).ok_or(DexError::Overflow)
+}
+
+/// 账本减法，不足返回 Overflow
+pub fn checked_sub(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_sub(b).ok_or(DexError::Overflow)
+}
+
+/// 账本乘法（价格 × 数量），溢出返回 Overflow
+pub fn checked_mul(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_mul(b).ok_or(DexError::Overflow)
+}
+
+/// 手续费 = 金额 × fee_bps / 10000，中间结果用 u128
+pub fn checked_fee(amount: u64, fee_bps: u64) -> Result<u64, DexError> {
+    u64::try_from(amount as u128 * fee_bps as u128 / 10_000).map_err(|_| DexError::Overflow)
+}
 
 /// 订单方向（买单/卖单）
 /// Side is order side (Bid/Ask)
@@ -139,25 +181,92 @@ pub struct MarketState {
 }
 
 impl MarketState {
-    /// 用户充值
-    pub fn deposit(&mut self, user: &str, base: u64, quote: u64) {
+    /// 用户充值，余额溢出时拒绝充值，余额不变
+    pub fn deposit(&mut self, user: &str, base: u64, quote: u64) -> Result<(), DexError> {
         let bal = self.balances.entry(user.to_string()).or_default();
-        bal.base += base;
-        bal.quote += quote;
+        let (Ok(new_base), Ok(new_quote)) =
+            (checked_add(bal.base, base), checked_add(bal.quote, quote))
+        else {
+            println!("充值失败，用户 {} 余额溢出", user);
+            return Err(DexError::Overflow);
+        };
+        bal.base = new_base;
+        bal.quote = new_quote;
         println!(
             "用户 {} 在本市场充值：主币 {}，报价币 {}",
             user, base, quote
         );
+        Ok(())
+    }
+
+    /// 成交结算：买家得主币，卖家得报价币（成交金额扣除手续费），手续费归 fee_receiver
+    /// 先算出所有新值，任一溢出则返回错误、不做任何变更；成功返回手续费
+    fn settle_fill(
+        &mut self,
+        buyer: &str,
+        seller: &str,
+        price: u64,
+        qty: u64,
+        fee_bps: u64,
+    ) -> Result<u64, DexError> {
+        let notional = checked_mul(price, qty)?;
+        let fee = checked_fee(notional, fee_bps)?;
+        let buyer_base = self.balances.get(buyer).map(|b| b.base).unwrap_or(0);
+        let seller_quote = self.balances.get(seller).map(|b| b.quote).unwrap_or(0);
+        let buyer_base = checked_add(buyer_base, qty)?;
+        let seller_quote = checked_add(seller_quote, checked_sub(notional, fee)?)?;
+        let collected_fee = checked_add(self.fee_receiver.collected_fee, fee)?;
+        self.balances.entry(buyer.to_string()).or_default().base = buyer_base;
+        self.balances.entry(seller.to_string()).or_default().quote = seller_quote;
+        self.fee_receiver.collected_fee = collected_fee;
+        Ok(fee)
+    }
+
+    /// 返还订单冻结的资产（撤单/过期），溢出时返回错误、余额不变
+    /// 只借用余额表，便于在 retain 闭包中调用
+    fn refund(balances: &mut HashMap<String, UserBalance>, order: &Order) -> Result<(), DexError> {
+        let bal = balances.entry(order.owner.clone()).or_default();
+        match order.side {
+            Side::Bid => {
+                bal.quote = checked_add(bal.quote, checked_mul(order.price, order.quantity)?)?
+            }
+            Side::Ask => bal.base = checked_add(bal.base, order.quantity)?,
+        }
+        Ok(())
+    }
+
+    /// 批量返还的预检：按用户累加多个订单的返还额，任一用户余额溢出则返回错误，不修改任何状态
+    fn check_refunds<'a>(&self, orders: impl Iterator<Item = &'a Order>) -> Result<(), DexError> {
+        let mut balances: HashMap<&str, (u64, u64)> = HashMap::new();
+        for o in orders {
+            let (base, quote) = balances.get(o.owner.as_str()).copied().unwrap_or_else(|| {
+                self.balances
+                    .get(&o.owner)
+                    .map(|b| (b.base, b.quote))
+                    .unwrap_or((0, 0))
+            });
+            let bal = match o.side {
+                Side::Bid => (base, checked_add(quote, checked_mul(o.price, o.quantity)?)?),
+                Side::Ask => (checked_add(base, o.quantity)?, quote),
+            };
+            balances.insert(&o.owner, bal);
+        }
+        Ok(())
     }
 
     /// 清理所有已过期订单
-    pub fn clean_expired_orders(&mut self, now: u64, market: &str) {
-        // 买单
+    /// 先预检全部返还，任一返还溢出则返回错误，订单和余额保持不变
+    pub fn clean_expired_orders(&mut self, now: u64, market: &str) -> Result<(), DexError> {
+        let is_expired = |o: &Order| o.expire_ts.map(|ts| ts <= now).unwrap_or(false);
+        self.check_refunds(
+            self.bids
+                .iter()
+                .chain(self.asks.iter())
+                .filter(|o| is_expired(o)),
+        )?;
+        // 买单（返还已预检，retain 中不会失败）
         self.bids.retain(|o| {
-            let expired = o.expire_ts.map(|ts| ts <= now).unwrap_or(false);
-            if expired {
-                let refund = o.price * o.quantity;
-                self.balances.get_mut(&o.owner).unwrap().quote += refund;
+            if is_expired(o) && Self::refund(&mut self.balances, o).is_ok() {
                 self.event_queue.push(Event {
                     event_type: EventType::Expire,
                     market: market.to_string(),
@@ -169,14 +278,13 @@ impl MarketState {
                     order_id: o.id,
                     timestamp: now,
                 });
+                return false;
             }
-            !expired
+            true
         });
         // 卖单
         self.asks.retain(|o| {
-            let expired = o.expire_ts.map(|ts| ts <= now).unwrap_or(false);
-            if expired {
-                self.balances.get_mut(&o.owner).unwrap().base += o.quantity;
+            if is_expired(o) && Self::refund(&mut self.balances, o).is_ok() {
                 self.event_queue.push(Event {
                     event_type: EventType::Expire,
                     market: market.to_string(),
@@ -188,51 +296,99 @@ impl MarketState {
                     order_id: o.id,
                     timestamp: now,
                 });
+                return false;
             }
-            !expired
+            true
         });
+        Ok(())
+    }
+
+    /// 下单前的结算预检：按当前订单簿模拟整笔订单的冻结、每一笔成交（含手续费）和买单的价差返还，
+    /// 任一步余额不足或溢出都返回错误。预检不修改任何状态，通过后实际撮合不会再失败
+    fn check_settlement(&self, order: &Order, fee_bps: u64) -> Result<(), DexError> {
+        let balance_of = |user: &str| {
+            self.balances
+                .get(user)
+                .map(|b| (b.base, b.quote))
+                .unwrap_or((0, 0))
+        };
+        let owner = order.owner.as_str();
+        // 只记录本单涉及用户的（主币，报价币）余额
+        let mut balances: HashMap<&str, (u64, u64)> = HashMap::new();
+        let (base, quote) = balance_of(owner);
+        let locked = match order.side {
+            Side::Bid => {
+                checked_sub(quote, checked_mul(order.price, order.quantity)?).map(|q| (base, q))
+            }
+            Side::Ask => checked_sub(base, order.quantity).map(|b| (b, quote)),
+        };
+        let (base, quote) = locked.map_err(|_| DexError::InsufficientBalance)?;
+        balances.insert(owner, (base, quote));
+
+        let mut collected_fee = self.fee_receiver.collected_fee;
+        let mut remaining = order.quantity;
+        let makers = match order.side {
+            Side::Bid => &self.asks,
+            Side::Ask => &self.bids,
+        };
+        for maker in makers {
+            let crossed = match order.side {
+                Side::Bid => order.price >= maker.price,
+                Side::Ask => order.price <= maker.price,
+            };
+            if remaining == 0 || !crossed {
+                break;
+            }
+            let qty = remaining.min(maker.quantity);
+            let (buyer, seller) = match order.side {
+                Side::Bid => (owner, maker.owner.as_str()),
+                Side::Ask => (maker.owner.as_str(), owner),
+            };
+            let notional = checked_mul(maker.price, qty)?;
+            let fee = checked_fee(notional, fee_bps)?;
+            let (buyer_base, buyer_quote) = balances
+                .get(buyer)
+                .copied()
+                .unwrap_or_else(|| balance_of(buyer));
+            // 买单吃单按卖单价成交，限价与成交价之差返还给买家
+            let improvement = match order.side {
+                Side::Bid => checked_mul(order.price - maker.price, qty)?,
+                Side::Ask => 0,
+            };
+            let buyer_quote = checked_add(buyer_quote, improvement)?;
+            balances.insert(buyer, (checked_add(buyer_base, qty)?, buyer_quote));
+            let (seller_base, seller_quote) = balances
+                .get(seller)
+                .copied()
+                .unwrap_or_else(|| balance_of(seller));
+            let seller_quote = checked_add(seller_quote, checked_sub(notional, fee)?)?;
+            balances.insert(seller, (seller_base, seller_quote));
+            collected_fee = checked_add(collected_fee, fee)?;
+            remaining -= qty;
+        }
+
+        Ok(())
     }
 
     /// 下单（挂入订单簿或直接撮合，支持订单有效期和自动清理过期订单）
+    /// 余额不足或任一笔结算溢出时拒绝整笔订单，订单簿、余额和手续费池保持不变
+    #[allow(clippy::too_many_arguments)]
     pub fn place_order(
         &mut self,
         market: &str,
         owner: &str,
         side: Side,
         price: u64,
-        mut quantity: u64,
+        quantity: u64,
         now: u64,
         fee_bps: u64,
         expire_ts: Option<u64>,
-    ) -> Option<u64> {
-        self.clean_expired_orders(now, market);
-
-        // 校验余额
-        let bal = self.balances.entry(owner.to_string()).or_default();
-        match side {
-            Side::Bid => {
-                let needed_quote = price * quantity;
-                if bal.quote < needed_quote {
-                    println!("下单失败，用户 {} 报价币余额不足", owner);
-                    return None;
-                }
-                bal.quote -= needed_quote;
-            }
-            Side::Ask => {
-                if bal.base < quantity {
-                    println!("下单失败，用户 {} 主币余额不足", owner);
-                    return None;
-                }
-                bal.base -= quantity;
-            }
-        }
+    ) -> Result<u64, DexError> {
+        self.clean_expired_orders(now, market)?;
 
         // 构造订单
-        let order_id = self.next_order_id;
-        self.next_order_id += 1;
-
         let mut order = Order {
-            id: order_id,
+            id: self.next_order_id,
             owner: owner.to_string(),
             side: side.clone(),
             price,
@@ -240,20 +396,38 @@ impl MarketState {
             expire_ts,
         };
 
+        // 结算预检，通过后再冻结余额
+        if let Err(err) = self.check_settlement(&order, fee_bps) {
+            println!("下单失败，用户 {}：{}", owner, err);
+            return Err(err);
+        }
+        let bal = self.balances.entry(owner.to_string()).or_default();
+        match side {
+            Side::Bid => bal.quote = checked_sub(bal.quote, checked_mul(price, quantity)?)?,
+            Side::Ask => bal.base = checked_sub(bal.base, quantity)?,
+        }
+        let order_id = self.next_order_id;
+        self.next_order_id += 1;
+
         // 撮合逻辑
         match side {
             Side::Bid => {
-                while let Some(mut best_ask) = self.asks.first().cloned() {
+                while let Some(best_ask) = self.asks.first().cloned() {
                     if order.price >= best_ask.price && order.quantity > 0 {
                         let deal_qty = order.quantity.min(best_ask.quantity);
                         let deal_price = best_ask.price;
-                        let fee = deal_price * deal_qty * fee_bps / 10_000;
-                        self.fee_receiver.collected_fee += fee;
-
                         // 买家获得主币，卖家获得报价币（扣除手续费）
-                        self.balances.get_mut(&order.owner).unwrap().base += deal_qty;
-                        self.balances.get_mut(&best_ask.owner).unwrap().quote +=
-                            deal_price * deal_qty - fee;
+                        let fee = self.settle_fill(
+                            &order.owner,
+                            &best_ask.owner,
+                            deal_price,
+                            deal_qty,
+                            fee_bps,
+                        )?;
+                        // 按限价冻结、按卖单价成交，价差返还给买家
+                        let improvement = checked_mul(order.price - deal_price, deal_qty)?;
+                        let bal = self.balances.entry(order.owner.clone()).or_default();
+                        bal.quote = checked_add(bal.quote, improvement)?;
 
                         self.event_queue.push(Event {
                             event_type: EventType::Fill,
@@ -278,12 +452,10 @@ impl MarketState {
                         break;
                     }
                 }
-                // 剩余未成交部分挂入订单簿
+                // 剩余未成交部分挂入订单簿，对应的报价币保持冻结，撤单/过期时返还
                 if order.quantity > 0 {
-                    let refund = order.price * order.quantity;
-                    self.balances.get_mut(&order.owner).unwrap().quote += refund;
                     self.bids.push(order.clone());
-                    self.bids.sort_by(|a, b| b.price.cmp(&a.price));
+                    self.bids.sort_by_key(|o| std::cmp::Reverse(o.price));
                     println!(
                         "买单部分未成交，剩余 {} 进入买单簿，订单ID={}",
                         order.quantity, order.id
@@ -291,17 +463,18 @@ impl MarketState {
                 }
             }
             Side::Ask => {
-                while let Some(mut best_bid) = self.bids.first().cloned() {
+                while let Some(best_bid) = self.bids.first().cloned() {
                     if order.price <= best_bid.price && order.quantity > 0 {
                         let deal_qty = order.quantity.min(best_bid.quantity);
                         let deal_price = best_bid.price;
-                        let fee = deal_price * deal_qty * fee_bps / 10_000;
-                        self.fee_receiver.collected_fee += fee;
-
                         // 卖家获得报价币（扣手续费），买家获得主币
-                        self.balances.get_mut(&order.owner).unwrap().quote +=
-                            deal_price * deal_qty - fee;
-                        self.balances.get_mut(&best_bid.owner).unwrap().base += deal_qty;
+                        let fee = self.settle_fill(
+                            &best_bid.owner,
+                            &order.owner,
+                            deal_price,
+                            deal_qty,
+                            fee_bps,
+                        )?;
 
                         self.event_queue.push(Event {
                             event_type: EventType::Fill,
@@ -326,11 +499,10 @@ impl MarketState {
                         break;
                     }
                 }
-                // 剩余未成交部分挂入订单簿
+                // 剩余未成交部分挂入订单簿，对应的主币保持冻结，撤单/过期时返还
                 if order.quantity > 0 {
-                    self.balances.get_mut(&order.owner).unwrap().base += order.quantity;
                     self.asks.push(order.clone());
-                    self.asks.sort_by(|a, b| a.price.cmp(&b.price));
+                    self.asks.sort_by_key(|o| o.price);
                     println!(
                         "卖单部分未成交，剩余 {} 进入卖单簿，订单ID={}",
                         order.quantity, order.id
@@ -338,14 +510,22 @@ impl MarketState {
                 }
             }
         }
-        Some(order_id)
+        Ok(order_id)
     }
 
     /// 批量撮合（对前 n 个订单尝试撮合）
     /// side: 批量撮合哪一侧（Bid/Ask）
     /// n: 前n个订单
-    pub fn batch_match(&mut self, market: &str, side: Side, n: usize, now: u64, fee_bps: u64) {
-        self.clean_expired_orders(now, market);
+    /// 每笔订单各自原子执行，遇到第一笔失败即返回错误，之前的订单保持已执行
+    pub fn batch_match(
+        &mut self,
+        market: &str,
+        side: Side,
+        n: usize,
+        now: u64,
+        fee_bps: u64,
+    ) -> Result<(), DexError> {
+        self.clean_expired_orders(now, market)?;
         match side {
             Side::Bid => {
                 let bids = self.bids.clone();
@@ -359,7 +539,7 @@ impl MarketState {
                         now,
                         fee_bps,
                         order.expire_ts,
-                    );
+                    )?;
                 }
             }
             Side::Ask => {
@@ -374,21 +554,34 @@ impl MarketState {
                         now,
                         fee_bps,
                         order.expire_ts,
-                    );
+                    )?;
                 }
             }
         }
+        Ok(())
     }
 
     /// 批量撤销指定用户的订单
     /// ids: 要撤销的订单id列表
-    pub fn batch_cancel(&mut self, market: &str, user: &str, ids: &[u64], now: u64) {
-        let mut cancel_ids: Vec<u64> = ids.to_vec();
-        // 买单
+    /// 先预检全部返还，任一返还溢出则返回错误，所有订单和余额保持不变
+    pub fn batch_cancel(
+        &mut self,
+        market: &str,
+        user: &str,
+        ids: &[u64],
+        now: u64,
+    ) -> Result<(), DexError> {
+        let cancel_ids: Vec<u64> = ids.to_vec();
+        let is_target = |o: &Order| o.owner == user && cancel_ids.contains(&o.id);
+        self.check_refunds(
+            self.bids
+                .iter()
+                .chain(self.asks.iter())
+                .filter(|o| is_target(o)),
+        )?;
+        // 买单（返还已预检，retain 中不会失败）
         self.bids.retain(|o| {
-            if o.owner == user && cancel_ids.contains(&o.id) {
-                let refund = o.price * o.quantity;
-                self.balances.get_mut(user).unwrap().quote += refund;
+            if is_target(o) && Self::refund(&mut self.balances, o).is_ok() {
                 self.event_queue.push(Event {
                     event_type: EventType::Cancel,
                     market: market.to_string(),
@@ -407,8 +600,7 @@ impl MarketState {
         });
         // 卖单
         self.asks.retain(|o| {
-            if o.owner == user && cancel_ids.contains(&o.id) {
-                self.balances.get_mut(user).unwrap().base += o.quantity;
+            if is_target(o) && Self::refund(&mut self.balances, o).is_ok() {
                 self.event_queue.push(Event {
                     event_type: EventType::Cancel,
                     market: market.to_string(),
@@ -425,6 +617,7 @@ impl MarketState {
                 true
             }
         });
+        Ok(())
     }
 
     /// 打印订单簿
@@ -472,6 +665,12 @@ pub struct Markets {
     pub markets: HashMap<String, MarketState>,
 }
 
+impl Default for Markets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Markets {
     /// 新建Markets实例
     pub fn new() -> Self {
@@ -482,22 +681,28 @@ impl Markets {
 
     /// 新建市场
     pub fn create_market(&mut self, market: &str) {
-        self.markets
-            .entry(market.to_string())
-            .or_insert_with(MarketState::default);
+        self.markets.entry(market.to_string()).or_default();
         println!("新市场已创建: {}", market);
     }
 
     /// 用户充值
-    pub fn deposit(&mut self, market: &str, user: &str, base: u64, quote: u64) {
+    pub fn deposit(
+        &mut self,
+        market: &str,
+        user: &str,
+        base: u64,
+        quote: u64,
+    ) -> Result<(), DexError> {
         if let Some(state) = self.markets.get_mut(market) {
-            state.deposit(user, base, quote);
+            state.deposit(user, base, quote)
         } else {
             println!("市场 {} 不存在", market);
+            Err(DexError::MarketNotFound)
         }
     }
 
     /// 下单
+    #[allow(clippy::too_many_arguments)]
     pub fn place_order(
         &mut self,
         market: &str,
@@ -508,28 +713,43 @@ impl Markets {
         now: u64,
         fee_bps: u64,
         expire_ts: Option<u64>,
-    ) -> Option<u64> {
+    ) -> Result<u64, DexError> {
         if let Some(state) = self.markets.get_mut(market) {
             state.place_order(
                 market, owner, side, price, quantity, now, fee_bps, expire_ts,
             )
         } else {
             println!("市场 {} 不存在", market);
-            None
+            Err(DexError::MarketNotFound)
         }
     }
 
     /// 批量撮合
-    pub fn batch_match(&mut self, market: &str, side: Side, n: usize, now: u64, fee_bps: u64) {
-        if let Some(state) = self.markets.get_mut(market) {
-            state.batch_match(market, side, n, now, fee_bps);
+    pub fn batch_match(
+        &mut self,
+        market: &str,
+        side: Side,
+        n: usize,
+        now: u64,
+        fee_bps: u64,
+    ) -> Result<(), DexError> {
+        match self.markets.get_mut(market) {
+            Some(state) => state.batch_match(market, side, n, now, fee_bps),
+            None => Err(DexError::MarketNotFound),
         }
     }
 
     /// 批量撤销
-    pub fn batch_cancel(&mut self, market: &str, user: &str, ids: &[u64], now: u64) {
-        if let Some(state) = self.markets.get_mut(market) {
-            state.batch_cancel(market, user, ids, now);
+    pub fn batch_cancel(
+        &mut self,
+        market: &str,
+        user: &str,
+        ids: &[u64],
+        now: u64,
+    ) -> Result<(), DexError> {
+        match self.markets.get_mut(market) {
+            Some(state) => state.batch_cancel(market, user, ids, now),
+            None => Err(DexError::MarketNotFound),
         }
     }
 
@@ -586,11 +806,11 @@ fn main() {
     markets.create_market("SOL/USDC");
 
     // 用户充值
-    markets.deposit("SOL/USDC", "Alice", 100, 2000);
-    markets.deposit("SOL/USDC", "Bob", 50, 1000);
+    markets.deposit("SOL/USDC", "Alice", 100, 2000).unwrap();
+    markets.deposit("SOL/USDC", "Bob", 50, 1000).unwrap();
 
     // Alice下买单，有效期5秒
-    let alice_bid = markets.place_order(
+    let _alice_bid = markets.place_order(
         "SOL/USDC",
         "Alice",
         Side::Bid,
@@ -602,7 +822,7 @@ fn main() {
     );
     now += 1;
     // Bob下卖单，有效期10秒
-    let bob_ask = markets.place_order(
+    let _bob_ask = markets.place_order(
         "SOL/USDC",
         "Bob",
         Side::Ask,
@@ -634,7 +854,9 @@ fn main() {
 
     // 批量撮合（批量对Bid订单撮合2笔）
     println!("\n--- 批量撮合 ---");
-    markets.batch_match("SOL/USDC", Side::Bid, 2, now, fee_bps);
+    if let Err(err) = markets.batch_match("SOL/USDC", Side::Bid, 2, now, fee_bps) {
+        println!("批量撮合失败：{}", err);
+    }
 
     // 批量撤单（批量撤销Bob所有挂单）
     println!("\n--- 批量撤单 ---");
@@ -645,7 +867,9 @@ fn main() {
             .filter(|o| o.owner == "Bob")
             .map(|o| o.id)
             .collect();
-        markets.batch_cancel("SOL/USDC", "Bob", &bob_orders, now);
+        if let Err(err) = markets.batch_cancel("SOL/USDC", "Bob", &bob_orders, now) {
+            println!("批量撤单失败：{}", err);
+        }
     }
 
     // 查看订单簿、余额、手续费、事件队列
@@ -664,3 +888,291 @@ fn main() {
     println!("\n--- crank2 首次批量消费事件（独立消费指针） ---");
     markets.print_market_event_consume("SOL/USDC", "crank2", 3);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MARKET: &str = "SOL/USDC";
+    const NOW: u64 = 1000;
+    const FEE_BPS: u64 = 30;
+
+    /// 简单的线性同余随机数（不引入外部依赖，失败时可按 seed 复现）
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            self.0 >> 33
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+
+        /// 偏向边界的随机数：小数、2 的幂、u32/u64 上限附近
+        fn extreme(&mut self) -> u64 {
+            match self.below(5) {
+                0 => self.below(1_000) + 1,
+                1 => 1u64 << self.below(64),
+                2 => u32::MAX as u64 - self.below(1_000),
+                3 => u64::MAX - self.below(1_000),
+                _ => (self.next() << 31) | self.next(),
+            }
+        }
+    }
+
+    fn balance(state: &MarketState, user: &str) -> (u64, u64) {
+        state
+            .balances
+            .get(user)
+            .map(|b| (b.base, b.quote))
+            .unwrap_or((0, 0))
+    }
+
+    /// 订单簿、按用户名排序的余额、手续费池和事件序号，用于比较操作前后状态是否完全一致
+    fn snapshot(state: &MarketState) -> String {
+        let mut balances: Vec<_> = state.balances.iter().collect();
+        balances.sort_by_key(|(user, _)| user.as_str());
+        format!(
+            "{:?} {:?} {:?} {} {}",
+            state.bids,
+            state.asks,
+            balances,
+            state.fee_receiver.collected_fee,
+            state.event_queue.next_seq
+        )
+    }
+
+    /// 总量守恒：所有用户余额 + 挂单冻结的资金 + 手续费池 = 累计充值（用 u128 计算，不会溢出）
+    fn assert_conserved(state: &MarketState, deposited: (u128, u128)) {
+        let mut held = (0u128, state.fee_receiver.collected_fee as u128);
+        for bal in state.balances.values() {
+            held.0 += bal.base as u128;
+            held.1 += bal.quote as u128;
+        }
+        for order in state.bids.iter().chain(state.asks.iter()) {
+            match order.side {
+                Side::Bid => held.1 += order.price as u128 * order.quantity as u128,
+                Side::Ask => held.0 += order.quantity as u128,
+            }
+        }
+        assert_eq!(held, deposited);
+    }
+
+    #[test]
+    fn test_deposit_overflow_and_unknown_market() {
+        let mut markets = Markets::new();
+        markets.create_market(MARKET);
+        markets.deposit(MARKET, "A", u64::MAX, 0).unwrap();
+        assert_eq!(markets.deposit(MARKET, "A", 1, 1), Err(DexError::Overflow));
+        assert_eq!(balance(&markets.markets[MARKET], "A"), (u64::MAX, 0));
+
+        assert_eq!(
+            markets.deposit("BTC/USDT", "A", 1, 1),
+            Err(DexError::MarketNotFound)
+        );
+        assert_eq!(
+            markets.place_order("BTC/USDT", "A", Side::Bid, 1, 1, NOW, FEE_BPS, None),
+            Err(DexError::MarketNotFound)
+        );
+        assert_eq!(
+            markets.batch_match("BTC/USDT", Side::Bid, 1, NOW, FEE_BPS),
+            Err(DexError::MarketNotFound)
+        );
+        assert_eq!(
+            markets.batch_cancel("BTC/USDT", "A", &[0], NOW),
+            Err(DexError::MarketNotFound)
+        );
+    }
+
+    #[test]
+    fn test_check_settlement_rejects_without_changes() {
+        let mut state = MarketState::default();
+        state.deposit("A", 0, u64::MAX).unwrap();
+
+        // 价格 × 数量溢出、余额不足：拒绝下单，状态不变
+        let before = snapshot(&state);
+        assert_eq!(
+            state.place_order(MARKET, "A", Side::Bid, u64::MAX, 2, NOW, FEE_BPS, None),
+            Err(DexError::Overflow)
+        );
+        assert_eq!(
+            state.place_order(MARKET, "A", Side::Ask, 1, 1, NOW, FEE_BPS, None),
+            Err(DexError::InsufficientBalance)
+        );
+        assert_eq!(snapshot(&state), before);
+
+        // 成交时卖家报价币余额会溢出：整笔卖单被拒绝，订单簿、余额、手续费池和事件都不变
+        state
+            .place_order(MARKET, "A", Side::Bid, 2, 10, NOW, FEE_BPS, None)
+            .unwrap();
+        state.deposit("B", 10, u64::MAX).unwrap();
+        let before = snapshot(&state);
+        assert_eq!(
+            state.place_order(MARKET, "B", Side::Ask, 2, 10, NOW, 0, None),
+            Err(DexError::Overflow)
+        );
+        assert_eq!(snapshot(&state), before);
+
+        // 手续费池会溢出：同样整笔拒绝
+        state.fee_receiver.collected_fee = u64::MAX;
+        state.deposit("C", 10, 0).unwrap();
+        let before = snapshot(&state);
+        assert_eq!(
+            state.place_order(MARKET, "C", Side::Ask, 2, 10, NOW, 10_000, None),
+            Err(DexError::Overflow)
+        );
+        assert_eq!(snapshot(&state), before);
+    }
+
+    #[test]
+    fn test_check_refunds_rejects_without_changes() {
+        let mut state = MarketState::default();
+        state.deposit("A", 20, 0).unwrap();
+        let keep = state
+            .place_order(MARKET, "A", Side::Ask, 2, 10, NOW, FEE_BPS, None)
+            .unwrap();
+        state
+            .place_order(MARKET, "A", Side::Ask, 3, 10, NOW, FEE_BPS, Some(NOW + 10))
+            .unwrap();
+
+        // 两笔卖单合计返还 20 个主币：单笔不溢出、合计溢出，批量撤单整体拒绝
+        state.deposit("A", u64::MAX - 10, 0).unwrap();
+        let before = snapshot(&state);
+        assert_eq!(
+            state.batch_cancel(MARKET, "A", &[keep, keep + 1], NOW),
+            Err(DexError::Overflow)
+        );
+        assert_eq!(snapshot(&state), before);
+
+        // 过期清理返还溢出：同样整体拒绝，过期订单保留
+        state.deposit("A", 10, 0).unwrap();
+        let before = snapshot(&state);
+        assert_eq!(
+            state.clean_expired_orders(NOW + 10, MARKET),
+            Err(DexError::Overflow)
+        );
+        assert_eq!(snapshot(&state), before);
+
+        // 清理失败时下单也被拒绝
+        state.deposit("B", 0, 100).unwrap();
+        let before = snapshot(&state);
+        assert_eq!(
+            state.place_order(MARKET, "B", Side::Bid, 1, 1, NOW + 10, FEE_BPS, None),
+            Err(DexError::Overflow)
+        );
+        assert_eq!(snapshot(&state), before);
+    }
+
+    #[test]
+    fn test_resting_remainder_stays_locked_and_price_improvement_is_refunded() {
+        let mut state = MarketState::default();
+        state.deposit("A", 10, 0).unwrap();
+        state.deposit("B", 0, 100_000).unwrap();
+        state
+            .place_order(MARKET, "A", Side::Ask, 2_000, 4, NOW, FEE_BPS, None)
+            .unwrap();
+
+        // 限价 5000 买 10 个：4 个按卖单价 2000 成交（手续费 8000 × 0.3% = 24 由卖家承担），
+        // 价差 (5000 - 2000) × 4 返还，剩余 6 个挂单冻结 30000
+        let bid = state
+            .place_order(
+                MARKET,
+                "B",
+                Side::Bid,
+                5_000,
+                10,
+                NOW,
+                FEE_BPS,
+                Some(NOW + 10),
+            )
+            .unwrap();
+        assert_eq!(balance(&state, "A"), (6, 8_000 - 24));
+        assert_eq!(balance(&state, "B"), (4, 100_000 - 8_000 - 30_000));
+        assert_eq!(state.fee_receiver.collected_fee, 24);
+
+        // 过期清理只返还一次冻结的 30000，之后撤单找不到订单
+        state.clean_expired_orders(NOW + 10, MARKET).unwrap();
+        assert_eq!(balance(&state, "B"), (4, 92_000));
+        state.batch_cancel(MARKET, "B", &[bid], NOW + 10).unwrap();
+        assert_eq!(balance(&state, "B"), (4, 92_000));
+        assert_eq!(state.event_queue.next_seq, 2);
+        assert_conserved(&state, (10, 100_000));
+    }
+
+    /// 随机充值/下单/批量撮合/批量撤单/过期清理，价格、数量和费率偏向边界值：
+    /// 任何操作都不能 panic，被拒绝的操作不改变状态，每一步之后总量守恒（含手续费池）
+    #[test]
+    fn test_fuzz_extreme_prices_and_quantities() {
+        let users = ["A", "B", "C"];
+        for seed in 0..200 {
+            let mut rng = Lcg(seed);
+            let mut state = MarketState::default();
+            let mut deposited = (0u128, 0u128);
+            for user in users {
+                state.deposit(user, u64::MAX / 4, u64::MAX / 4).unwrap();
+                deposited.0 += (u64::MAX / 4) as u128;
+                deposited.1 += (u64::MAX / 4) as u128;
+            }
+            let mut order_ids: Vec<u64> = Vec::new();
+            for step in 0..100 {
+                let now = NOW + step;
+                let user = users[rng.below(3) as usize];
+                let fee_bps = [0, FEE_BPS, 10_000, 20_000][rng.below(4) as usize];
+                // 下单前先单独清理过期订单，之后下单被拒绝时状态必须完全不变
+                if state.clean_expired_orders(now, MARKET).is_err() {
+                    continue;
+                }
+                let before = snapshot(&state);
+                match rng.below(6) {
+                    0 => {
+                        let (base, quote) = (rng.extreme(), rng.extreme());
+                        if state.deposit(user, base, quote).is_ok() {
+                            deposited.0 += base as u128;
+                            deposited.1 += quote as u128;
+                        } else {
+                            assert_eq!(snapshot(&state), before);
+                        }
+                    }
+                    1 if !order_ids.is_empty() => {
+                        let n = rng.below(3) as usize + 1;
+                        let ids: Vec<u64> = (0..n)
+                            .map(|_| order_ids[rng.below(order_ids.len() as u64) as usize])
+                            .collect();
+                        if state.batch_cancel(MARKET, user, &ids, now).is_err() {
+                            assert_eq!(snapshot(&state), before);
+                        }
+                    }
+                    2 => {
+                        // 每笔订单各自原子执行，失败前已执行的订单保留，这里只检查守恒
+                        let side = if rng.below(2) == 0 {
+                            Side::Bid
+                        } else {
+                            Side::Ask
+                        };
+                        let _ = state.batch_match(MARKET, side, 2, now, fee_bps);
+                    }
+                    k => {
+                        let side = if k % 2 == 0 { Side::Bid } else { Side::Ask };
+                        let (price, quantity) = (rng.extreme(), rng.extreme());
+                        let expire_ts = match rng.below(3) {
+                            0 => Some(now + rng.below(20)),
+                            _ => None,
+                        };
+                        match state.place_order(
+                            MARKET, user, side, price, quantity, now, fee_bps, expire_ts,
+                        ) {
+                            Ok(id) => order_ids.push(id),
+                            Err(_) => assert_eq!(snapshot(&state), before),
+                        }
+                    }
+                }
+                assert_conserved(&state, deposited);
+            }
+        }
+    }
+}
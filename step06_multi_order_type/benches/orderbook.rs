@@ -0,0 +1,147 @@
+//! 订单簿性能对比：价格档位 BookSide vs 旧版有序 Vec<Order>
+//!
+//! 运行：cargo bench -p step06_multi_order_type
+//!
+//! 每个场景都在同一组随机订单上执行：
+//! 1. 挂单：依次插入 N 个随机价格的订单
+//! 2. 撤单：按订单ID撤销其中一半
+//! 3. 吃单：不断弹出最优订单直到订单簿为空（模拟撮合）
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use step06_multi_order_type::book_side::BookSide;
+use step06_multi_order_type::openbook::{Order, OrderType, Side};
+
+/// 旧版订单簿的一侧：每次插入后整体排序，撮合时 remove(0)
+struct VecBookSide {
+    side: Side,
+    orders: Vec<Order>,
+}
+
+impl VecBookSide {
+    fn new(side: Side) -> Self {
+        Self {
+            side,
+            orders: vec![],
+        }
+    }
+
+    fn insert(&mut self, order: Order) {
+        self.orders.push(order);
+        match self.side {
+            Side::Bid => self.orders.sort_by_key(|o| std::cmp::Reverse(o.price)),
+            Side::Ask => self.orders.sort_by_key(|o| o.price),
+        }
+    }
+
+    fn remove(&mut self, order_id: u64) -> Option<Order> {
+        let pos = self.orders.iter().position(|o| o.id == order_id)?;
+        Some(self.orders.remove(pos))
+    }
+
+    fn pop_best(&mut self) -> Option<Order> {
+        if self.orders.is_empty() {
+            None
+        } else {
+            Some(self.orders.remove(0))
+        }
+    }
+}
+
+/// 简单的线性同余随机数（不引入外部依赖，保证每次运行数据一致）
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+}
+
+fn random_orders(n: usize, side: Side) -> Vec<Order> {
+    let mut rng = Lcg(42);
+    (0..n as u64)
+        .map(|id| Order {
+            id,
+            owner: format!("user{}", id % 100),
+            side: side.clone(),
+            price: 1 + rng.next() % 1000,
+            quantity: 1 + rng.next() % 100,
+            expire_ts: None,
+            order_type: OrderType::Limit,
+            client_order_id: 0,
+        })
+        .collect()
+}
+
+fn cancel_ids(n: usize) -> Vec<u64> {
+    let mut rng = Lcg(7);
+    (0..n / 2).map(|_| rng.next() % n as u64).collect()
+}
+
+fn time<F: FnOnce()>(f: F) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn bench_vec(orders: &[Order], cancels: &[u64], side: Side) -> [Duration; 3] {
+    let mut book = VecBookSide::new(side);
+    let insert = time(|| {
+        for order in orders {
+            book.insert(order.clone());
+        }
+    });
+    let cancel = time(|| {
+        for id in cancels {
+            black_box(book.remove(*id));
+        }
+    });
+    let matching = time(|| while black_box(book.pop_best()).is_some() {});
+    [insert, cancel, matching]
+}
+
+fn bench_levels(orders: &[Order], cancels: &[u64], side: Side) -> [Duration; 3] {
+    let mut book = BookSide::new(side);
+    let insert = time(|| {
+        for order in orders {
+            book.insert(order.clone());
+        }
+    });
+    let cancel = time(|| {
+        for id in cancels {
+            black_box(book.remove(*id));
+        }
+    });
+    let matching = time(|| while black_box(book.pop_best()).is_some() {});
+    [insert, cancel, matching]
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>4} {:>10} {:>14} {:>14}",
+        "orders", "side", "op", "Vec<Order>", "BookSide"
+    );
+    for n in [1_000, 10_000, 20_000] {
+        for side in [Side::Bid, Side::Ask] {
+            let orders = random_orders(n, side.clone());
+            let cancels = cancel_ids(n);
+            let vec = bench_vec(&orders, &cancels, side.clone());
+            let levels = bench_levels(&orders, &cancels, side.clone());
+            for (i, op) in ["insert", "cancel", "match"].iter().enumerate() {
+                println!(
+                    "{:>8} {:>4} {:>10} {:>14?} {:>14?}",
+                    n,
+                    format!("{:?}", side),
+                    op,
+                    vec[i],
+                    levels[i]
+                );
+            }
+        }
+    }
+}
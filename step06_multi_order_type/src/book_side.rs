@@ -0,0 +1,253 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::openbook::{Order, Side};
+use crate::slab::{LeafNode, NodeHandle, Slab, SlabError, order_key};
+
+/// 同一价格档位上的订单队列
+/// key 为入簿序号（越小越早入簿），保证同价位的时间优先
+pub type PriceLevel = BTreeMap<u64, Order>;
+
+/// 订单簿底层存储
+#[derive(Clone)]
+enum Storage {
+    /// 价格档位（价格 -> 该价格档位的订单队列），容量不限
+    Levels(BTreeMap<u64, PriceLevel>),
+    /// Serum 风格的 critbit slab，容量固定
+    Slab(Slab),
+}
+
+/// 订单簿的一侧（买单簿或卖单簿）
+/// BookSide replaces the sorted Vec<Order> with an ordered store:
+/// - 插入/撤单 O(log n)：按订单ID找到 (价格, 入簿序号)，再在存储中定位订单
+/// - 取最优价 O(log n)：买单簿取最高价，卖单簿取最低价
+/// - 严格价格优先、时间优先
+///
+/// 底层存储可以是价格档位（`BookSide::new`），也可以是 Serum 的 critbit slab（`BookSide::with_slab`）
+#[derive(Clone)]
+pub struct BookSide {
+    /// 所属方向（决定最优价是最高价还是最低价）
+    side: Side,
+    /// 底层存储
+    storage: Storage,
+    /// 订单ID -> (价格, 入簿序号)，用于 O(log n) 撤单
+    index: HashMap<u64, (u64, u64)>,
+    /// 下一个入簿序号
+    next_seq: u64,
+}
+
+impl BookSide {
+    /// 新建空的一侧订单簿（价格档位存储）
+    pub fn new(side: Side) -> Self {
+        Self::with_storage(side, Storage::Levels(BTreeMap::new()))
+    }
+
+    /// 新建空的一侧订单簿（critbit slab 存储，最多容纳 max_orders 个挂单）
+    pub fn with_slab(side: Side, max_orders: usize) -> Self {
+        // n 个叶子需要 n - 1 个内部节点
+        let capacity = (2 * max_orders).saturating_sub(1);
+        Self::with_storage(side, Storage::Slab(Slab::with_capacity(capacity)))
+    }
+
+    fn with_storage(side: Side, storage: Storage) -> Self {
+        Self {
+            side,
+            storage,
+            index: HashMap::new(),
+            next_seq: 0,
+        }
+    }
+
+    /// 订单数量
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// 是否为空
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// 是否已满（价格档位存储没有容量上限，slab 存储受节点数限制）
+    pub fn is_full(&self) -> bool {
+        match &self.storage {
+            Storage::Levels(_) => false,
+            Storage::Slab(slab) => self.len() >= slab.capacity().div_ceil(2),
+        }
+    }
+
+    /// 挂入订单（排在同价位所有已有订单之后）
+    /// slab 存储已满时与 Serum 一致：挤出优先级最低的订单并返回它；
+    /// 若新订单本身就是优先级最低的，则不入簿，直接返回新订单
+    pub fn insert(&mut self, order: Order) -> Option<Order> {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let (order_id, price) = (order.id, order.price);
+        let bumped = match &mut self.storage {
+            Storage::Levels(levels) => {
+                levels.entry(price).or_default().insert(seq, order);
+                None
+            }
+            Storage::Slab(slab) => {
+                let leaf = LeafNode::new(order_key(&self.side, price, seq), order);
+                match slab.insert_leaf(&leaf) {
+                    Ok(_) => None,
+                    Err(SlabError::OutOfSpace) => {
+                        let worst = worst_handle(&self.side, slab)
+                            .and_then(|h| slab.get_leaf(h))
+                            .map(|l| l.key);
+                        let new_is_better = match (worst, &self.side) {
+                            (None, _) => false,
+                            (Some(key), Side::Bid) => leaf.key > key,
+                            (Some(key), Side::Ask) => leaf.key < key,
+                        };
+                        if !new_is_better {
+                            return Some(leaf.order);
+                        }
+                        let worst = slab.remove_by_key(worst.unwrap()).unwrap();
+                        slab.insert_leaf(&leaf)
+                            .expect("slab has room after removing a leaf");
+                        Some(worst.order)
+                    }
+                }
+            }
+        };
+        if let Some(bumped) = &bumped {
+            self.index.remove(&bumped.id);
+        }
+        self.index.insert(order_id, (price, seq));
+        bumped
+    }
+
+    /// 按订单ID移除订单
+    pub fn remove(&mut self, order_id: u64) -> Option<Order> {
+        let (price, seq) = self.index.remove(&order_id)?;
+        match &mut self.storage {
+            Storage::Levels(levels) => {
+                let level = levels.get_mut(&price)?;
+                let order = level.remove(&seq);
+                if level.is_empty() {
+                    levels.remove(&price);
+                }
+                order
+            }
+            Storage::Slab(slab) => slab
+                .remove_by_key(order_key(&self.side, price, seq))
+                .map(|leaf| leaf.order),
+        }
+    }
+
+    /// 按订单ID查询订单
+    pub fn get(&self, order_id: u64) -> Option<&Order> {
+        let (price, seq) = self.index.get(&order_id)?;
+        match &self.storage {
+            Storage::Levels(levels) => levels.get(price)?.get(seq),
+            Storage::Slab(slab) => {
+                let handle = slab.find_by_key(order_key(&self.side, *price, *seq))?;
+                slab.get_leaf(handle).map(|leaf| &leaf.order)
+            }
+        }
+    }
+
+    /// 按订单ID查询订单的可变引用
+    /// 只能修改数量等不影响排序的字段，改价需要 remove 后重新 insert
+    pub fn get_mut(&mut self, order_id: u64) -> Option<&mut Order> {
+        let (price, seq) = self.index.get(&order_id)?;
+        match &mut self.storage {
+            Storage::Levels(levels) => levels.get_mut(price)?.get_mut(seq),
+            Storage::Slab(slab) => {
+                let handle = slab.find_by_key(order_key(&self.side, *price, *seq))?;
+                slab.get_leaf_mut(handle)
+            }
+        }
+    }
+
+    /// 最优价格
+    pub fn best_price(&self) -> Option<u64> {
+        self.best().map(|o| o.price)
+    }
+
+    /// 最优订单（价格最优、同价位最早入簿）
+    pub fn best(&self) -> Option<&Order> {
+        match &self.storage {
+            Storage::Levels(levels) => {
+                let level = match self.side {
+                    Side::Bid => levels.values().next_back(),
+                    Side::Ask => levels.values().next(),
+                }?;
+                level.values().next()
+            }
+            Storage::Slab(slab) => {
+                let handle = best_handle(&self.side, slab)?;
+                slab.get_leaf(handle).map(|leaf| &leaf.order)
+            }
+        }
+    }
+
+    /// 最优订单的可变引用（用于撮合时扣减数量）
+    pub fn best_mut(&mut self) -> Option<&mut Order> {
+        match &mut self.storage {
+            Storage::Levels(levels) => {
+                let level = match self.side {
+                    Side::Bid => levels.values_mut().next_back(),
+                    Side::Ask => levels.values_mut().next(),
+                }?;
+                level.values_mut().next()
+            }
+            Storage::Slab(slab) => {
+                let handle = best_handle(&self.side, slab)?;
+                slab.get_leaf_mut(handle)
+            }
+        }
+    }
+
+    /// 弹出最优订单
+    pub fn pop_best(&mut self) -> Option<Order> {
+        let order_id = self.best()?.id;
+        self.remove(order_id)
+    }
+
+    /// 按撮合优先级（价格优先、时间优先）遍历所有订单
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &Order> + '_> {
+        match (&self.storage, &self.side) {
+            (Storage::Levels(levels), Side::Bid) => {
+                Box::new(levels.values().rev().flat_map(|level| level.values()))
+            }
+            (Storage::Levels(levels), Side::Ask) => {
+                Box::new(levels.values().flat_map(|level| level.values()))
+            }
+            (Storage::Slab(slab), Side::Bid) => Box::new(slab.iter_rev().map(|leaf| &leaf.order)),
+            (Storage::Slab(slab), Side::Ask) => Box::new(slab.iter().map(|leaf| &leaf.order)),
+        }
+    }
+
+    /// 保留满足条件的订单，移除其余订单（如清理过期订单）
+    pub fn retain<F: FnMut(&Order) -> bool>(&mut self, mut keep: F) {
+        let removed: Vec<u64> = self.iter().filter(|o| !keep(o)).map(|o| o.id).collect();
+        for order_id in removed {
+            self.remove(order_id);
+        }
+    }
+}
+
+/// slab 中优先级最高的订单（买单 key 最大，卖单 key 最小）
+fn best_handle(side: &Side, slab: &Slab) -> Option<NodeHandle> {
+    match side {
+        Side::Bid => slab.find_max(),
+        Side::Ask => slab.find_min(),
+    }
+}
+
+/// slab 中优先级最低的订单（订单簿已满时被挤出）
+fn worst_handle(side: &Side, slab: &Slab) -> Option<NodeHandle> {
+    match side {
+        Side::Bid => slab.find_min(),
+        Side::Ask => slab.find_max(),
+    }
+}
+
+impl fmt::Debug for BookSide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
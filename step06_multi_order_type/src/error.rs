@@ -0,0 +1,137 @@
+use std::fmt;
+
+/// 撮合引擎错误类型
+/// DexError is the typed error returned by market operations.
+/// 对齐 Serum DEX 的 DexErrorCode，调用方（如做市机器人）可按变体区分失败原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DexError {
+    /// 市场不存在
+    MarketNotFound(String),
+    /// 同名市场已存在
+    MarketAlreadyExists(String),
+    /// 主币余额不足（needed: 需要的数量，available: 当前可用数量）
+    InsufficientBase { needed: u64, available: u64 },
+    /// 报价币余额不足（needed: 需要的数量，available: 当前可用数量）
+    InsufficientQuote { needed: u64, available: u64 },
+    /// 钱包中某种币余额不足（token: 币种，needed: 需要的数量，available: 当前余额）
+    InsufficientFunds {
+        token: String,
+        needed: u64,
+        available: u64,
+    },
+    /// 提现超过可用（未被挂单冻结）余额（token: 币种，requested: 申请数量，free: 可用，locked: 被挂单冻结）
+    InsufficientFreeBalance {
+        token: String,
+        requested: u64,
+        free: u64,
+        locked: u64,
+    },
+    /// FOK 订单无法全部成交（requested: 下单数量，fillable: 订单簿可成交数量）
+    FokNotFillable { requested: u64, fillable: u64 },
+    /// 订单数量非法（如数量为0）
+    InvalidQuantity,
+    /// 订单数量不是 base_lot_size 的整数倍
+    InvalidLotSize { quantity: u64, lot_size: u64 },
+    /// 订单价格不是 tick_size 的整数倍
+    InvalidTickSize { price: u64, tick_size: u64 },
+    /// 市场配置非法
+    InvalidMarketConfig(String),
+    /// 只挂单（PostOnly）订单会立即成交（price: 订单价格，best_price: 对手方最优价）
+    PostOnlyWouldCross { price: u64, best_price: u64 },
+    /// 改单后的价格会与对手方挂单成交（price: 新价格，best_price: 对手方最优价）
+    AmendWouldCross { price: u64, best_price: u64 },
+    /// 订单会与自己的挂单成交（自成交策略为 AbortTransaction，maker_order_id: 自己的挂单ID）
+    SelfTrade { maker_order_id: u64 },
+    /// 订单不存在（或不属于该用户）
+    OrderNotFound(u64),
+    /// 用户没有该客户端订单ID的挂单
+    ClientOrderIdNotFound(u64),
+    /// 客户端订单ID与该用户已有的挂单重复
+    DuplicateClientOrderId(u64),
+    /// 无权执行该操作（如非市场管理员提取手续费）
+    Unauthorized(String),
+    /// 事件队列已满，需等待 crank 消费后才能继续撮合
+    QueueFull,
+    /// 数值溢出（金额、余额或手续费超出 u64 范围）
+    Overflow,
+    /// 账本不变量被破坏（说明撮合/结算逻辑有 bug）
+    InvariantViolation(String),
+}
+
+impl fmt::Display for DexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DexError::MarketNotFound(market) => write!(f, "市场 {} 不存在", market),
+            DexError::MarketAlreadyExists(market) => write!(f, "市场 {} 已存在", market),
+            DexError::InsufficientBase { needed, available } => {
+                write!(f, "主币余额不足：需要 {}，可用 {}", needed, available)
+            }
+            DexError::InsufficientQuote { needed, available } => {
+                write!(f, "报价币余额不足：需要 {}，可用 {}", needed, available)
+            }
+            DexError::InsufficientFunds {
+                token,
+                needed,
+                available,
+            } => write!(f, "{} 余额不足：需要 {}，可用 {}", token, needed, available),
+            DexError::InsufficientFreeBalance {
+                token,
+                requested,
+                free,
+                locked,
+            } => write!(
+                f,
+                "{} 可用余额不足：申请提现 {}，可用 {}，挂单冻结 {}",
+                token, requested, free, locked
+            ),
+            DexError::FokNotFillable {
+                requested,
+                fillable,
+            } => write!(
+                f,
+                "FOK订单无法全部成交：下单 {}，可成交 {}",
+                requested, fillable
+            ),
+            DexError::InvalidQuantity => write!(f, "订单数量非法"),
+            DexError::InvalidLotSize { quantity, lot_size } => {
+                write!(
+                    f,
+                    "订单数量 {} 不是 lot size {} 的整数倍",
+                    quantity, lot_size
+                )
+            }
+            DexError::InvalidTickSize { price, tick_size } => {
+                write!(
+                    f,
+                    "订单价格 {} 不是 tick size {} 的整数倍",
+                    price, tick_size
+                )
+            }
+            DexError::InvalidMarketConfig(reason) => write!(f, "市场配置非法：{}", reason),
+            DexError::PostOnlyWouldCross { price, best_price } => write!(
+                f,
+                "只挂单订单价格 {} 会与对手方最优价 {} 成交，已拒绝",
+                price, best_price
+            ),
+            DexError::AmendWouldCross { price, best_price } => write!(
+                f,
+                "改单后价格 {} 会与对手方最优价 {} 成交，已拒绝",
+                price, best_price
+            ),
+            DexError::SelfTrade { maker_order_id } => {
+                write!(f, "订单会与自己的挂单 {} 成交，已拒绝", maker_order_id)
+            }
+            DexError::OrderNotFound(id) => write!(f, "订单 {} 不存在", id),
+            DexError::ClientOrderIdNotFound(id) => write!(f, "客户端订单ID {} 不存在", id),
+            DexError::DuplicateClientOrderId(id) => {
+                write!(f, "客户端订单ID {} 与已有挂单重复", id)
+            }
+            DexError::Unauthorized(user) => write!(f, "用户 {} 无权执行该操作", user),
+            DexError::QueueFull => write!(f, "事件队列已满"),
+            DexError::Overflow => write!(f, "数值溢出"),
+            DexError::InvariantViolation(reason) => write!(f, "账本不变量被破坏：{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for DexError {}
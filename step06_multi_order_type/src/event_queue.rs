@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+
+use crate::error::DexError;
+use crate::openbook::{Order, Side};
+
+/// 事件类型枚举（撮合/撤单/过期）
+/// EventType describes the event kind in event queue.
+#[derive(Debug, Clone)]
+pub enum EventType {
+    /// 成交事件（订单被撮合成交）
+    Fill,
+    /// 撤单事件（用户撤销订单）
+    Cancel,
+    /// 过期事件（订单到期自动撤销）
+    Expire,
+    /// 手续费提取事件（taker 为管理员，quantity 为提取的报价币数量）
+    SweepFees,
+    /// 改单事件（price、quantity 为修改后的挂单价格和数量）
+    Amend,
+    /// 挂单离开订单簿：紧跟在导致离开的成交、撤单、过期、被挤出或自成交撤销事件之后，
+    /// quantity 为离开时撤销的未成交数量（完全成交为0）
+    Out,
+}
+
+/// 事件队列中每条事件结构
+#[derive(Debug, Clone)]
+pub struct Event {
+    /// 事件类型（成交/撤单/过期）
+    pub event_type: EventType,
+    /// 所属市场名（如 "SOL/USDC"）
+    pub market: String,
+    /// maker账户（撮合中的被动方，部分事件可为None）
+    pub maker: Option<String>,
+    /// taker账户（撮合中的主动方；非成交事件为订单所有者，手续费提取事件为管理员）
+    pub taker: Option<String>,
+    /// 成交价格（部分事件可为None）
+    pub price: Option<u64>,
+    /// 成交数量
+    pub quantity: u64,
+    /// taker 支付的手续费（单位：报价币）
+    pub fee: u64,
+    /// maker 获得的返佣（单位：报价币）
+    pub rebate: u64,
+    /// 成交时 taker 适用的手续费档位（0 为基础费率，非成交事件为0）
+    pub taker_fee_tier: usize,
+    /// 成交时 maker 适用的手续费档位（0 为基础费率，非成交事件为0）
+    pub maker_fee_tier: usize,
+    /// 成交时 taker 指定的推荐人（无推荐人或非成交事件为None）
+    pub referrer: Option<String>,
+    /// 推荐人从 taker 手续费中分得的返佣（单位：报价币，已计入 fee）
+    pub referrer_rebate: u64,
+    /// 订单ID（成交事件为 taker 的订单ID）
+    pub order_id: u64,
+    /// 订单的客户端订单ID（未指定或非订单事件为0；成交事件为 taker 的客户端订单ID）
+    pub client_order_id: u64,
+    /// 被吃掉的挂单ID（非成交事件为None）
+    pub maker_order_id: Option<u64>,
+    /// 被吃掉的挂单的客户端订单ID（未指定或非成交事件为0）
+    pub maker_client_order_id: u64,
+    /// taker 付出的数量（native，买单包含手续费）：买单为 成交金额 + 手续费 的报价币，卖单为主币数量
+    pub taker_native_paid: u64,
+    /// taker 收到的数量（native，卖单已扣除手续费）：买单为主币数量，卖单为 成交金额 - 手续费 的报价币
+    pub taker_native_received: u64,
+    /// maker 付出的数量（native，已计入返佣）：卖单 maker 为主币数量，买单 maker 为 成交金额 - 返佣 的报价币
+    pub maker_native_paid: u64,
+    /// maker 收到的数量（native，已计入返佣）：卖单 maker 为 成交金额 + 返佣 的报价币，买单 maker 为主币数量
+    pub maker_native_received: u64,
+    /// 订单方向（成交事件为 taker 的方向，maker 在另一侧；手续费提取事件没有方向，为None）
+    pub side: Option<Side>,
+    /// 事件发生的时间戳
+    pub timestamp: u64,
+}
+
+impl Event {
+    /// 单个订单的事件（撤单/过期/改单/离开订单簿）：taker 为订单所有者，价格、方向和订单ID取自订单，
+    /// quantity 由调用方给出；成交相关字段为0
+    pub fn order_event(
+        event_type: EventType,
+        market: &str,
+        order: &Order,
+        quantity: u64,
+        now: u64,
+    ) -> Self {
+        Self {
+            event_type,
+            market: market.to_string(),
+            maker: None,
+            taker: Some(order.owner.clone()),
+            price: Some(order.price),
+            quantity,
+            fee: 0,
+            rebate: 0,
+            taker_fee_tier: 0,
+            maker_fee_tier: 0,
+            referrer: None,
+            referrer_rebate: 0,
+            order_id: order.id,
+            client_order_id: order.client_order_id,
+            maker_order_id: None,
+            maker_client_order_id: 0,
+            taker_native_paid: 0,
+            taker_native_received: 0,
+            maker_native_paid: 0,
+            maker_native_received: 0,
+            side: Some(order.side.clone()),
+            timestamp: now,
+        }
+    }
+
+    /// 成交事件：taker 吃掉挂单 maker 的 quantity，成交价为挂单价格，方向为 taker 的方向；
+    /// 手续费、返佣、档位、推荐人和 native 金额由调用方用结构体更新语法填入
+    pub fn fill(market: &str, taker: &Order, maker: &Order, quantity: u64, now: u64) -> Self {
+        Self {
+            event_type: EventType::Fill,
+            maker: Some(maker.owner.clone()),
+            maker_order_id: Some(maker.id),
+            maker_client_order_id: maker.client_order_id,
+            price: Some(maker.price),
+            ..Self::order_event(EventType::Fill, market, taker, quantity, now)
+        }
+    }
+
+    /// 手续费提取事件：taker 为管理员，quantity 为提取的报价币数量
+    pub fn sweep_fees(market: &str, authority: &str, amount: u64, now: u64) -> Self {
+        Self {
+            event_type: EventType::SweepFees,
+            market: market.to_string(),
+            maker: None,
+            taker: Some(authority.to_string()),
+            price: None,
+            quantity: amount,
+            fee: 0,
+            rebate: 0,
+            taker_fee_tier: 0,
+            maker_fee_tier: 0,
+            referrer: None,
+            referrer_rebate: 0,
+            order_id: 0,
+            client_order_id: 0,
+            maker_order_id: None,
+            maker_client_order_id: 0,
+            taker_native_paid: 0,
+            taker_native_received: 0,
+            maker_native_paid: 0,
+            maker_native_received: 0,
+            side: None,
+            timestamp: now,
+        }
+    }
+}
+
+/// 事件队列默认容量
+pub const DEFAULT_EVENT_QUEUE_CAPACITY: usize = 1024;
+
+/// 市场事件队列
+/// 对齐 Serum DEX 的 EventQueue：固定容量的环形缓冲区，由 head（队首位置）和 count（事件数）描述，
+/// 队列满时拒绝写入（QueueFull），只有队首事件被弹出后才有空间继续撮合。
+///
+/// 每个事件都有一个全局递增的序号（seq），队首事件的序号为 next_seq - count。
+/// consumer 的消费指针记录的是"下一个要读的序号"，因此 head 前进（弹出事件）后指针依然有效。
+/// 所有已注册 consumer 都读过的事件可以通过 pop_consumed 弹出，释放队列空间。
+#[derive(Debug)]
+pub struct EventQueue {
+    /// 环形缓冲区（长度即容量）
+    buf: Vec<Option<Event>>,
+    /// 队首事件在缓冲区中的位置
+    head: usize,
+    /// 队列中的事件数
+    count: usize,
+    /// 下一个事件序号（用于分配事件序号、便于指针管理）
+    pub next_seq: u64,
+    /// 已注册consumer（如crank/前端）的消费指针，记录该consumer下一个要读取的事件序号
+    pub consumer_positions: HashMap<String, u64>,
+}
+
+impl Default for EventQueue {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_EVENT_QUEUE_CAPACITY)
+    }
+}
+
+impl EventQueue {
+    /// 新建容量为 capacity 的事件队列
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: vec![None; capacity],
+            head: 0,
+            count: 0,
+            next_seq: 0,
+            consumer_positions: HashMap::new(),
+        }
+    }
+
+    /// 队列容量
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// 队列中的事件数
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// 队列是否为空
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// 队列是否已满
+    pub fn is_full(&self) -> bool {
+        self.count == self.buf.len()
+    }
+
+    /// 剩余可写入的事件数
+    pub fn free_slots(&self) -> usize {
+        self.buf.len() - self.count
+    }
+
+    /// 队首事件的序号
+    pub fn head_seq(&self) -> u64 {
+        self.next_seq - self.count as u64
+    }
+
+    /// 推入新事件（写到队尾），队列已满返回 QueueFull
+    pub fn push(&mut self, event: Event) -> Result<u64, DexError> {
+        if self.is_full() {
+            return Err(DexError::QueueFull);
+        }
+        let slot = (self.head + self.count) % self.buf.len();
+        self.buf[slot] = Some(event);
+        self.count += 1;
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        Ok(seq)
+    }
+
+    /// 弹出队首事件（head 前进一格，释放空间）
+    pub fn pop_front(&mut self) -> Option<Event> {
+        if self.is_empty() {
+            return None;
+        }
+        let event = self.buf[self.head].take();
+        self.head = (self.head + 1) % self.buf.len();
+        self.count -= 1;
+        event
+    }
+
+    /// 按序号读取事件（已被弹出或尚未写入的序号返回None）
+    pub fn get(&self, seq: u64) -> Option<&Event> {
+        if seq < self.head_seq() || seq >= self.next_seq {
+            return None;
+        }
+        let offset = (seq - self.head_seq()) as usize;
+        self.buf[(self.head + offset) % self.buf.len()].as_ref()
+    }
+
+    /// 从队首到队尾遍历队列中的事件
+    pub fn iter(&self) -> impl Iterator<Item = &Event> + '_ {
+        (self.head_seq()..self.next_seq).filter_map(move |seq| self.get(seq))
+    }
+
+    /// 注册consumer，从当前队首开始消费；已注册则保持原消费指针
+    pub fn register_consumer(&mut self, consumer: &str) {
+        let head_seq = self.head_seq();
+        self.consumer_positions
+            .entry(consumer.to_string())
+            .or_insert(head_seq);
+    }
+
+    /// 注销consumer，之后它不再阻止事件被弹出
+    pub fn unregister_consumer(&mut self, consumer: &str) {
+        self.consumer_positions.remove(consumer);
+    }
+
+    /// 已注册consumer下一个要读取的事件序号（落后于队首时为队首序号），未注册返回None
+    pub fn consumer_position(&self, consumer: &str) -> Option<u64> {
+        self.consumer_positions
+            .get(consumer)
+            .map(|&pos| pos.max(self.head_seq()))
+    }
+
+    /// 把已注册consumer的消费指针推进到 seq（只前进不后退），未注册的consumer不做处理
+    pub fn advance_consumer(&mut self, consumer: &str, seq: u64) {
+        if let Some(pos) = self.consumer_positions.get_mut(consumer) {
+            *pos = (*pos).max(seq);
+        }
+    }
+
+    /// 低水位：所有已注册consumer中最小的消费指针（没有consumer时为None）
+    /// 序号小于低水位的事件已被所有consumer读过
+    pub fn low_watermark(&self) -> Option<u64> {
+        self.consumer_positions.values().copied().min()
+    }
+
+    /// 弹出所有已注册consumer都读过的事件，返回弹出的事件数
+    /// 没有已注册consumer时不弹出任何事件（无法确认事件已被处理）
+    pub fn pop_consumed(&mut self) -> usize {
+        let Some(low_watermark) = self.low_watermark() else {
+            return 0;
+        };
+        let mut popped = 0;
+        while self.head_seq() < low_watermark && self.pop_front().is_some() {
+            popped += 1;
+        }
+        popped
+    }
+
+    /// 消费者批量消费事件，返回未消费事件并推进消费指针
+    /// consumer: 消费者ID
+    /// max_events: 本次最多消费的事件数
+    /// 未注册的consumer只从队首读取，不记录消费指针（也就不会挡住事件弹出），需要持续消费的先 register_consumer
+    /// 若消费指针落后于队首（事件已被弹出），从队首继续消费
+    pub fn consume_events(&mut self, consumer: &str, max_events: usize) -> Vec<Event> {
+        let head_seq = self.head_seq();
+        let registered = self.consumer_positions.get(consumer).copied();
+        let mut pos = registered.unwrap_or(head_seq).max(head_seq);
+        let mut result = vec![];
+        while pos < self.next_seq && result.len() < max_events {
+            if let Some(event) = self.get(pos) {
+                result.push(event.clone());
+            }
+            pos += 1;
+        }
+        if registered.is_some() {
+            self.consumer_positions.insert(consumer.to_string(), pos);
+        }
+        result
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use crate::event_queue::{Event, EventType};
+
+/// 30天（秒），成交量档位默认的统计窗口
+pub const THIRTY_DAYS: u64 = 30 * 24 * 60 * 60;
+
+/// 手续费档位
+/// FeeTier is one row of the tier table in MarketConfig.
+/// 对齐 Serum 按 SRM/MSRM 持仓划分的费率档位：资格值（成交量或质押数量）达到 threshold 即可享受该档费率
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeTier {
+    /// 进入该档位所需的最低资格值
+    pub threshold: u64,
+    /// 该档位的 taker 手续费率（基点）
+    pub taker_fee_bps: u64,
+    /// 该档位的 maker 返佣率（基点）
+    pub maker_rebate_bps: u64,
+}
+
+/// 在档位表中查找资格值对应的档位：0 为基础费率，n 为档位表中第 n 档（表按 threshold 升序）
+pub fn tier_for(tiers: &[FeeTier], qualifier: u64) -> usize {
+    tiers
+        .iter()
+        .take_while(|t| t.threshold <= qualifier)
+        .count()
+}
+
+/// 手续费档位解析器（可插拔）
+/// FeeTierResolver maps a user to a fee tier.
+/// 市场撮合时用它决定 taker/maker 的档位，具体按成交量还是质押数量由实现决定
+pub trait FeeTierResolver: fmt::Debug {
+    /// 用户在 now 时刻的资格值（如30日成交量、质押数量）
+    fn qualifier(&self, user: &str, now: u64) -> u64;
+
+    /// 用户在 now 时刻所处的档位
+    fn tier(&self, user: &str, tiers: &[FeeTier], now: u64) -> usize {
+        tier_for(tiers, self.qualifier(user, now))
+    }
+
+    /// 每写入一条成交事件回调一次（notional 为成交金额），默认忽略
+    fn record_fill(&mut self, _event: &Event, _notional: u64) {}
+}
+
+/// 按滚动窗口成交量（报价币）划分档位，maker 和 taker 都计入成交量
+#[derive(Debug, Clone)]
+pub struct VolumeTierResolver {
+    /// 统计窗口（秒）
+    pub window: u64,
+    /// user -> 窗口内的 (成交时间, 成交金额)，按时间顺序
+    fills: HashMap<String, VecDeque<(u64, u64)>>,
+}
+
+impl Default for VolumeTierResolver {
+    fn default() -> Self {
+        Self::new(THIRTY_DAYS)
+    }
+}
+
+impl VolumeTierResolver {
+    /// 新建统计窗口为 window 秒的解析器
+    pub fn new(window: u64) -> Self {
+        Self {
+            window,
+            fills: HashMap::new(),
+        }
+    }
+
+    /// 用户在 (now - window, now] 内的成交量
+    pub fn volume(&self, user: &str, now: u64) -> u64 {
+        let since = now.saturating_sub(self.window);
+        self.fills
+            .get(user)
+            .map(|fills| {
+                fills
+                    .iter()
+                    .filter(|(ts, _)| *ts > since)
+                    .map(|(_, n)| n)
+                    .sum()
+            })
+            .unwrap_or(0)
+    }
+
+    fn add(&mut self, user: &str, timestamp: u64, notional: u64) {
+        let since = timestamp.saturating_sub(self.window);
+        let fills = self.fills.entry(user.to_string()).or_default();
+        while fills.front().map(|(ts, _)| *ts <= since).unwrap_or(false) {
+            fills.pop_front();
+        }
+        fills.push_back((timestamp, notional));
+    }
+}
+
+impl FeeTierResolver for VolumeTierResolver {
+    fn qualifier(&self, user: &str, now: u64) -> u64 {
+        self.volume(user, now)
+    }
+
+    fn record_fill(&mut self, event: &Event, notional: u64) {
+        if !matches!(event.event_type, EventType::Fill) {
+            return;
+        }
+        for user in [&event.maker, &event.taker].into_iter().flatten() {
+            self.add(user, event.timestamp, notional);
+        }
+    }
+}
+
+/// 按质押数量划分档位（对应 Serum 的 SRM/MSRM 持仓）
+#[derive(Debug, Clone, Default)]
+pub struct StakeTierResolver {
+    /// user -> 质押数量
+    pub stakes: HashMap<String, u64>,
+}
+
+impl StakeTierResolver {
+    /// 设置用户的质押数量
+    pub fn set_stake(&mut self, user: &str, amount: u64) {
+        self.stakes.insert(user.to_string(), amount);
+    }
+}
+
+impl FeeTierResolver for StakeTierResolver {
+    fn qualifier(&self, user: &str, _now: u64) -> u64 {
+        self.stakes.get(user).copied().unwrap_or(0)
+    }
+}
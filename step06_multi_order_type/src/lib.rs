@@ -0,0 +1,10 @@
+pub mod book_side;
+pub mod error;
+pub mod event_queue;
+pub mod fee_tier;
+pub mod market_config;
+pub mod math;
+pub mod open_orders;
+pub mod openbook;
+pub mod slab;
+pub mod wallet;
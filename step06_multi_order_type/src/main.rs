@@ -1,706 +1,14 @@
-use std::collections::{HashMap, VecDeque};
-
-/// 订单方向（买单/卖单）
-/// Side is order side (Bid/Ask)
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Side {
-    /// 买单（出价买入）
-    Bid,
-    /// 卖单（挂出卖出）
-    Ask,
-}
-
-/// 订单类型（撮合行为控制）
-/// 对齐 Serum DEX OrderType
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum OrderType {
-    /// 限价单（剩余可挂入订单簿，部分成交也允许）
-    Limit,
-    /// 市价单（只吃单，不入簿，能成交多少吃多少，其余自动取消）
-    Market,
-    /// IOC（立即成交否则取消，能成交多少就成交多少，其余立即取消，不入簿）
-    IOC,
-    /// FOK（全部成交否则全部取消，一笔不能全吃掉则全部撤销）
-    FOK,
-}
-
-/// 订单结构    
-#[derive(Debug, Clone)]
-pub struct Order {
-    /// 订单唯一ID
-    pub id: u64,
-    /// 持有者（用户名）
-    pub owner: String,
-    /// 订单方向（买/卖）
-    pub side: Side,
-    /// 挂单价格
-    pub price: u64,
-    /// 挂单数量
-    pub quantity: u64,
-    /// 订单过期时间戳（可选，Some(ts)则ts时刻后订单无效）
-    pub expire_ts: Option<u64>,
-    /// 订单类型
-    pub order_type: OrderType,
-}
-
-/// 用户余额信息
-#[derive(Debug, Default, Clone)]
-pub struct UserBalance {
-    /// 主币余额（如SOL/BTC/ETH等）
-    pub base: u64,
-    /// 报价币余额（如USDC/USDT等）
-    pub quote: u64,
-}
-
-/// 平台手续费归集账户
-#[derive(Debug, Default)]
-pub struct FeeReceiver {
-    /// 已累计收取的手续费（单位：报价币）
-    pub collected_fee: u64,
-}
-
-/// 事件类型枚举（撮合/撤单/过期）
-/// EventType describes the event kind in event queue.
-#[derive(Debug, Clone)]
-pub enum EventType {
-    /// 成交事件（订单被撮合成交）
-    Fill,
-    /// 撤单事件（用户撤销订单）
-    Cancel,
-    /// 过期事件（订单到期自动撤销）
-    Expire,
-}
-
-/// 事件队列中每条事件结构
-#[derive(Debug, Clone)]
-pub struct Event {
-    /// 事件类型（成交/撤单/过期）
-    pub event_type: EventType,
-    /// 所属市场名（如 "SOL/USDC"）
-    pub market: String,
-    /// maker账户（撮合中的被动方，部分事件可为None）
-    pub maker: Option<String>,
-    /// taker账户（撮合中的主动方，部分事件可为None）
-    pub taker: Option<String>,
-    /// 成交价格（部分事件可为None）
-    pub price: Option<u64>,
-    /// 成交数量
-    pub quantity: u64,
-    /// 手续费（单位：报价币）
-    pub fee: u64,
-    /// 订单ID
-    pub order_id: u64,
-    /// 事件发生的时间戳
-    pub timestamp: u64,
-}
-
-/// 市场事件队列
-#[derive(Debug, Default)]
-pub struct EventQueue {
-    /// 事件列表（先进先出队列）
-    pub events: VecDeque<Event>,
-    /// 下一个事件序号（用于分配事件序号、便于指针管理）
-    pub next_seq: u64,
-    /// 每个consumer（如crank/前端）消费指针，记录该consumer已消费到第几个事件
-    pub consumer_positions: HashMap<String, u64>,
-}
-
-impl EventQueue {
-    /// 推入新事件
-    pub fn push(&mut self, event: Event) {
-        self.events.push_back(event);
-        self.next_seq += 1;
-    }
-
-    /// 消费者批量消费事件，返回未消费事件并推进消费指针
-    /// consumer: 消费者ID
-    /// max_events: 本次最多消费的事件数
-    pub fn consume_events(&mut self, consumer: &str, max_events: usize) -> Vec<Event> {
-        let last_pos = self
-            .consumer_positions
-            .entry(consumer.to_string())
-            .or_insert(0);
-        let mut result = vec![];
-        let total_events = self.events.len() as u64;
-        let mut cnt = 0;
-        while *last_pos < total_events && cnt < max_events {
-            let idx = *last_pos as usize;
-            if idx < self.events.len() {
-                result.push(self.events[idx].clone());
-                *last_pos += 1;
-                cnt += 1;
-            } else {
-                break;
-            }
-        }
-        result
-    }
-}
-
-/// 单一市场状态
-#[derive(Debug, Default)]
-pub struct MarketState {
-    /// 买单簿（降序按价格排列，价格高优先）
-    pub bids: Vec<Order>,
-    /// 卖单簿（升序按价格排列，价格低优先）
-    pub asks: Vec<Order>,
-    /// 下一个订单号（自增ID）
-    pub next_order_id: u64,
-    /// 用户余额表
-    pub balances: HashMap<String, UserBalance>,
-    /// 平台手续费账户
-    pub fee_receiver: FeeReceiver,
-    /// 事件队列
-    pub event_queue: EventQueue,
-}
-
-impl MarketState {
-    /// 用户充值
-    pub fn deposit(&mut self, user: &str, base: u64, quote: u64) {
-        let bal = self.balances.entry(user.to_string()).or_default();
-        bal.base += base;
-        bal.quote += quote;
-        println!(
-            "用户 {} 在本市场充值：主币 {}，报价币 {}",
-            user, base, quote
-        );
-    }
-
-    /// 清理所有已过期订单
-    pub fn clean_expired_orders(&mut self, now: u64, market: &str) {
-        // 买单
-        self.bids.retain(|o| {
-            let expired = o.expire_ts.map(|ts| ts <= now).unwrap_or(false);
-            if expired {
-                let refund = o.price * o.quantity;
-                self.balances.get_mut(&o.owner).unwrap().quote += refund;
-                self.event_queue.push(Event {
-                    event_type: EventType::Expire,
-                    market: market.to_string(),
-                    maker: None,
-                    taker: Some(o.owner.clone()),
-                    price: Some(o.price),
-                    quantity: o.quantity,
-                    fee: 0,
-                    order_id: o.id,
-                    timestamp: now,
-                });
-            }
-            !expired
-        });
-        // 卖单
-        self.asks.retain(|o| {
-            let expired = o.expire_ts.map(|ts| ts <= now).unwrap_or(false);
-            if expired {
-                self.balances.get_mut(&o.owner).unwrap().base += o.quantity;
-                self.event_queue.push(Event {
-                    event_type: EventType::Expire,
-                    market: market.to_string(),
-                    maker: None,
-                    taker: Some(o.owner.clone()),
-                    price: Some(o.price),
-                    quantity: o.quantity,
-                    fee: 0,
-                    order_id: o.id,
-                    timestamp: now,
-                });
-            }
-            !expired
-        });
-    }
-
-    /// 下单（挂入订单簿或直接撮合，支持订单有效期和自动清理过期订单）
-    pub fn place_order(
-        &mut self,
-        market: &str,
-        owner: &str,
-        side: Side,
-        price: u64,
-        mut quantity: u64,
-        now: u64,
-        fee_bps: u64,
-        expire_ts: Option<u64>,
-        order_type: OrderType,
-    ) -> Option<u64> {
-        self.clean_expired_orders(now, market);
-
-        // 校验余额
-        let bal = self.balances.entry(owner.to_string()).or_default();
-        match side {
-            Side::Bid => {
-                let needed_quote = price * quantity;
-                if bal.quote < needed_quote {
-                    println!("下单失败，用户 {} 报价币余额不足", owner);
-                    return None;
-                }
-                bal.quote -= needed_quote;
-            }
-            Side::Ask => {
-                if bal.base < quantity {
-                    println!("下单失败，用户 {} 主币余额不足", owner);
-                    return None;
-                }
-                bal.base -= quantity;
-            }
-        }
-
-        // 构造订单
-        let order_id = self.next_order_id;
-        self.next_order_id += 1;
-
-        let mut order = Order {
-            id: order_id,
-            owner: owner.to_string(),
-            side: side.clone(),
-            price,
-            quantity,
-            expire_ts,
-            order_type: order_type.clone(),
-        };
-
-        let mut filled = 0;
-        let mut total_fee = 0;
-        let mut fully_filled = false;
-
-        // 撮合逻辑
-        match side {
-            Side::Bid => {
-                // 1. FOK 先模拟能否完全成交
-                if matches!(order_type, OrderType::FOK) {
-                    let mut remain = quantity;
-                    for a in &self.asks {
-                        if order.price >= a.price {
-                            remain = remain.saturating_sub(a.quantity);
-                            if remain == 0 {
-                                break;
-                            }
-                        }
-                    }
-                    if remain > 0 {
-                        // 全部无法成交，订单撤销并退款
-                        bal.quote += price * quantity;
-                        println!("FOK买单无法全部成交，直接撤销");
-                        return None;
-                    }
-                }
-                // 2. Market单重置价格为最大（吃到所有可成交为止）
-                let exec_price = if matches!(order_type, OrderType::Market) {
-                    u64::MAX
-                } else {
-                    price
-                };
-                while let Some(mut best_ask) = self.asks.first().cloned() {
-                    if order.price >= best_ask.price && order.quantity > 0 {
-                        let deal_qty = order.quantity.min(best_ask.quantity);
-                        let deal_price = best_ask.price;
-                        let fee = deal_price * deal_qty * fee_bps / 10_000;
-                        self.fee_receiver.collected_fee += fee;
-
-                        // 买家获得主币，卖家获得报价币（扣除手续费）
-                        self.balances.get_mut(&order.owner).unwrap().base += deal_qty;
-                        self.balances.get_mut(&best_ask.owner).unwrap().quote +=
-                            deal_price * deal_qty - fee;
-
-                        self.event_queue.push(Event {
-                            event_type: EventType::Fill,
-                            market: market.to_string(),
-                            maker: Some(best_ask.owner.clone()),
-                            taker: Some(order.owner.clone()),
-                            price: Some(deal_price),
-                            quantity: deal_qty,
-                            fee,
-                            order_id: order.id,
-                            timestamp: now,
-                        });
-
-                        order.quantity -= deal_qty;
-                        filled += deal_qty;
-                        total_fee += fee;
-                        if let Some(b0) = self.asks.first_mut() {
-                            b0.quantity -= deal_qty;
-                        }
-                        if self.asks.first().map(|b| b.quantity == 0).unwrap_or(false) {
-                            self.asks.remove(0);
-                        }
-                    } else {
-                        break;
-                    }
-                }
-
-                fully_filled = order.quantity == 0;
-                // 3. 剩余逻辑
-                match order_type {
-                    OrderType::Limit => {
-                        if order.quantity > 0 {
-                            let refund = order.price * order.quantity;
-                            self.balances.get_mut(&order.owner).unwrap().quote += refund;
-                            self.bids.push(order.clone());
-                            self.bids.sort_by(|a, b| b.price.cmp(&a.price));
-                            println!("限价买单未完全成交，剩余 {} 入簿", order.quantity);
-                        }
-                    }
-                    OrderType::Market | OrderType::IOC => {
-                        if order.quantity > 0 {
-                            let refund = price * order.quantity;
-                            self.balances.get_mut(&order.owner).unwrap().quote += refund;
-                            println!("市价/IOC买单未完全成交，剩余 {} 自动撤销", order.quantity);
-                        }
-                    }
-                    OrderType::FOK => {
-                        if !fully_filled {
-                            // 回滚所有成交（简化版直接退款）
-                            self.balances.get_mut(&order.owner).unwrap().base -= filled;
-                            self.balances.get_mut(&order.owner).unwrap().quote += price * quantity;
-                            println!("FOK买单未完全成交，全部撤销");
-                            return None;
-                        }
-                    }
-                }
-            }
-            Side::Ask => {
-                // 1. FOK 先模拟能否完全成交
-                if matches!(order_type, OrderType::FOK) {
-                    let mut remain = quantity;
-                    for b in &self.bids {
-                        if order.price <= b.price {
-                            remain = remain.saturating_sub(b.quantity);
-                            if remain == 0 {
-                                break;
-                            }
-                        }
-                    }
-                    if remain > 0 {
-                        bal.base += quantity;
-                        println!("FOK卖单无法全部成交，直接撤销");
-                        return None;
-                    }
-                }
-                // 2. Market单重置价格为最小
-                let exec_price = if matches!(order_type, OrderType::Market) {
-                    0
-                } else {
-                    price
-                };
-                while let Some(mut best_bid) = self.bids.first().cloned() {
-                    if order.price <= best_bid.price && order.quantity > 0 {
-                        let deal_qty = order.quantity.min(best_bid.quantity);
-                        let deal_price = best_bid.price;
-                        let fee = deal_price * deal_qty * fee_bps / 10_000;
-                        self.fee_receiver.collected_fee += fee;
-
-                        // 卖家获得报价币（扣手续费），买家获得主币
-                        self.balances.get_mut(&order.owner).unwrap().quote +=
-                            deal_price * deal_qty - fee;
-                        self.balances.get_mut(&best_bid.owner).unwrap().base += deal_qty;
-
-                        self.event_queue.push(Event {
-                            event_type: EventType::Fill,
-                            market: market.to_string(),
-                            maker: Some(best_bid.owner.clone()),
-                            taker: Some(order.owner.clone()),
-                            price: Some(deal_price),
-                            quantity: deal_qty,
-                            fee,
-                            order_id: order.id,
-                            timestamp: now,
-                        });
-
-                        order.quantity -= deal_qty;
-                        filled += deal_qty;
-                        total_fee += fee;
-                        if let Some(b0) = self.bids.first_mut() {
-                            b0.quantity -= deal_qty;
-                        }
-                        if self.bids.first().map(|b| b.quantity == 0).unwrap_or(false) {
-                            self.bids.remove(0);
-                        }
-                    } else {
-                        break;
-                    }
-                }
-                // 剩余未成交部分挂入订单簿
-                fully_filled = order.quantity == 0;
-                match order_type {
-                    OrderType::Limit => {
-                        if order.quantity > 0 {
-                            self.balances.get_mut(&order.owner).unwrap().base += order.quantity;
-                            self.asks.push(order.clone());
-                            self.asks.sort_by(|a, b| a.price.cmp(&b.price));
-                            println!("限价卖单未完全成交，剩余 {} 入簿", order.quantity);
-                        }
-                    }
-                    OrderType::Market | OrderType::IOC => {
-                        if order.quantity > 0 {
-                            self.balances.get_mut(&order.owner).unwrap().base += order.quantity;
-                            println!("市价/IOC卖单未完全成交，剩余 {} 自动撤销", order.quantity);
-                        }
-                    }
-                    OrderType::FOK => {
-                        if !fully_filled {
-                            self.balances.get_mut(&order.owner).unwrap().quote -= filled * price;
-                            self.balances.get_mut(&order.owner).unwrap().base += quantity;
-                            println!("FOK卖单未完全成交，全部撤销");
-                            return None;
-                        }
-                    }
-                }
-            }
-        }
-        Some(order_id)
-    }
-
-    /// 批量撮合（对前 n 个订单尝试撮合）
-    /// side: 批量撮合哪一侧（Bid/Ask）
-    /// n: 前n个订单
-    pub fn batch_match(
-        &mut self,
-        market: &str,
-        side: Side,
-        n: usize,
-        now: u64,
-        fee_bps: u64,
-        order_type: OrderType,
-    ) {
-        self.clean_expired_orders(now, market);
-        match side {
-            Side::Bid => {
-                let bids = self.bids.clone();
-                for order in bids.iter().take(n) {
-                    self.place_order(
-                        market,
-                        &order.owner,
-                        Side::Bid,
-                        order.price,
-                        order.quantity,
-                        now,
-                        fee_bps,
-                        order.expire_ts,
-                        order_type.clone(),
-                    );
-                }
-            }
-            Side::Ask => {
-                let asks = self.asks.clone();
-                for order in asks.iter().take(n) {
-                    self.place_order(
-                        market,
-                        &order.owner,
-                        Side::Ask,
-                        order.price,
-                        order.quantity,
-                        now,
-                        fee_bps,
-                        order.expire_ts,
-                        order_type.clone(),
-                    );
-                }
-            }
-        }
-    }
-
-    /// 批量撤销指定用户的订单
-    /// ids: 要撤销的订单id列表
-    pub fn batch_cancel(&mut self, market: &str, user: &str, ids: &[u64], now: u64) {
-        let mut cancel_ids: Vec<u64> = ids.to_vec();
-        // 买单
-        self.bids.retain(|o| {
-            if o.owner == user && cancel_ids.contains(&o.id) {
-                let refund = o.price * o.quantity;
-                self.balances.get_mut(user).unwrap().quote += refund;
-                self.event_queue.push(Event {
-                    event_type: EventType::Cancel,
-                    market: market.to_string(),
-                    maker: None,
-                    taker: Some(user.to_string()),
-                    price: Some(o.price),
-                    quantity: o.quantity,
-                    fee: 0,
-                    order_id: o.id,
-                    timestamp: now,
-                });
-                false
-            } else {
-                true
-            }
-        });
-        // 卖单
-        self.asks.retain(|o| {
-            if o.owner == user && cancel_ids.contains(&o.id) {
-                self.balances.get_mut(user).unwrap().base += o.quantity;
-                self.event_queue.push(Event {
-                    event_type: EventType::Cancel,
-                    market: market.to_string(),
-                    maker: None,
-                    taker: Some(user.to_string()),
-                    price: Some(o.price),
-                    quantity: o.quantity,
-                    fee: 0,
-                    order_id: o.id,
-                    timestamp: now,
-                });
-                false
-            } else {
-                true
-            }
-        });
-    }
-
-    /// 打印订单簿
-    pub fn print_book(&self) {
-        println!("买单簿: {:?}", self.bids);
-        println!("卖单簿: {:?}", self.asks);
-    }
-
-    /// 打印所有用户余额
-    pub fn print_balances(&self) {
-        for (user, bal) in &self.balances {
-            println!("用户 {} 主币:{} 报价币:{}", user, bal.base, bal.quote);
-        }
-    }
-
-    /// 打印平台手续费余额
-    pub fn print_fee_receiver(&self) {
-        println!(
-            "平台累计收取手续费(报价币): {}",
-            self.fee_receiver.collected_fee
-        );
-    }
-
-    /// 打印事件队列
-    pub fn print_events(&self) {
-        println!("=== Event Queue（成交/撤单/过期历史）===");
-        for event in &self.event_queue.events {
-            println!("{:?}", event);
-        }
-    }
-
-    /// 打印某consumer批量消费到的事件
-    pub fn print_event_consume(&mut self, consumer: &str, max_events: usize) {
-        let events = self.event_queue.consume_events(consumer, max_events);
-        println!("=== {} 消费到的事件 ===", consumer);
-        for event in events {
-            println!("{:?}", event);
-        }
-    }
-}
-
-/// 多市场管理器
-pub struct Markets {
-    /// 市场状态集合（market name -> MarketState）
-    pub markets: HashMap<String, MarketState>,
-}
-
-impl Markets {
-    /// 新建Markets实例
-    pub fn new() -> Self {
-        Self {
-            markets: HashMap::new(),
-        }
-    }
-
-    /// 新建市场
-    pub fn create_market(&mut self, market: &str) {
-        self.markets
-            .entry(market.to_string())
-            .or_insert_with(MarketState::default);
-        println!("新市场已创建: {}", market);
-    }
-
-    /// 用户充值
-    pub fn deposit(&mut self, market: &str, user: &str, base: u64, quote: u64) {
-        if let Some(state) = self.markets.get_mut(market) {
-            state.deposit(user, base, quote);
-        } else {
-            println!("市场 {} 不存在", market);
-        }
-    }
-
-    /// 下单
-    pub fn place_order(
-        &mut self,
-        market: &str,
-        owner: &str,
-        side: Side,
-        price: u64,
-        quantity: u64,
-        now: u64,
-        fee_bps: u64,
-        expire_ts: Option<u64>,
-        order_type: OrderType,
-    ) -> Option<u64> {
-        if let Some(state) = self.markets.get_mut(market) {
-            state.place_order(
-                market, owner, side, price, quantity, now, fee_bps, expire_ts, order_type,
-            )
-        } else {
-            println!("市场 {} 不存在", market);
-            None
-        }
-    }
-
-    /// 批量撮合
-    pub fn batch_match(
-        &mut self,
-        market: &str,
-        side: Side,
-        n: usize,
-        now: u64,
-        fee_bps: u64,
-        order_type: OrderType,
-    ) {
-        if let Some(state) = self.markets.get_mut(market) {
-            state.batch_match(market, side, n, now, fee_bps, order_type);
-        }
-    }
-
-    /// 批量撤销
-    pub fn batch_cancel(&mut self, market: &str, user: &str, ids: &[u64], now: u64) {
-        if let Some(state) = self.markets.get_mut(market) {
-            state.batch_cancel(market, user, ids, now);
-        }
-    }
-
-    /// 打印市场订单簿
-    pub fn print_market_book(&self, market: &str) {
-        if let Some(state) = self.markets.get(market) {
-            println!("=== 市场 {} 订单簿 ===", market);
-            state.print_book();
-        } else {
-            println!("市场 {} 不存在", market);
-        }
-    }
-
-    /// 打印市场余额
-    pub fn print_market_balances(&self, market: &str) {
-        if let Some(state) = self.markets.get(market) {
-            println!("=== 市场 {} 用户余额 ===", market);
-            state.print_balances();
-        } else {
-            println!("市场 {} 不存在", market);
-        }
-    }
-
-    /// 打印市场手续费池
-    pub fn print_market_fee_receiver(&self, market: &str) {
-        if let Some(state) = self.markets.get(market) {
-            println!("=== 市场 {} 平台手续费 ===", market);
-            state.print_fee_receiver();
-        }
-    }
-
-    /// 打印市场事件队列
-    pub fn print_market_events(&self, market: &str) {
-        if let Some(state) = self.markets.get(market) {
-            println!("=== 市场 {} Event Queue ===", market);
-            state.print_events();
-        }
-    }
-
-    /// 打印市场中某consumer批量消费到的事件
-    pub fn print_market_event_consume(&mut self, market: &str, consumer: &str, max_events: usize) {
-        if let Some(state) = self.markets.get_mut(market) {
-            state.print_event_consume(consumer, max_events);
-        }
+use step06_multi_order_type::error::DexError;
+use step06_multi_order_type::market_config::MarketConfig;
+use step06_multi_order_type::openbook::{
+    Markets, OrderType, PlaceOrderOutcome, SelfTradeBehavior, SettlementMode, Side,
+};
+
+/// 打印下单结果
+fn report(result: Result<PlaceOrderOutcome, DexError>) {
+    match result {
+        Ok(outcome) => println!("下单成功: {:?}", outcome),
+        Err(err) => println!("下单失败: {}", err),
     }
 }
 
@@ -711,105 +19,222 @@ impl Markets {
  * 3. IOC单只能吃部分，剩余立即撤销。
  * 4. FOK单，只有能全部成交才真正成交，否则全撤销。
  * 5. 批量撮合：批量以不同 ordertype（如批量 Market、批量 FOK）操作。
+ * 6. 延迟结算：撮合只给 taker 记账，maker 由 crank 消费事件后入账。
+ * 7. 自成交防护：taker 遇到自己的挂单时按 SelfTradeBehavior 处理。
  */
 fn main() {
     let mut markets = Markets::new();
-    let fee_bps = 30; // 0.3%
-    let mut now = 1_000_000_000u64;
-
-    markets.create_market("SOL/USDC");
-    markets.deposit("SOL/USDC", "Alice", 100, 2000);
-    markets.deposit("SOL/USDC", "Bob", 50, 1000);
+    let now = 1_000_000_000u64;
+
+    // taker 手续费 0.3%，maker 返佣 0.1%，推荐人分得 taker 手续费的 20%
+    let config = MarketConfig {
+        taker_fee_bps: 30,
+        maker_rebate_bps: 10,
+        referrer_fee_share_bps: 2_000,
+        authority: Some("Admin".to_string()),
+        ..MarketConfig::new("SOL", "USDC")
+    };
+    markets.create_market("SOL/USDC", config).unwrap();
+    markets.deposit("SOL/USDC", "Alice", 100, 2000).unwrap();
+    markets.deposit("SOL/USDC", "Bob", 50, 1000).unwrap();
 
     // 限价单
-    let _ = markets.place_order(
+    report(markets.place_order(
         "SOL/USDC",
         "Alice",
         Side::Bid,
         10,
         10,
         now,
-        fee_bps,
         Some(now + 10),
         OrderType::Limit,
-    );
-    let _ = markets.place_order(
+        0,
+        SelfTradeBehavior::DecrementTake,
+        None,
+    ));
+    report(markets.place_order(
         "SOL/USDC",
         "Bob",
         Side::Ask,
         10,
         5,
         now + 1,
-        fee_bps,
         Some(now + 20),
         OrderType::Limit,
-    );
+        0,
+        SelfTradeBehavior::DecrementTake,
+        Some("Carol"),
+    ));
 
-    // 市价单
-    let _ = markets.place_order(
+    // 市价单（买单的数量为报价币预算，含手续费）
+    report(markets.place_order(
         "SOL/USDC",
         "Alice",
         Side::Bid,
         0,
-        6,
+        60,
         now + 2,
-        fee_bps,
         None,
         OrderType::Market,
-    );
+        0,
+        SelfTradeBehavior::DecrementTake,
+        None,
+    ));
 
     // IOC单
-    let _ = markets.place_order(
+    report(markets.place_order(
         "SOL/USDC",
         "Bob",
         Side::Ask,
         10,
         10,
         now + 3,
-        fee_bps,
         None,
         OrderType::IOC,
-    );
+        0,
+        SelfTradeBehavior::DecrementTake,
+        None,
+    ));
 
     // FOK单：买单无法全部成交
-    let _ = markets.place_order(
+    report(markets.place_order(
         "SOL/USDC",
         "Alice",
         Side::Bid,
         10,
         100,
         now + 4,
-        fee_bps,
         None,
         OrderType::FOK,
-    );
+        0,
+        SelfTradeBehavior::DecrementTake,
+        None,
+    ));
 
     // FOK单：卖单可以全部成交
-    let _ = markets.place_order(
+    report(markets.place_order(
         "SOL/USDC",
         "Bob",
         Side::Ask,
         10,
         5,
         now + 5,
-        fee_bps,
         None,
         OrderType::FOK,
-    );
+        0,
+        SelfTradeBehavior::DecrementTake,
+        None,
+    ));
 
     // 批量撮合（以Market类型批量撮合前2个挂单）
-    markets.batch_match(
-        "SOL/USDC",
-        Side::Bid,
-        2,
-        now + 6,
-        fee_bps,
-        OrderType::Market,
-    );
+    let results = markets
+        .batch_match("SOL/USDC", Side::Bid, 2, now + 6, OrderType::Market)
+        .unwrap();
+    for result in results {
+        report(result);
+    }
 
     // 查看订单簿、余额、事件队列
     markets.print_market_book("SOL/USDC");
     markets.print_market_balances("SOL/USDC");
-    markets.print_market_fee_receiver("SOL/USDC");
+    // 自成交防护：Alice 的卖单会吃到自己的买单，AbortTransaction 直接拒绝
+    report(markets.place_order(
+        "SOL/USDC",
+        "Alice",
+        Side::Bid,
+        9,
+        2,
+        now + 5,
+        None,
+        OrderType::Limit,
+        1001,
+        SelfTradeBehavior::DecrementTake,
+        None,
+    ));
+    report(markets.place_order(
+        "SOL/USDC",
+        "Alice",
+        Side::Ask,
+        9,
+        1,
+        now + 5,
+        None,
+        OrderType::IOC,
+        0,
+        SelfTradeBehavior::AbortTransaction,
+        None,
+    ));
+    // 按客户端订单ID撤销 Alice 的买单
+    match markets.cancel_order_by_client_id("SOL/USDC", "Alice", 1001, now + 5) {
+        Ok(order_id) => println!("按客户端订单ID 1001 撤销订单 {}", order_id),
+        Err(err) => println!("撤单失败: {}", err),
+    }
+    markets.print_market_fee_receiver("SOL/USDC").unwrap();
+    println!(
+        "推荐人 Carol 累计分成: {}",
+        markets.referrer_rebates("SOL/USDC", "Carol").unwrap()
+    );
     markets.print_market_events("SOL/USDC");
+
+    // 延迟结算市场：Bob（maker）的报价币要等 crank 处理成交事件后才入账
+    // 钱包按币种跨市场共用：Bob 在 SOL/USDC 充值的 SOL 可以直接在 SOL/USDT 卖出
+    let config = MarketConfig {
+        settlement_mode: SettlementMode::Deferred,
+        ..MarketConfig::new("SOL", "USDT")
+    };
+    markets.create_market("SOL/USDT", config).unwrap();
+    markets.deposit_token("Alice", "USDT", 2000).unwrap();
+    report(markets.place_order(
+        "SOL/USDT",
+        "Bob",
+        Side::Ask,
+        10,
+        5,
+        now,
+        None,
+        OrderType::Limit,
+        0,
+        SelfTradeBehavior::DecrementTake,
+        None,
+    ));
+    report(markets.place_order(
+        "SOL/USDT",
+        "Alice",
+        Side::Bid,
+        10,
+        5,
+        now + 1,
+        None,
+        OrderType::IOC,
+        0,
+        SelfTradeBehavior::DecrementTake,
+        None,
+    ));
+    markets.print_market_balances("SOL/USDT");
+    let processed = markets.crank("SOL/USDT", 10).unwrap();
+    println!("crank 处理了 {} 条事件", processed);
+    markets.print_market_balances("SOL/USDT");
+
+    // 结算：挂单账户中的可用资金转回钱包
+    markets.settle_funds("SOL/USDT", "Alice").unwrap();
+    markets.settle_funds("SOL/USDT", "Bob").unwrap();
+    markets.print_market_balances("SOL/USDT");
+
+    // 提现：超过可用余额会被拒绝
+    for quote in [60, 50] {
+        if let Err(err) = markets.withdraw("SOL/USDT", "Bob", 0, quote, now + 2) {
+            println!("提现失败: {}", err);
+        }
+    }
+    println!(
+        "提现记录: {:?}",
+        markets.market("SOL/USDT").unwrap().withdrawals
+    );
+
+    // 管理员提取手续费，并查看跨市场手续费报表
+    if let Err(err) = markets.sweep_fees("SOL/USDC", "Bob", now + 3) {
+        println!("提取手续费失败: {}", err);
+    }
+    markets.sweep_fees("SOL/USDC", "Admin", now + 3).unwrap();
+    markets.print_fee_report().unwrap();
 }
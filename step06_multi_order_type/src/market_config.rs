@@ -0,0 +1,241 @@
+use crate::error::DexError;
+use crate::fee_tier::FeeTier;
+use crate::math;
+use crate::openbook::SettlementMode;
+
+/// 市场配置（创建市场时确定，运行中不可修改）
+/// MarketConfig describes the tokens and granularity of a market.
+/// 对齐 Serum DEX 的 base_lot_size / quote_lot_size / tick size：
+/// - 下单数量必须是 base_lot_size 的整数倍
+/// - 下单价格必须是 tick_size 的整数倍
+/// - 价格（每单位主币的报价币）换算成 lots 后为"每个 base lot 值多少个 quote lot"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketConfig {
+    /// 主币币种（如SOL/BTC/ETH等）
+    pub base_mint: String,
+    /// 报价币币种（如USDC/USDT等）
+    pub quote_mint: String,
+    /// 每个 base lot 包含的主币原生单位数
+    pub base_lot_size: u64,
+    /// 每个 quote lot 包含的报价币原生单位数
+    pub quote_lot_size: u64,
+    /// 最小价格变动单位（原生价格）
+    pub tick_size: u64,
+    /// taker 手续费率（基点，1 = 0.01%）
+    pub taker_fee_bps: u64,
+    /// maker 返佣率（基点，不能超过 taker 手续费率）
+    pub maker_rebate_bps: u64,
+    /// 手续费档位表（按 threshold 升序），档位由市场的 FeeTierResolver 决定；为空时所有用户都用基础费率
+    pub fee_tiers: Vec<FeeTier>,
+    /// 推荐人分成比例（基点，占 taker 手续费的比例，10_000 = 全部手续费）
+    pub referrer_fee_share_bps: u64,
+    /// 结算模式
+    pub settlement_mode: SettlementMode,
+    /// 市场管理员（唯一可以提取手续费的账户，None 时任何人都不能提取）
+    pub authority: Option<String>,
+    /// 订单簿存储：None 为价格档位，Some(n) 为每一侧最多容纳 n 个挂单的 critbit slab
+    pub slab_capacity: Option<usize>,
+}
+
+impl Default for MarketConfig {
+    fn default() -> Self {
+        Self::new("BASE", "QUOTE")
+    }
+}
+
+impl MarketConfig {
+    /// 新建交易 base_mint/quote_mint 的市场配置（lot 和 tick 均为1，无手续费，即时结算）
+    pub fn new(base_mint: &str, quote_mint: &str) -> Self {
+        Self {
+            base_mint: base_mint.to_string(),
+            quote_mint: quote_mint.to_string(),
+            base_lot_size: 1,
+            quote_lot_size: 1,
+            tick_size: 1,
+            taker_fee_bps: 0,
+            maker_rebate_bps: 0,
+            fee_tiers: Vec::new(),
+            referrer_fee_share_bps: 0,
+            settlement_mode: SettlementMode::Immediate,
+            authority: None,
+            slab_capacity: None,
+        }
+    }
+
+    /// 校验配置：lot/tick 不能为0，且每个 tick 必须能整数换算成 quote lot；
+    /// 任一档位的 maker 返佣加推荐人分成不能超过任一档位的 taker 手续费（平台不能倒贴）；档位表 threshold 严格升序
+    pub fn validate(&self) -> Result<(), DexError> {
+        if self.base_mint == self.quote_mint {
+            return Err(DexError::InvalidMarketConfig(format!(
+                "主币和报价币不能相同: {}",
+                self.base_mint
+            )));
+        }
+        if self.slab_capacity == Some(0) {
+            return Err(DexError::InvalidMarketConfig(
+                "slab_capacity 不能为0".to_string(),
+            ));
+        }
+        if self.base_lot_size == 0 || self.quote_lot_size == 0 || self.tick_size == 0 {
+            return Err(DexError::InvalidMarketConfig(
+                "base_lot_size / quote_lot_size / tick_size 不能为0".to_string(),
+            ));
+        }
+        if !(self.tick_size as u128 * self.base_lot_size as u128)
+            .is_multiple_of(self.quote_lot_size as u128)
+        {
+            return Err(DexError::InvalidMarketConfig(format!(
+                "tick_size({}) × base_lot_size({}) 必须是 quote_lot_size({}) 的整数倍",
+                self.tick_size, self.base_lot_size, self.quote_lot_size
+            )));
+        }
+        if !self
+            .fee_tiers
+            .windows(2)
+            .all(|w| w[0].threshold < w[1].threshold)
+        {
+            return Err(DexError::InvalidMarketConfig(
+                "手续费档位表必须按 threshold 严格升序".to_string(),
+            ));
+        }
+        if self.referrer_fee_share_bps > 10_000 {
+            return Err(DexError::InvalidMarketConfig(format!(
+                "推荐人分成比例不能超过 10000 bps: {}",
+                self.referrer_fee_share_bps
+            )));
+        }
+        let rates: Vec<(u64, u64)> = (0..=self.fee_tiers.len())
+            .map(|tier| self.fee_rates(tier))
+            .collect();
+        let max_taker = rates.iter().map(|r| r.0).max().unwrap_or(0);
+        let min_taker = rates.iter().map(|r| r.0).min().unwrap_or(0);
+        let max_rebate = rates.iter().map(|r| r.1).max().unwrap_or(0);
+        if max_taker > 10_000
+            || max_rebate as u128 * 10_000
+                > min_taker as u128 * (10_000 - self.referrer_fee_share_bps) as u128
+        {
+            return Err(DexError::InvalidMarketConfig(format!(
+                "手续费率非法：taker 最低 {} bps，maker 返佣最高 {} bps，推荐人分成 {} bps",
+                min_taker, max_rebate, self.referrer_fee_share_bps
+            )));
+        }
+        Ok(())
+    }
+
+    /// 校验订单：数量必须是 base_lot_size 的整数倍，价格必须是 tick_size 的整数倍
+    pub fn validate_order(&self, price: u64, quantity: u64) -> Result<(), DexError> {
+        if !quantity.is_multiple_of(self.base_lot_size) {
+            return Err(DexError::InvalidLotSize {
+                quantity,
+                lot_size: self.base_lot_size,
+            });
+        }
+        if !price.is_multiple_of(self.tick_size) {
+            return Err(DexError::InvalidTickSize {
+                price,
+                tick_size: self.tick_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// 主币原生数量 -> base lots
+    pub fn base_lots(&self, quantity: u64) -> u64 {
+        quantity / self.base_lot_size
+    }
+
+    /// base lots -> 主币原生数量
+    pub fn base_native(&self, lots: u64) -> Result<u64, DexError> {
+        math::mul(lots, self.base_lot_size)
+    }
+
+    /// 报价币原生数量 -> quote lots
+    pub fn quote_lots(&self, amount: u64) -> u64 {
+        amount / self.quote_lot_size
+    }
+
+    /// quote lots -> 报价币原生数量
+    pub fn quote_native(&self, lots: u64) -> Result<u64, DexError> {
+        math::mul(lots, self.quote_lot_size)
+    }
+
+    /// 原生价格 -> lots 价格（每个 base lot 值多少个 quote lot）
+    pub fn price_lots(&self, price: u64) -> Result<u64, DexError> {
+        math::mul_div(price, self.base_lot_size, self.quote_lot_size)
+    }
+
+    /// lots 价格 -> 原生价格
+    pub fn price_native(&self, price_lots: u64) -> Result<u64, DexError> {
+        math::mul_div(price_lots, self.quote_lot_size, self.base_lot_size)
+    }
+
+    /// 按 price 成交 quantity 主币的金额（报价币原生单位）
+    /// = 价格(lots) × 数量(lots) × quote_lot_size，撮合冻结、成交、退款和手续费都以它为准；超出 u64 返回 Overflow
+    pub fn notional(&self, price: u64, quantity: u64) -> Result<u64, DexError> {
+        self.quote_native(math::mul(
+            self.price_lots(price)?,
+            self.base_lots(quantity),
+        )?)
+    }
+
+    /// 档位 tier 的 (taker 手续费率, maker 返佣率)：0 为基础费率，超出档位表时取最高档
+    pub fn fee_rates(&self, tier: usize) -> (u64, u64) {
+        match tier.min(self.fee_tiers.len()) {
+            0 => (self.taker_fee_bps, self.maker_rebate_bps),
+            n => {
+                let t = &self.fee_tiers[n - 1];
+                (t.taker_fee_bps, t.maker_rebate_bps)
+            }
+        }
+    }
+
+    /// 档位为 tier 的 taker 成交金额为 notional 时支付的手续费
+    pub fn taker_fee(&self, notional: u64, tier: usize) -> Result<u64, DexError> {
+        math::bps(notional, self.fee_rates(tier).0)
+    }
+
+    /// 档位为 tier 的 maker 成交金额为 notional 时获得的返佣
+    pub fn maker_rebate(&self, notional: u64, tier: usize) -> Result<u64, DexError> {
+        math::bps(notional, self.fee_rates(tier).1)
+    }
+
+    /// 档位为 tier 的 taker 按 price 买入 quantity 主币需要支付的报价币（成交金额 + 手续费）
+    pub fn taker_cost(&self, price: u64, quantity: u64, tier: usize) -> Result<u64, DexError> {
+        let notional = self.notional(price, quantity)?;
+        math::add(notional, self.taker_fee(notional, tier)?)
+    }
+
+    /// 报价币预算 budget（含档位 tier 的 taker 手续费）按 price 最多能买到的主币数量（base_lot_size 的整数倍）
+    /// 价格为0时不受预算限制，返回 u64::MAX
+    pub fn max_base_for_quote(&self, price: u64, budget: u64, tier: usize) -> u64 {
+        let lot_cost = match self.notional(price, self.base_lot_size) {
+            Ok(0) => return u64::MAX,
+            Ok(cost) => cost,
+            // 一个 lot 的成交金额已超出 u64，任何预算都买不起
+            Err(_) => return 0,
+        };
+        // 成本超出 u64 的数量一定超出预算
+        let affordable = |lots: u64| {
+            self.base_native(lots)
+                .and_then(|quantity| self.taker_cost(price, quantity, tier))
+                .is_ok_and(|cost| cost <= budget)
+        };
+        // 先按费率估算，再修正手续费向下取整带来的误差
+        let bps = self.fee_rates(tier).0 as u128;
+        let max_lots = u64::MAX / self.base_lot_size;
+        let mut lots = (budget as u128 * 10_000 / (lot_cost as u128 * (10_000 + bps))) as u64;
+        lots = lots.min(max_lots);
+        while lots < max_lots && affordable(lots + 1) {
+            lots += 1;
+        }
+        while lots > 0 && !affordable(lots) {
+            lots -= 1;
+        }
+        lots * self.base_lot_size
+    }
+
+    /// taker 支付手续费 fee 时推荐人分得的部分
+    pub fn referrer_rebate(&self, fee: u64) -> Result<u64, DexError> {
+        math::bps(fee, self.referrer_fee_share_bps)
+    }
+}
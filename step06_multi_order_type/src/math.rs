@@ -0,0 +1,49 @@
+//! 账本算术
+//! Checked helpers for all balance, notional and fee math.
+//! 余额、成交金额、手续费的计算都经过这里：溢出（或扣减超过余额）时返回 DexError::Overflow，
+//! 而不是在 debug 构建下 panic、在 release 构建下静默回绕
+
+use crate::error::DexError;
+
+/// 基点分母（1 bps = 0.01%）
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// a + b
+pub fn add(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_add(b).ok_or(DexError::Overflow)
+}
+
+/// a - b（b 大于 a 时返回 Overflow）
+pub fn sub(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_sub(b).ok_or(DexError::Overflow)
+}
+
+/// a × b
+pub fn mul(a: u64, b: u64) -> Result<u64, DexError> {
+    a.checked_mul(b).ok_or(DexError::Overflow)
+}
+
+/// a × b ÷ c（向下取整），中间结果用 u128，只有最终结果超出 u64（或 c 为0）时返回 Overflow
+pub fn mul_div(a: u64, b: u64, c: u64) -> Result<u64, DexError> {
+    if c == 0 {
+        return Err(DexError::Overflow);
+    }
+    u64::try_from(a as u128 * b as u128 / c as u128).map_err(|_| DexError::Overflow)
+}
+
+/// amount × rate_bps ÷ 10_000（手续费、返佣、推荐人分成）
+pub fn bps(amount: u64, rate_bps: u64) -> Result<u64, DexError> {
+    mul_div(amount, rate_bps, BPS_DENOMINATOR)
+}
+
+/// *target += amount，溢出时 target 不变
+pub fn add_to(target: &mut u64, amount: u64) -> Result<(), DexError> {
+    *target = add(*target, amount)?;
+    Ok(())
+}
+
+/// *target -= amount，不足时 target 不变
+pub fn sub_from(target: &mut u64, amount: u64) -> Result<(), DexError> {
+    *target = sub(*target, amount)?;
+    Ok(())
+}
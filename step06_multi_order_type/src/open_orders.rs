@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+
+use crate::error::DexError;
+use crate::math;
+
+/// 用户在某个市场的挂单账户
+/// OpenOrders tracks one user's funds and resting orders in one market.
+/// 对齐 Serum DEX 的 OpenOrders 账户：
+/// - total：挂单账户中的全部资金（可用 + 被挂单冻结）
+/// - free：未被挂单冻结、可随时通过 settle_funds 转回钱包的资金
+/// - 成交所得、撤单解冻的资金都先进入 free，由用户 settle_funds 提回钱包
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OpenOrders {
+    /// 可用主币
+    pub base_free: u64,
+    /// 主币总额（可用 + 冻结）
+    pub base_total: u64,
+    /// 可用报价币
+    pub quote_free: u64,
+    /// 报价币总额（可用 + 冻结）
+    pub quote_total: u64,
+    /// 挂单ID -> 客户端订单ID（未指定时为0）
+    pub orders: BTreeMap<u64, u64>,
+}
+
+impl OpenOrders {
+    /// 被挂单冻结的主币
+    pub fn base_locked(&self) -> u64 {
+        self.base_total - self.base_free
+    }
+
+    /// 被挂单冻结的报价币
+    pub fn quote_locked(&self) -> u64 {
+        self.quote_total - self.quote_free
+    }
+
+    /// 用户在本市场的挂单ID（升序）
+    pub fn order_ids(&self) -> Vec<u64> {
+        self.orders.keys().copied().collect()
+    }
+
+    /// 用户在本市场挂单的客户端订单ID（与 order_ids 一一对应）
+    pub fn client_order_ids(&self) -> Vec<u64> {
+        self.orders.values().copied().collect()
+    }
+
+    /// 按客户端订单ID查找挂单ID（client_order_id 为0 表示未指定，不参与查找）
+    pub fn find_by_client_id(&self, client_order_id: u64) -> Option<u64> {
+        if client_order_id == 0 {
+            return None;
+        }
+        self.orders
+            .iter()
+            .find(|&(_, &cid)| cid == client_order_id)
+            .map(|(&id, _)| id)
+    }
+
+    /// 记录入簿的挂单
+    pub fn add_order(&mut self, order_id: u64, client_order_id: u64) {
+        self.orders.insert(order_id, client_order_id);
+    }
+
+    /// 移除离开订单簿的挂单，返回该挂单是否存在
+    pub fn remove_order(&mut self, order_id: u64) -> bool {
+        self.orders.remove(&order_id).is_some()
+    }
+
+    /// 是否没有任何资金和挂单
+    pub fn is_empty(&self) -> bool {
+        self.base_total == 0 && self.quote_total == 0 && self.orders.is_empty()
+    }
+
+    /// 入账主币（成交所得），直接计入可用余额
+    pub fn credit_base(&mut self, amount: u64) -> Result<(), DexError> {
+        let total = math::add(self.base_total, amount)?;
+        self.base_free = math::add(self.base_free, amount)?;
+        self.base_total = total;
+        Ok(())
+    }
+
+    /// 入账报价币（成交所得），直接计入可用余额
+    pub fn credit_quote(&mut self, amount: u64) -> Result<(), DexError> {
+        let total = math::add(self.quote_total, amount)?;
+        self.quote_free = math::add(self.quote_free, amount)?;
+        self.quote_total = total;
+        Ok(())
+    }
+
+    /// 解冻主币（撤单/过期/未成交部分）
+    pub fn unlock_base(&mut self, amount: u64) -> Result<(), DexError> {
+        math::add_to(&mut self.base_free, amount)
+    }
+
+    /// 解冻报价币（撤单/过期/未成交部分）
+    pub fn unlock_quote(&mut self, amount: u64) -> Result<(), DexError> {
+        math::add_to(&mut self.quote_free, amount)
+    }
+
+    /// 扣除冻结的主币（卖单成交，主币交给对手方）
+    pub fn debit_locked_base(&mut self, amount: u64) -> Result<(), DexError> {
+        math::sub_from(&mut self.base_total, amount)
+    }
+
+    /// 扣除冻结的报价币（买单成交，报价币交给对手方）
+    pub fn debit_locked_quote(&mut self, amount: u64) -> Result<(), DexError> {
+        math::sub_from(&mut self.quote_total, amount)
+    }
+
+    /// 取出全部可用资金（settle_funds），返回 (主币, 报价币)
+    pub fn take_free(&mut self) -> Result<(u64, u64), DexError> {
+        let (base, quote) = (self.base_free, self.quote_free);
+        let base_total = math::sub(self.base_total, base)?;
+        self.quote_total = math::sub(self.quote_total, quote)?;
+        self.base_total = base_total;
+        self.base_free = 0;
+        self.quote_free = 0;
+        Ok((base, quote))
+    }
+}
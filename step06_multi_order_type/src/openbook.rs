@@ -0,0 +1,2358 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::book_side::BookSide;
+use crate::error::DexError;
+use crate::event_queue::{Event, EventQueue, EventType};
+use crate::fee_tier::FeeTierResolver;
+use crate::market_config::MarketConfig;
+use crate::math;
+use crate::open_orders::OpenOrders;
+use crate::wallet::Wallets;
+
+/// 订单方向（买单/卖单）
+/// Side is order side (Bid/Ask)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Side {
+    /// 买单（出价买入）
+    Bid,
+    /// 卖单（挂出卖出）
+    Ask,
+}
+
+/// 订单类型（撮合行为控制）
+/// 对齐 Serum DEX OrderType
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderType {
+    /// 限价单（剩余可挂入订单簿，部分成交也允许）
+    Limit,
+    /// 市价单（不限价，只吃单，不入簿，其余自动取消）
+    /// 买单的数量为报价币预算（含手续费，对应 Serum 的 max_native_pc_qty_including_fees），卖单的数量为主币数量
+    Market,
+    /// IOC（立即成交否则取消，能成交多少就成交多少，其余立即取消，不入簿）
+    IOC,
+    /// FOK（全部成交否则全部取消，一笔不能全吃掉则全部撤销）
+    FOK,
+    /// 只挂单（会与对手方成交则拒绝，保证只做 maker）
+    PostOnly,
+    /// 只挂单（滑价）：会与对手方成交时把价格调整到对手方最优价外一个 tick 后挂单
+    PostOnlySlide,
+}
+
+impl OrderType {
+    /// 未成交部分是否挂入订单簿
+    pub fn rests(&self) -> bool {
+        matches!(
+            self,
+            OrderType::Limit | OrderType::PostOnly | OrderType::PostOnlySlide
+        )
+    }
+
+    /// 是否为只挂单（不吃单）
+    pub fn is_post_only(&self) -> bool {
+        matches!(self, OrderType::PostOnly | OrderType::PostOnlySlide)
+    }
+}
+
+/// 自成交处理策略（taker 遇到自己的挂单时的行为）
+/// 对齐 Serum DEX SelfTradeBehavior
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelfTradeBehavior {
+    /// 双方同时减少重叠的数量，不成交、不收手续费
+    #[default]
+    DecrementTake,
+    /// 撤销自己的挂单，taker 继续与后面的挂单撮合
+    CancelProvide,
+    /// 拒绝整笔订单，不做任何变更
+    AbortTransaction,
+}
+
+/// 订单结构    
+#[derive(Debug, Clone)]
+pub struct Order {
+    /// 订单唯一ID
+    pub id: u64,
+    /// 持有者（用户名）
+    pub owner: String,
+    /// 订单方向（买/卖）
+    pub side: Side,
+    /// 挂单价格
+    pub price: u64,
+    /// 挂单数量
+    pub quantity: u64,
+    /// 订单过期时间戳（可选，Some(ts)则ts时刻后订单无效）
+    pub expire_ts: Option<u64>,
+    /// 订单类型
+    pub order_type: OrderType,
+    /// 客户端订单ID（下单时由用户指定，0 表示未指定），对齐 Serum 的 client_order_id
+    pub client_order_id: u64,
+}
+
+impl Order {
+    /// 订单在 now 时刻是否已过期
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expire_ts.is_some_and(|ts| ts <= now)
+    }
+}
+
+/// 下单结果
+/// PlaceOrderOutcome is returned by a successful place_order call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaceOrderOutcome {
+    /// 本次下单分配的订单ID
+    pub order_id: u64,
+    /// 本次撮合成交的数量
+    pub filled_quantity: u64,
+    /// 本次撮合 taker 支付的手续费（单位：报价币）
+    pub fees_paid: u64,
+    /// 剩余部分入簿时的挂单ID（未入簿为None）
+    pub resting_order_id: Option<u64>,
+}
+
+/// 结算模式
+/// SettlementMode controls when makers are credited for fills.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SettlementMode {
+    /// 即时结算：撮合时同时给 taker 和 maker 记账
+    #[default]
+    Immediate,
+    /// 延迟结算（对齐 Serum）：撮合只给 taker 记账并写入成交事件，
+    /// maker 的资金由 crank 消费事件队列时再入账
+    Deferred,
+}
+
+/// crank 在事件队列上使用的 consumer ID
+pub const CRANK_CONSUMER: &str = "crank";
+
+/// 提现审计记录
+/// WithdrawRecord is appended for every successful withdraw, for reconciliation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WithdrawRecord {
+    /// 提现用户
+    pub user: String,
+    /// 提现的主币数量
+    pub base: u64,
+    /// 提现的报价币数量
+    pub quote: u64,
+    /// 提现后该用户在本市场剩余的可用主币（钱包 + 挂单账户可用）
+    pub base_remaining: u64,
+    /// 提现后该用户在本市场剩余的可用报价币（钱包 + 挂单账户可用）
+    pub quote_remaining: u64,
+    /// 提现时间
+    pub timestamp: u64,
+}
+
+/// 按币种提现的审计记录（直接从钱包提现，不属于任何市场，记录在 Markets 上）
+/// TokenWithdrawRecord is appended for every successful withdraw_token, for reconciliation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenWithdrawRecord {
+    /// 提现用户
+    pub user: String,
+    /// 提现币种
+    pub token: String,
+    /// 提现数量
+    pub amount: u64,
+    /// 提现后该用户钱包中该币种的剩余余额
+    pub remaining: u64,
+    /// 提现时间
+    pub timestamp: u64,
+}
+
+/// 平台手续费归集账户
+#[derive(Debug, Default)]
+pub struct FeeReceiver {
+    /// 已累计收取的 taker 手续费（单位：报价币）
+    pub taker_fees_collected: u64,
+    /// 已累计支付的 maker 返佣（单位：报价币）
+    pub maker_rebates_paid: u64,
+    /// 已累计支付给推荐人的分成（单位：报价币）
+    pub referrer_rebates_paid: u64,
+    /// 已被管理员提取的手续费（单位：报价币）
+    pub fees_swept: u64,
+}
+
+impl FeeReceiver {
+    /// 平台净手续费收入 = taker 手续费 - maker 返佣 - 推荐人分成（为负说明记账出错，返回 Overflow）
+    pub fn net_fees(&self) -> Result<u64, DexError> {
+        math::sub(
+            math::sub(self.taker_fees_collected, self.maker_rebates_paid)?,
+            self.referrer_rebates_paid,
+        )
+    }
+
+    /// 尚未提取的手续费 = 净收入 - 已提取
+    pub fn unswept_fees(&self) -> Result<u64, DexError> {
+        math::sub(self.net_fees()?, self.fees_swept)
+    }
+}
+
+/// 市场手续费报表（Markets::fee_report 中的一行）
+/// FeeReport summarizes one market's fee account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeReport {
+    /// 市场名
+    pub market: String,
+    /// 手续费币种（市场的报价币）
+    pub quote_mint: String,
+    /// 累计收取的 taker 手续费
+    pub taker_fees_collected: u64,
+    /// 累计支付的 maker 返佣
+    pub maker_rebates_paid: u64,
+    /// 累计支付的推荐人分成
+    pub referrer_rebates_paid: u64,
+    /// 净收入
+    pub net_fees: u64,
+    /// 已提取
+    pub fees_swept: u64,
+    /// 未提取
+    pub unswept_fees: u64,
+}
+
+/// 模拟撮合中的一笔成交
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulatedFill {
+    /// 被吃掉的挂单ID
+    pub maker_order_id: u64,
+    /// 挂单所有者
+    pub maker: String,
+    /// 成交价格（挂单价格）
+    pub price: u64,
+    /// 成交数量
+    pub quantity: u64,
+    /// 挂单是否被吃完、离开订单簿（会再写一条 Out 事件）
+    pub maker_out: bool,
+}
+
+/// 模拟撮合结果（见 MarketState::simulate_match）
+/// MatchSimulation lists the fills an order would produce, without touching the market.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MatchSimulation {
+    /// 会发生的成交（按撮合顺序）
+    pub fills: Vec<SimulatedFill>,
+    /// 撮合路径上属于 taker 自己的挂单ID（按自成交策略撤销或抵消，不成交）
+    pub self_trades: Vec<u64>,
+    /// 其中会因自成交离开订单簿的挂单数（CancelProvide 全部离开，DecrementTake 被抵消完时离开）
+    pub self_trade_outs: usize,
+}
+
+impl MatchSimulation {
+    /// 可成交的总数量（不含与自己挂单抵消的数量）
+    pub fn filled_quantity(&self) -> u64 {
+        self.fills.iter().map(|f| f.quantity).sum()
+    }
+
+    /// 撮合会写入的事件数：每笔成交一条成交事件，每个自己的挂单一条撤单事件，
+    /// 每个离开订单簿的挂单（被吃完或因自成交撤销）再加一条 Out 事件
+    pub fn events(&self) -> usize {
+        let outs = self.fills.iter().filter(|f| f.maker_out).count() + self.self_trade_outs;
+        self.fills.len() + self.self_trades.len() + outs
+    }
+}
+
+/// 单一市场状态
+#[derive(Debug)]
+pub struct MarketState {
+    /// 市场配置（币种、lot/tick、结算模式）
+    pub config: MarketConfig,
+    /// 买单簿（按价格档位组织，价格高优先，同价位时间优先）
+    pub bids: BookSide,
+    /// 卖单簿（按价格档位组织，价格低优先，同价位时间优先）
+    pub asks: BookSide,
+    /// 下一个订单号（自增ID）
+    pub next_order_id: u64,
+    /// 用户挂单账户表（user -> OpenOrders）
+    pub open_orders: HashMap<String, OpenOrders>,
+    /// 平台手续费账户
+    pub fee_receiver: FeeReceiver,
+    /// 提现审计记录（按时间顺序）
+    pub withdrawals: Vec<WithdrawRecord>,
+    /// 推荐人累计获得的分成（referrer -> 报价币数量）
+    pub referrer_rebates: HashMap<String, u64>,
+    /// 手续费档位解析器（None 时所有用户都用基础费率）
+    pub fee_tier_resolver: Option<Box<dyn FeeTierResolver>>,
+    /// 事件队列
+    pub event_queue: EventQueue,
+}
+
+impl Default for MarketState {
+    fn default() -> Self {
+        Self::new(MarketConfig::default()).expect("默认市场配置总是合法")
+    }
+}
+
+impl MarketState {
+    /// 按配置新建市场（配置非法返回 InvalidMarketConfig），订单簿存储由 config.slab_capacity 决定
+    /// 延迟结算模式下会预先注册 crank consumer，保证成交事件在 crank 处理前不会被弹出
+    pub fn new(config: MarketConfig) -> Result<Self, DexError> {
+        config.validate()?;
+        let (bids, asks) = match config.slab_capacity {
+            Some(max_orders) => (
+                BookSide::with_slab(Side::Bid, max_orders),
+                BookSide::with_slab(Side::Ask, max_orders),
+            ),
+            None => (BookSide::new(Side::Bid), BookSide::new(Side::Ask)),
+        };
+        let mut state = Self {
+            config,
+            bids,
+            asks,
+            next_order_id: 0,
+            open_orders: HashMap::new(),
+            fee_receiver: FeeReceiver::default(),
+            withdrawals: Vec::new(),
+            referrer_rebates: HashMap::new(),
+            fee_tier_resolver: None,
+            event_queue: EventQueue::default(),
+        };
+        if state.config.settlement_mode == SettlementMode::Deferred {
+            state.event_queue.register_consumer(CRANK_CONSUMER);
+        }
+        Ok(state)
+    }
+
+    /// 按配置新建以 critbit slab 为订单簿存储的市场（每一侧最多容纳 max_orders 个挂单）
+    pub fn with_slab(config: MarketConfig, max_orders: usize) -> Result<Self, DexError> {
+        Self::new(MarketConfig {
+            slab_capacity: Some(max_orders),
+            ..config
+        })
+    }
+
+    /// 用户充值（按本市场的主币/报价币存入钱包）
+    /// 任一币种的流通总量会超出 u64 时返回 Overflow，不做任何变更
+    pub fn deposit(
+        &self,
+        wallets: &mut Wallets,
+        user: &str,
+        base: u64,
+        quote: u64,
+    ) -> Result<(), DexError> {
+        math::add(wallets.supply(&self.config.base_mint), base)?;
+        math::add(wallets.supply(&self.config.quote_mint), quote)?;
+        wallets.deposit(user, &self.config.base_mint, base)?;
+        wallets.deposit(user, &self.config.quote_mint, quote)?;
+        println!(
+            "用户 {} 在本市场充值：主币 {}，报价币 {}",
+            user, base, quote
+        );
+        Ok(())
+    }
+
+    /// 清理所有已过期订单
+    /// 每个过期订单写入过期和 Out 事件；事件队列已满（且没有可腾出的旧事件）时返回 QueueFull，剩余过期订单留待下次清理
+    pub fn clean_expired_orders(&mut self, now: u64, market: &str) -> Result<(), DexError> {
+        let expired: Vec<u64> = self
+            .bids
+            .iter()
+            .chain(self.asks.iter())
+            .filter(|o| o.is_expired(now))
+            .map(|o| o.id)
+            .collect();
+        for id in expired {
+            // 每个过期挂单写过期和 Out 两条事件
+            self.check_event_space(2)?;
+            self.make_event_space(2);
+            let Some(o) = self.bids.remove(id).or_else(|| self.asks.remove(id)) else {
+                continue;
+            };
+            self.release_order(&o)?;
+            self.event_queue.push(Event::order_event(
+                EventType::Expire,
+                market,
+                &o,
+                o.quantity,
+                now,
+            ))?;
+            self.push_out(market, &o, o.quantity, now)?;
+        }
+        self.debug_check();
+        Ok(())
+    }
+
+    /// 模拟撮合（不修改任何状态）：返回该订单在当前订单簿上会产生的成交
+    /// 只挂单不会成交；市价单忽略 price，市价买单的 quantity 为报价币预算（按基础费率计算手续费）
+    pub fn simulate_match(
+        &self,
+        side: &Side,
+        price: u64,
+        quantity: u64,
+        order_type: &OrderType,
+    ) -> MatchSimulation {
+        let (price, quantity) = match (order_type, side) {
+            (OrderType::PostOnly | OrderType::PostOnlySlide, _) => {
+                return MatchSimulation::default();
+            }
+            (OrderType::Market, Side::Bid) => (
+                u64::MAX,
+                self.market_bid_quantity(None, quantity, 0, SelfTradeBehavior::default(), None),
+            ),
+            (OrderType::Market, Side::Ask) => (0, quantity),
+            _ => (price, quantity),
+        };
+        self.simulate_taker(
+            None,
+            side,
+            price,
+            quantity,
+            SelfTradeBehavior::default(),
+            None,
+        )
+    }
+
+    /// 模拟 owner 的订单在对手方订单簿上的撮合路径（不修改任何状态）
+    /// now 为 Some 时跳过已过期（尚未清理）的挂单
+    fn simulate_taker(
+        &self,
+        owner: Option<&str>,
+        side: &Side,
+        price: u64,
+        quantity: u64,
+        self_trade_behavior: SelfTradeBehavior,
+        now: Option<u64>,
+    ) -> MatchSimulation {
+        let (book, crosses): (&BookSide, fn(u64, u64) -> bool) = match side {
+            Side::Bid => (&self.asks, |price, maker| price >= maker),
+            Side::Ask => (&self.bids, |price, maker| price <= maker),
+        };
+        let mut simulation = MatchSimulation::default();
+        let mut remain = quantity;
+        for maker in book.iter() {
+            if remain == 0 || !crosses(price, maker.price) {
+                break;
+            }
+            if now.is_some_and(|now| maker.is_expired(now)) {
+                continue;
+            }
+            if owner == Some(maker.owner.as_str()) {
+                simulation.self_trades.push(maker.id);
+                match self_trade_behavior {
+                    // 撤掉自己的挂单，不消耗 taker 的数量
+                    SelfTradeBehavior::CancelProvide => {
+                        simulation.self_trade_outs += 1;
+                        continue;
+                    }
+                    // 双方减少重叠数量，这部分不算成交
+                    SelfTradeBehavior::DecrementTake => {
+                        if remain >= maker.quantity {
+                            simulation.self_trade_outs += 1;
+                        }
+                        remain -= remain.min(maker.quantity);
+                        continue;
+                    }
+                    SelfTradeBehavior::AbortTransaction => {}
+                }
+            }
+            let deal = remain.min(maker.quantity);
+            remain -= deal;
+            simulation.fills.push(SimulatedFill {
+                maker_order_id: maker.id,
+                maker: maker.owner.clone(),
+                price: maker.price,
+                quantity: deal,
+                maker_out: deal == maker.quantity,
+            });
+        }
+        simulation
+    }
+
+    /// 下单（挂入订单簿或直接撮合，支持订单有效期和自动清理过期订单）
+    /// 市价单忽略 price：买单的 quantity 为报价币预算（含手续费），按预算从最优卖价开始扫单，未用完的预算解冻；
+    /// 卖单的 quantity 为主币数量
+    /// client_order_id: 客户端订单ID（0 表示未指定），写入挂单和事件，可用于 cancel_order_by_client_id；
+    /// 与该用户已有挂单的客户端订单ID重复时返回 DuplicateClientOrderId
+    /// referrer: 推荐人（可选），每笔成交按市场配置的分成比例从 taker 手续费中分给推荐人，直接计入其钱包
+    /// 失败时返回 DexError（余额不足 / FOK无法全部成交 / 数量非法 / 事件队列已满），余额不做任何变更
+    #[allow(clippy::too_many_arguments)]
+    pub fn place_order(
+        &mut self,
+        wallets: &mut Wallets,
+        market: &str,
+        owner: &str,
+        side: Side,
+        price: u64,
+        quantity: u64,
+        now: u64,
+        expire_ts: Option<u64>,
+        order_type: OrderType,
+        client_order_id: u64,
+        self_trade_behavior: SelfTradeBehavior,
+        referrer: Option<&str>,
+    ) -> Result<PlaceOrderOutcome, DexError> {
+        let result = self.execute_order(
+            wallets,
+            market,
+            owner,
+            side,
+            price,
+            quantity,
+            now,
+            expire_ts,
+            order_type,
+            client_order_id,
+            self_trade_behavior,
+            referrer,
+        );
+        self.debug_check();
+        result
+    }
+
+    /// 下单的执行过程（place_order 在其后校验不变量）
+    #[allow(clippy::too_many_arguments)]
+    fn execute_order(
+        &mut self,
+        wallets: &mut Wallets,
+        market: &str,
+        owner: &str,
+        side: Side,
+        price: u64,
+        quantity: u64,
+        now: u64,
+        expire_ts: Option<u64>,
+        order_type: OrderType,
+        client_order_id: u64,
+        self_trade_behavior: SelfTradeBehavior,
+        referrer: Option<&str>,
+    ) -> Result<PlaceOrderOutcome, DexError> {
+        if quantity == 0 {
+            return Err(DexError::InvalidQuantity);
+        }
+        if client_order_id != 0
+            && self
+                .open_orders
+                .get(owner)
+                .and_then(|oo| oo.find_by_client_id(client_order_id))
+                .is_some()
+        {
+            return Err(DexError::DuplicateClientOrderId(client_order_id));
+        }
+        let budget = (order_type == OrderType::Market && side == Side::Bid).then_some(quantity);
+        let price = match (&order_type, &side) {
+            (OrderType::Market, Side::Bid) => u64::MAX,
+            (OrderType::Market, Side::Ask) => 0,
+            _ => price,
+        };
+        if budget.is_none() {
+            self.config.validate_order(price, quantity)?;
+        }
+
+        // 所有拒绝（FOK 无法全部成交 / 只挂单会成交 / 自成交中止 / 事件队列已满 / 余额不足）
+        // 都在清理过期订单之前按"跳过已过期挂单"模拟判断，任何一项失败时市场状态不做任何变更
+        let taker_tier = self.fee_tier(owner, now);
+        let price = self.post_only_price(&side, price, &order_type, now)?;
+        let quantity = match budget {
+            Some(budget) => self.market_bid_quantity(
+                Some(owner),
+                budget,
+                taker_tier,
+                self_trade_behavior,
+                Some(now),
+            ),
+            None => quantity,
+        };
+        let simulation = self.simulate_taker(
+            Some(owner),
+            &side,
+            price,
+            quantity,
+            self_trade_behavior,
+            Some(now),
+        );
+
+        // FOK 不能全部成交则直接拒绝（与自己挂单抵消的数量不算成交）
+        if order_type == OrderType::FOK {
+            let fillable = simulation.filled_quantity();
+            if fillable < quantity {
+                return Err(DexError::FokNotFillable {
+                    requested: quantity,
+                    fillable,
+                });
+            }
+        }
+        if self_trade_behavior == SelfTradeBehavior::AbortTransaction
+            && let Some(&maker_order_id) = simulation.self_trades.first()
+        {
+            return Err(DexError::SelfTrade { maker_order_id });
+        }
+
+        // 事件队列没有足够空间记录本次撮合，拒绝撮合：
+        // 每个过期挂单清理时写过期和 Out 两条事件；
+        // 每碰到一个挂单写一条成交（或自成交撤单）事件，离开订单簿的挂单再写一条 Out 事件；
+        // 限价单剩余部分入簿时若订单簿（slab）已满会挤出一个挂单，再写撤单和 Out 两条事件
+        // （同侧有过期挂单时清理后订单簿不再是满的）
+        let (own_book, other_book) = match side {
+            Side::Bid => (&self.bids, &self.asks),
+            Side::Ask => (&self.asks, &self.bids),
+        };
+        let own_expired = own_book.iter().filter(|o| o.is_expired(now)).count();
+        let expired = own_expired + other_book.iter().filter(|o| o.is_expired(now)).count();
+        let may_bump = order_type.rests() && own_book.is_full() && own_expired == 0;
+        let needed_events = 2 * expired + simulation.events() + 2 * may_bump as usize;
+        self.check_event_space(needed_events)?;
+
+        // 冻结资金（买单冻结报价币，并按全部数量预留 taker 手续费；市价买单冻结全部预算；卖单冻结主币）
+        // 只挂单不会吃单，不需要预留手续费
+        let reserved_fee = match side {
+            Side::Bid if order_type.is_post_only() || budget.is_some() => 0,
+            Side::Bid => self
+                .config
+                .taker_fee(self.config.notional(price, quantity)?, taker_tier)?,
+            Side::Ask => 0,
+        };
+        let lock_amount = match (&side, budget) {
+            (_, Some(budget)) => budget,
+            (Side::Bid, None) => math::add(self.config.notional(price, quantity)?, reserved_fee)?,
+            (Side::Ask, None) => quantity,
+        };
+        self.check_funds(wallets, owner, &side, lock_amount, Some(now))?;
+
+        // 以上检查都通过后才腾出事件空间、清理过期订单、冻结资金
+        self.make_event_space(needed_events);
+        self.clean_expired_orders(now, market)?;
+        self.lock_funds(wallets, owner, &side, lock_amount)?;
+
+        // 构造订单
+        let order_id = self.next_order_id;
+        self.next_order_id += 1;
+
+        let mut order = Order {
+            id: order_id,
+            owner: owner.to_string(),
+            side: side.clone(),
+            price,
+            quantity,
+            expire_ts,
+            order_type: order_type.clone(),
+            client_order_id,
+        };
+
+        let mut filled = 0;
+        let mut total_fee = 0;
+        let mut spent_quote = 0;
+        let mut rested = false;
+
+        // 撮合逻辑
+        match side {
+            Side::Bid => {
+                // 1. 按价格优先吃掉可成交的卖单
+                while let Some(best_ask) = self.asks.best().cloned() {
+                    if order.price >= best_ask.price && order.quantity > 0 {
+                        if best_ask.owner == order.owner {
+                            order.quantity -= self.prevent_self_trade(
+                                market,
+                                &order,
+                                &best_ask,
+                                self_trade_behavior,
+                                now,
+                            )?;
+                            continue;
+                        }
+                        let deal_qty = order.quantity.min(best_ask.quantity);
+                        let deal_price = best_ask.price;
+                        let notional = self.config.notional(deal_price, deal_qty)?;
+                        let maker_tier = self.fee_tier(&best_ask.owner, now);
+                        let fee = self.config.taker_fee(notional, taker_tier)?;
+                        let rebate = self.config.maker_rebate(notional, maker_tier)?;
+                        math::add_to(&mut self.fee_receiver.taker_fees_collected, fee)?;
+                        math::add_to(&mut self.fee_receiver.maker_rebates_paid, rebate)?;
+                        let referrer_rebate = self.pay_referrer(wallets, referrer, fee)?;
+
+                        // 买家（taker）冻结的报价币支付成交金额和手续费，换成主币，
+                        // 按限价冻结、按更优的挂单价成交的差额（价格改善）立即解冻；
+                        // 卖家（maker）冻结的主币换成报价币并获得返佣，延迟结算模式下由 crank 入账
+                        let improvement = match budget {
+                            Some(_) => 0,
+                            None => {
+                                math::sub(self.config.notional(order.price, deal_qty)?, notional)?
+                            }
+                        };
+                        let cost = math::add(notional, fee)?;
+                        let (taker_native_paid, taker_native_received) = (cost, deal_qty);
+                        let (maker_native_paid, maker_native_received) =
+                            (deal_qty, math::add(notional, rebate)?);
+                        let taker_oo = self.open_orders.get_mut(&order.owner).unwrap();
+                        taker_oo.debit_locked_quote(cost)?;
+                        taker_oo.unlock_quote(improvement)?;
+                        taker_oo.credit_base(deal_qty)?;
+                        math::add_to(&mut spent_quote, cost)?;
+                        if self.config.settlement_mode == SettlementMode::Immediate {
+                            let maker_oo =
+                                self.open_orders.entry(best_ask.owner.clone()).or_default();
+                            maker_oo.debit_locked_base(deal_qty)?;
+                            maker_oo.credit_quote(maker_native_received)?;
+                        }
+
+                        self.push_fill(
+                            Event {
+                                fee,
+                                rebate,
+                                taker_fee_tier: taker_tier,
+                                maker_fee_tier: maker_tier,
+                                referrer: referrer.map(str::to_string),
+                                referrer_rebate,
+                                taker_native_paid,
+                                taker_native_received,
+                                maker_native_paid,
+                                maker_native_received,
+                                ..Event::fill(market, &order, &best_ask, deal_qty, now)
+                            },
+                            notional,
+                        )?;
+
+                        order.quantity -= deal_qty;
+                        filled += deal_qty;
+                        math::add_to(&mut total_fee, fee)?;
+                        if let Some(b0) = self.asks.best_mut() {
+                            b0.quantity -= deal_qty;
+                        }
+                        if self.asks.best().map(|b| b.quantity == 0).unwrap_or(false)
+                            && let Some(done) = self.asks.pop_best()
+                        {
+                            self.remove_filled_order(market, &done, now)?;
+                        }
+                    } else {
+                        break;
+                    }
+                }
+
+                // 未成交部分不再按 taker 收费，释放多预留的手续费；市价买单解冻未用完的预算
+                let unused = match budget {
+                    Some(budget) => math::sub(budget, spent_quote)?,
+                    None => math::sub(reserved_fee, total_fee)?,
+                };
+                self.open_orders
+                    .get_mut(&order.owner)
+                    .unwrap()
+                    .unlock_quote(unused)?;
+                // 2. 剩余逻辑
+                match order_type {
+                    OrderType::Limit | OrderType::PostOnly | OrderType::PostOnlySlide => {
+                        if order.quantity > 0 {
+                            // 剩余部分继续冻结，由挂单账户记录
+                            self.open_orders
+                                .get_mut(&order.owner)
+                                .unwrap()
+                                .add_order(order.id, order.client_order_id);
+                            if let Some(bumped) = self.bids.insert(order.clone()) {
+                                rested = bumped.id != order.id;
+                                self.release_bumped_order(market, bumped, now)?;
+                            } else {
+                                rested = true;
+                            }
+                            println!("限价买单未完全成交，剩余 {} 入簿", order.quantity);
+                        }
+                    }
+                    OrderType::Market => {
+                        println!(
+                            "市价买单成交 {}，花费 {}，未用完的预算 {} 已解冻",
+                            filled, spent_quote, unused
+                        );
+                    }
+                    // FOK 下单前已通过模拟撮合确认能全部成交，不会有剩余
+                    OrderType::IOC | OrderType::FOK => {
+                        if order.quantity > 0 {
+                            let refund = self.config.notional(price, order.quantity)?;
+                            self.open_orders
+                                .get_mut(&order.owner)
+                                .unwrap()
+                                .unlock_quote(refund)?;
+                            println!("IOC买单未完全成交，剩余 {} 自动撤销", order.quantity);
+                        }
+                    }
+                }
+            }
+            Side::Ask => {
+                // 1. 按价格优先吃掉可成交的买单
+                while let Some(best_bid) = self.bids.best().cloned() {
+                    if order.price <= best_bid.price && order.quantity > 0 {
+                        if best_bid.owner == order.owner {
+                            order.quantity -= self.prevent_self_trade(
+                                market,
+                                &order,
+                                &best_bid,
+                                self_trade_behavior,
+                                now,
+                            )?;
+                            continue;
+                        }
+                        let deal_qty = order.quantity.min(best_bid.quantity);
+                        let deal_price = best_bid.price;
+                        let notional = self.config.notional(deal_price, deal_qty)?;
+                        let maker_tier = self.fee_tier(&best_bid.owner, now);
+                        let fee = self.config.taker_fee(notional, taker_tier)?;
+                        let rebate = self.config.maker_rebate(notional, maker_tier)?;
+                        math::add_to(&mut self.fee_receiver.taker_fees_collected, fee)?;
+                        math::add_to(&mut self.fee_receiver.maker_rebates_paid, rebate)?;
+                        let referrer_rebate = self.pay_referrer(wallets, referrer, fee)?;
+
+                        // 卖家（taker）冻结的主币换成报价币（扣手续费）；
+                        // 买家（maker）冻结的报价币换成主币并获得返佣，延迟结算模式下由 crank 入账
+                        let (taker_native_paid, taker_native_received) =
+                            (deal_qty, math::sub(notional, fee)?);
+                        let (maker_native_paid, maker_native_received) =
+                            (math::sub(notional, rebate)?, deal_qty);
+                        let taker_oo = self.open_orders.get_mut(&order.owner).unwrap();
+                        taker_oo.debit_locked_base(deal_qty)?;
+                        taker_oo.credit_quote(taker_native_received)?;
+                        if self.config.settlement_mode == SettlementMode::Immediate {
+                            let maker_oo =
+                                self.open_orders.entry(best_bid.owner.clone()).or_default();
+                            maker_oo.debit_locked_quote(notional)?;
+                            maker_oo.credit_base(deal_qty)?;
+                            maker_oo.credit_quote(rebate)?;
+                        }
+
+                        self.push_fill(
+                            Event {
+                                fee,
+                                rebate,
+                                taker_fee_tier: taker_tier,
+                                maker_fee_tier: maker_tier,
+                                referrer: referrer.map(str::to_string),
+                                referrer_rebate,
+                                taker_native_paid,
+                                taker_native_received,
+                                maker_native_paid,
+                                maker_native_received,
+                                ..Event::fill(market, &order, &best_bid, deal_qty, now)
+                            },
+                            notional,
+                        )?;
+
+                        order.quantity -= deal_qty;
+                        filled += deal_qty;
+                        math::add_to(&mut total_fee, fee)?;
+                        if let Some(b0) = self.bids.best_mut() {
+                            b0.quantity -= deal_qty;
+                        }
+                        if self.bids.best().map(|b| b.quantity == 0).unwrap_or(false)
+                            && let Some(done) = self.bids.pop_best()
+                        {
+                            self.remove_filled_order(market, &done, now)?;
+                        }
+                    } else {
+                        break;
+                    }
+                }
+                // 剩余未成交部分挂入订单簿
+                match order_type {
+                    OrderType::Limit | OrderType::PostOnly | OrderType::PostOnlySlide => {
+                        if order.quantity > 0 {
+                            self.open_orders
+                                .get_mut(&order.owner)
+                                .unwrap()
+                                .add_order(order.id, order.client_order_id);
+                            if let Some(bumped) = self.asks.insert(order.clone()) {
+                                rested = bumped.id != order.id;
+                                self.release_bumped_order(market, bumped, now)?;
+                            } else {
+                                rested = true;
+                            }
+                            println!("限价卖单未完全成交，剩余 {} 入簿", order.quantity);
+                        }
+                    }
+                    // FOK 下单前已通过模拟撮合确认能全部成交，不会有剩余
+                    OrderType::Market | OrderType::IOC | OrderType::FOK => {
+                        if order.quantity > 0 {
+                            self.open_orders
+                                .get_mut(&order.owner)
+                                .unwrap()
+                                .unlock_base(order.quantity)?;
+                            println!("市价/IOC卖单未完全成交，剩余 {} 自动撤销", order.quantity);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(PlaceOrderOutcome {
+            order_id,
+            filled_quantity: filled,
+            fees_paid: total_fee,
+            resting_order_id: rested.then_some(order_id),
+        })
+    }
+
+    /// 管理员提取手续费：把未提取的净手续费转入管理员钱包（报价币），并写入提取事件
+    /// 非市场管理员返回 Unauthorized，事件队列已满返回 QueueFull，均不做任何变更；返回提取数量
+    pub fn sweep_fees(
+        &mut self,
+        wallets: &mut Wallets,
+        market: &str,
+        authority: &str,
+        now: u64,
+    ) -> Result<u64, DexError> {
+        if self.config.authority.as_deref() != Some(authority) {
+            return Err(DexError::Unauthorized(authority.to_string()));
+        }
+        self.check_event_space(1)?;
+        let amount = self.fee_receiver.unswept_fees()?;
+        let fees_swept = math::add(self.fee_receiver.fees_swept, amount)?;
+        self.make_event_space(1);
+        self.event_queue
+            .push(Event::sweep_fees(market, authority, amount, now))?;
+        self.fee_receiver.fees_swept = fees_swept;
+        wallets.credit(authority, &self.config.quote_mint, amount)?;
+        self.debug_check();
+        println!(
+            "管理员 {} 提取手续费 {} {}",
+            authority, amount, self.config.quote_mint
+        );
+        Ok(amount)
+    }
+
+    /// 本市场的手续费报表（手续费账户记账出错时返回 Overflow）
+    pub fn fee_report(&self, market: &str) -> Result<FeeReport, DexError> {
+        let fees = &self.fee_receiver;
+        Ok(FeeReport {
+            market: market.to_string(),
+            quote_mint: self.config.quote_mint.clone(),
+            taker_fees_collected: fees.taker_fees_collected,
+            maker_rebates_paid: fees.maker_rebates_paid,
+            referrer_rebates_paid: fees.referrer_rebates_paid,
+            net_fees: fees.net_fees()?,
+            fees_swept: fees.fees_swept,
+            unswept_fees: fees.unswept_fees()?,
+        })
+    }
+
+    /// 延迟结算模式下尚未被 crank 处理的成交事件（即时结算模式下为空）
+    pub fn pending_fills(&self) -> Vec<&Event> {
+        if self.config.settlement_mode != SettlementMode::Deferred {
+            return Vec::new();
+        }
+        let queue = &self.event_queue;
+        let from = queue
+            .consumer_position(CRANK_CONSUMER)
+            .unwrap_or(queue.head_seq());
+        (from..queue.next_seq)
+            .filter_map(|seq| queue.get(seq))
+            .filter(|e| matches!(e.event_type, EventType::Fill))
+            .collect()
+    }
+
+    /// 校验市场内部的不变量：
+    /// - 订单簿：买单价格从高到低、卖单价格从低到高，没有数量为0的挂单，买一价低于卖一价（没有交叉）
+    /// - 挂单账户：可用不超过总额，记录的挂单与订单簿中该用户的挂单一致
+    /// - 冻结资金与挂单一致（见 check_locked_funds）
+    pub fn check_invariants(&self) -> Result<(), DexError> {
+        let violation = |reason: String| Err(DexError::InvariantViolation(reason));
+        for (book, descending) in [(&self.bids, true), (&self.asks, false)] {
+            let orders: Vec<&Order> = book.iter().collect();
+            if let Some(o) = orders.iter().find(|o| o.quantity == 0) {
+                return violation(format!("订单 {} 数量为0", o.id));
+            }
+            if let Some(w) = orders.windows(2).find(|w| match descending {
+                true => w[0].price < w[1].price,
+                false => w[0].price > w[1].price,
+            }) {
+                return violation(format!("订单簿排序错误：{} 在 {} 之前", w[0].id, w[1].id));
+            }
+        }
+        if let (Some(bid), Some(ask)) = (self.bids.best_price(), self.asks.best_price())
+            && bid >= ask
+        {
+            return violation(format!("订单簿交叉：买一 {} ≥ 卖一 {}", bid, ask));
+        }
+        let mut resting: HashMap<&str, Vec<u64>> = HashMap::new();
+        for o in self.bids.iter().chain(self.asks.iter()) {
+            resting.entry(&o.owner).or_default().push(o.id);
+        }
+        for (user, oo) in &self.open_orders {
+            if oo.base_free > oo.base_total || oo.quote_free > oo.quote_total {
+                return violation(format!("用户 {} 可用资金超过总额", user));
+            }
+            let mut ids = resting.remove(user.as_str()).unwrap_or_default();
+            ids.sort_unstable();
+            if ids != oo.order_ids() {
+                return violation(format!(
+                    "用户 {} 挂单账户记录的挂单 {:?} 与订单簿 {:?} 不一致",
+                    user,
+                    oo.order_ids(),
+                    ids
+                ));
+            }
+        }
+        self.check_locked_funds()
+    }
+
+    /// 本市场持有的主币和报价币（用于跨市场总量守恒校验）
+    /// = 挂单账户总额 + 平台未提取的手续费 + 延迟结算中尚未被 crank 处理的成交差额
+    /// （未处理的成交中 taker 一侧已入账，maker 应得的资金尚未入账，maker 付出的资金仍冻结在挂单账户中）
+    /// 结果为负或超出 u64 说明账本已损坏，返回 InvariantViolation
+    pub fn holdings(&self) -> Result<(u64, u64), DexError> {
+        let mut base: i128 = 0;
+        let mut quote = self.fee_receiver.unswept_fees()? as i128;
+        for oo in self.open_orders.values() {
+            base += oo.base_total as i128;
+            quote += oo.quote_total as i128;
+        }
+        for e in self.pending_fills() {
+            let Some(side) = &e.side else {
+                continue;
+            };
+            let quantity = e.quantity as i128;
+            let notional = self.config.notional(e.price.unwrap_or(0), e.quantity)? as i128;
+            let rebate = e.rebate as i128;
+            match side {
+                Side::Bid => {
+                    base -= quantity;
+                    quote += notional + rebate;
+                }
+                Side::Ask => {
+                    base += quantity;
+                    quote += rebate - notional;
+                }
+            }
+        }
+        let checked = |token: &str, amount: i128| {
+            u64::try_from(amount).map_err(|_| {
+                DexError::InvariantViolation(format!("{} 持有量 {} 超出 u64 范围", token, amount))
+            })
+        };
+        Ok((
+            checked(&self.config.base_mint, base)?,
+            checked(&self.config.quote_mint, quote)?,
+        ))
+    }
+
+    /// 调试构建下每次变更后校验不变量，发现记账 bug 立即 panic
+    fn debug_check(&self) {
+        debug_assert_eq!(self.check_invariants(), Ok(()));
+    }
+
+    /// 校验挂单账户的冻结资金：每个用户冻结的主币必须等于其卖单数量之和，
+    /// 冻结的报价币必须等于其买单 price × quantity 之和；
+    /// 延迟结算模式下还要加上尚未被 crank 处理的成交中 maker 仍冻结的部分
+    pub fn check_locked_funds(&self) -> Result<(), DexError> {
+        let mut expected: HashMap<&str, (u64, u64)> = HashMap::new();
+        for o in self.bids.iter() {
+            let notional = self.config.notional(o.price, o.quantity)?;
+            math::add_to(&mut expected.entry(&o.owner).or_default().1, notional)?;
+        }
+        for o in self.asks.iter() {
+            math::add_to(&mut expected.entry(&o.owner).or_default().0, o.quantity)?;
+        }
+        for e in self.pending_fills() {
+            let (Some(maker), Some(price), Some(side)) = (&e.maker, e.price, &e.side) else {
+                continue;
+            };
+            let locked = expected.entry(maker).or_default();
+            // 事件的 side 是 taker 的方向，maker 在另一侧
+            match side {
+                Side::Bid => math::add_to(&mut locked.0, e.quantity)?,
+                Side::Ask => math::add_to(&mut locked.1, self.config.notional(price, e.quantity)?)?,
+            }
+        }
+        for (user, oo) in &self.open_orders {
+            let (base, quote) = expected.remove(user.as_str()).unwrap_or_default();
+            if oo.base_locked() != base || oo.quote_locked() != quote {
+                return Err(DexError::InvariantViolation(format!(
+                    "用户 {} 冻结 主币 {} / 报价币 {}，挂单需要 {} / {}",
+                    user,
+                    oo.base_locked(),
+                    oo.quote_locked(),
+                    base,
+                    quote
+                )));
+            }
+        }
+        if let Some(user) = expected.keys().next() {
+            return Err(DexError::InvariantViolation(format!(
+                "用户 {} 有挂单但没有挂单账户",
+                user
+            )));
+        }
+        Ok(())
+    }
+
+    /// 推荐人累计获得的分成
+    pub fn referrer_rebates(&self, referrer: &str) -> u64 {
+        self.referrer_rebates.get(referrer).copied().unwrap_or(0)
+    }
+
+    /// 从 taker 手续费 fee 中给推荐人分成，计入推荐人钱包的报价币，返回分成数量
+    fn pay_referrer(
+        &mut self,
+        wallets: &mut Wallets,
+        referrer: Option<&str>,
+        fee: u64,
+    ) -> Result<u64, DexError> {
+        let Some(referrer) = referrer else {
+            return Ok(0);
+        };
+        let amount = self.config.referrer_rebate(fee)?;
+        if amount > 0 {
+            wallets.credit(referrer, &self.config.quote_mint, amount)?;
+            math::add_to(&mut self.fee_receiver.referrer_rebates_paid, amount)?;
+            math::add_to(
+                self.referrer_rebates
+                    .entry(referrer.to_string())
+                    .or_default(),
+                amount,
+            )?;
+        }
+        Ok(amount)
+    }
+
+    /// 用户当前的手续费档位（未设置解析器时为基础档位0）
+    pub fn fee_tier(&self, user: &str, now: u64) -> usize {
+        self.fee_tier_resolver
+            .as_ref()
+            .map(|r| r.tier(user, &self.config.fee_tiers, now))
+            .unwrap_or(0)
+    }
+
+    /// 设置手续费档位解析器
+    pub fn set_fee_tier_resolver(&mut self, resolver: Box<dyn FeeTierResolver>) {
+        self.fee_tier_resolver = Some(resolver);
+    }
+
+    /// 可以腾出的旧事件数：队首开始所有已注册consumer都读过的事件；
+    /// 没有注册任何consumer时（即时结算模式且从未 crank）事件只作记录，全部可以腾出
+    fn reclaimable_events(&self) -> usize {
+        let queue = &self.event_queue;
+        match queue.low_watermark() {
+            Some(low) => (low.saturating_sub(queue.head_seq()) as usize).min(queue.len()),
+            None => queue.len(),
+        }
+    }
+
+    /// 检查事件队列能否写入 needed 条事件（计入可以腾出的旧事件），不能则返回 QueueFull，不做任何变更
+    fn check_event_space(&self, needed: usize) -> Result<(), DexError> {
+        if self.event_queue.free_slots() + self.reclaimable_events() < needed {
+            return Err(DexError::QueueFull);
+        }
+        Ok(())
+    }
+
+    /// 为即将写入的 needed 条事件腾出空间：按需从队首弹出可以腾出的旧事件（调用前已 check_event_space）
+    fn make_event_space(&mut self, needed: usize) {
+        let mut reclaimable = self.reclaimable_events();
+        while self.event_queue.free_slots() < needed && reclaimable > 0 {
+            self.event_queue.pop_front();
+            reclaimable -= 1;
+        }
+    }
+
+    /// 写入成交事件，并通知档位解析器（如累计成交量）
+    fn push_fill(&mut self, event: Event, notional: u64) -> Result<(), DexError> {
+        self.event_queue.push(event.clone())?;
+        if let Some(resolver) = self.fee_tier_resolver.as_mut() {
+            resolver.record_fill(&event, notional);
+        }
+        Ok(())
+    }
+
+    /// 市价买单按报价币预算 budget（含手续费）从最优卖价开始扫单能买到的主币数量
+    /// 与撮合循环的逐笔扣费一致，保证实际花费不超过预算；DecrementTake 抵消自己的挂单时按成交金额扣减预算
+    /// now 为 Some 时跳过已过期（尚未清理）的挂单
+    fn market_bid_quantity(
+        &self,
+        owner: Option<&str>,
+        budget: u64,
+        taker_tier: usize,
+        self_trade_behavior: SelfTradeBehavior,
+        now: Option<u64>,
+    ) -> u64 {
+        let mut remain = budget;
+        let mut quantity = 0;
+        for maker in self.asks.iter() {
+            if now.is_some_and(|now| maker.is_expired(now)) {
+                continue;
+            }
+            let own = owner == Some(maker.owner.as_str());
+            if own && self_trade_behavior == SelfTradeBehavior::CancelProvide {
+                continue;
+            }
+            let deal = self
+                .config
+                .max_base_for_quote(maker.price, remain, taker_tier)
+                .min(maker.quantity);
+            if deal == 0 {
+                break;
+            }
+            let cost = match own {
+                true => self.config.notional(maker.price, deal),
+                false => self.config.taker_cost(maker.price, deal, taker_tier),
+            };
+            // max_base_for_quote 保证成本不超过剩余预算
+            let Ok(rest) = cost.and_then(|cost| math::sub(remain, cost)) else {
+                break;
+            };
+            remain = rest;
+            quantity += deal;
+        }
+        quantity
+    }
+
+    /// 只挂单的实际挂单价格：不会与对手方成交时保持原价；
+    /// 会成交时 PostOnly 返回 PostOnlyWouldCross，PostOnlySlide 调整到对手方最优价外一个 tick
+    /// 对手方最优价跳过 now 时已过期（尚未清理）的挂单
+    fn post_only_price(
+        &self,
+        side: &Side,
+        price: u64,
+        order_type: &OrderType,
+        now: u64,
+    ) -> Result<u64, DexError> {
+        if !order_type.is_post_only() {
+            return Ok(price);
+        }
+        let best_live = |book: &BookSide| book.iter().find(|o| !o.is_expired(now)).map(|o| o.price);
+        let tick = self.config.tick_size;
+        let (best_price, slid) = match side {
+            Side::Bid => match best_live(&self.asks) {
+                Some(best) if price >= best => (best, best.checked_sub(tick).filter(|p| *p > 0)),
+                _ => return Ok(price),
+            },
+            Side::Ask => match best_live(&self.bids) {
+                Some(best) if price <= best => (best, best.checked_add(tick)),
+                _ => return Ok(price),
+            },
+        };
+        match (order_type, slid) {
+            (OrderType::PostOnlySlide, Some(slid)) => {
+                println!("只挂单价格 {} 会立即成交，调整为 {}", price, slid);
+                Ok(slid)
+            }
+            _ => Err(DexError::PostOnlyWouldCross { price, best_price }),
+        }
+    }
+
+    /// taker 遇到自己的挂单 maker（对手方最优挂单）时按自成交策略处理，写入 maker 的撤单事件，
+    /// maker 因此离开订单簿时再写一条 Out 事件；返回 taker 因此减少的数量（CancelProvide 为0）
+    fn prevent_self_trade(
+        &mut self,
+        market: &str,
+        taker: &Order,
+        maker: &Order,
+        self_trade_behavior: SelfTradeBehavior,
+        now: u64,
+    ) -> Result<u64, DexError> {
+        let book = match maker.side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        let (cancelled, decrement) = match self_trade_behavior {
+            SelfTradeBehavior::AbortTransaction => {
+                return Err(DexError::SelfTrade {
+                    maker_order_id: maker.id,
+                });
+            }
+            SelfTradeBehavior::CancelProvide => {
+                book.remove(maker.id);
+                self.release_order(maker)?;
+                (maker.quantity, 0)
+            }
+            SelfTradeBehavior::DecrementTake => {
+                let dec = taker.quantity.min(maker.quantity);
+                // 双方被抵消的数量都解冻（taker 按自己的限价冻结，maker 按挂单价冻结）
+                // 市价买单冻结的是预算，撮合结束后统一解冻未用完的部分
+                let unlocked_quote = match (&taker.side, &taker.order_type) {
+                    (Side::Bid, OrderType::Market) => 0,
+                    (Side::Bid, _) => self.config.notional(taker.price, dec)?,
+                    (Side::Ask, _) => self.config.notional(maker.price, dec)?,
+                };
+                if let Some(m) = book.best_mut() {
+                    m.quantity -= dec;
+                }
+                if book.best().map(|m| m.quantity == 0).unwrap_or(false) {
+                    book.pop_best();
+                }
+                let oo = self.open_orders.get_mut(&taker.owner).unwrap();
+                oo.unlock_quote(unlocked_quote)?;
+                oo.unlock_base(dec)?;
+                if dec == maker.quantity {
+                    oo.remove_order(maker.id);
+                }
+                (dec, dec)
+            }
+        };
+        println!(
+            "自成交：订单 {} 撤销自己的挂单 {} 数量 {}",
+            taker.id, maker.id, cancelled
+        );
+        self.event_queue.push(Event::order_event(
+            EventType::Cancel,
+            market,
+            maker,
+            cancelled,
+            now,
+        ))?;
+        if cancelled == maker.quantity {
+            self.push_out(market, maker, cancelled, now)?;
+        }
+        Ok(decrement)
+    }
+
+    /// 检查 owner 能否为新订单冻结 amount：挂单账户可用资金 + 钱包余额不足时返回 Insufficient*
+    /// now 为 Some 时计入 owner 已过期（尚未清理）的同侧挂单清理后解冻的资金
+    fn check_funds(
+        &self,
+        wallets: &Wallets,
+        owner: &str,
+        side: &Side,
+        amount: u64,
+        now: Option<u64>,
+    ) -> Result<(), DexError> {
+        let oo = self.open_orders.get(owner);
+        let (free, mint, book) = match side {
+            Side::Bid => (
+                oo.map_or(0, |oo| oo.quote_free),
+                &self.config.quote_mint,
+                &self.bids,
+            ),
+            Side::Ask => (
+                oo.map_or(0, |oo| oo.base_free),
+                &self.config.base_mint,
+                &self.asks,
+            ),
+        };
+        let mut available = math::add(free, wallets.balance(owner, mint))?;
+        if let Some(now) = now {
+            for o in book
+                .iter()
+                .filter(|o| o.owner == owner && o.is_expired(now))
+            {
+                let released = match side {
+                    Side::Bid => self.config.notional(o.price, o.quantity)?,
+                    Side::Ask => o.quantity,
+                };
+                math::add_to(&mut available, released)?;
+            }
+        }
+        if available < amount {
+            return Err(match side {
+                Side::Bid => DexError::InsufficientQuote {
+                    needed: amount,
+                    available,
+                },
+                Side::Ask => DexError::InsufficientBase {
+                    needed: amount,
+                    available,
+                },
+            });
+        }
+        Ok(())
+    }
+
+    /// 为新订单冻结资金：优先使用挂单账户中的可用资金，不足部分从钱包转入挂单账户
+    /// 可用资金 + 钱包余额不足时返回 Insufficient*，不做任何变更
+    fn lock_funds(
+        &mut self,
+        wallets: &mut Wallets,
+        owner: &str,
+        side: &Side,
+        amount: u64,
+    ) -> Result<(), DexError> {
+        self.check_funds(wallets, owner, side, amount, None)?;
+        let oo = self.open_orders.entry(owner.to_string()).or_default();
+        let (free, total, wallet_amount) = match side {
+            Side::Bid => (
+                &mut oo.quote_free,
+                &mut oo.quote_total,
+                wallets.balance_mut(owner, &self.config.quote_mint),
+            ),
+            Side::Ask => (
+                &mut oo.base_free,
+                &mut oo.base_total,
+                wallets.balance_mut(owner, &self.config.base_mint),
+            ),
+        };
+        let from_free = amount.min(*free);
+        let from_wallet = amount - from_free;
+        *total = math::add(*total, from_wallet)?;
+        *free -= from_free;
+        *wallet_amount -= from_wallet;
+        Ok(())
+    }
+
+    /// 订单离开订单簿（撤单/过期/被挤出）：剩余冻结资金解冻到挂单账户的可用余额
+    fn release_order(&mut self, order: &Order) -> Result<(), DexError> {
+        let locked_quote = match order.side {
+            Side::Bid => self.config.notional(order.price, order.quantity)?,
+            Side::Ask => 0,
+        };
+        let oo = self.open_orders.entry(order.owner.clone()).or_default();
+        oo.remove_order(order.id);
+        match order.side {
+            Side::Bid => oo.unlock_quote(locked_quote),
+            Side::Ask => oo.unlock_base(order.quantity),
+        }
+    }
+
+    /// 订单簿（slab）已满时被挤出的订单：退还冻结资金并记录撤单和 Out 事件
+    fn release_bumped_order(
+        &mut self,
+        market: &str,
+        order: Order,
+        now: u64,
+    ) -> Result<(), DexError> {
+        self.release_order(&order)?;
+        println!("订单簿已满，订单 {} 被挤出", order.id);
+        self.event_queue.push(Event::order_event(
+            EventType::Cancel,
+            market,
+            &order,
+            order.quantity,
+            now,
+        ))?;
+        self.push_out(market, &order, order.quantity, now)
+    }
+
+    /// 完全成交的挂单离开订单簿：从挂单账户中移除并写入 Out 事件（冻结资金已在成交时全部扣除）
+    fn remove_filled_order(
+        &mut self,
+        market: &str,
+        order: &Order,
+        now: u64,
+    ) -> Result<(), DexError> {
+        if let Some(oo) = self.open_orders.get_mut(&order.owner) {
+            oo.remove_order(order.id);
+        }
+        self.push_out(market, order, 0, now)
+    }
+
+    /// 挂单离开订单簿（完全成交/撤单/过期/被挤出/自成交撤销）后写入 Out 事件，
+    /// quantity 为离开时撤销的未成交数量（完全成交为0）
+    fn push_out(
+        &mut self,
+        market: &str,
+        order: &Order,
+        quantity: u64,
+        now: u64,
+    ) -> Result<(), DexError> {
+        self.event_queue.push(Event::order_event(
+            EventType::Out,
+            market,
+            order,
+            quantity,
+            now,
+        ))?;
+        Ok(())
+    }
+
+    /// 批量撮合（对前 n 个订单尝试撮合）
+    /// side: 批量撮合哪一侧（Bid/Ask）
+    /// n: 前n个订单
+    /// 返回每个订单各自的下单结果（单个失败不影响其余订单）
+    /// 市价买单以原挂单的成交金额作为报价币预算
+    pub fn batch_match(
+        &mut self,
+        wallets: &mut Wallets,
+        market: &str,
+        side: Side,
+        n: usize,
+        now: u64,
+        order_type: OrderType,
+    ) -> Vec<Result<PlaceOrderOutcome, DexError>> {
+        let mut results = vec![];
+        if let Err(err) = self.clean_expired_orders(now, market) {
+            results.push(Err(err));
+            return results;
+        }
+        match side {
+            Side::Bid => {
+                let bids: Vec<Order> = self.bids.iter().take(n).cloned().collect();
+                for order in bids.iter() {
+                    let quantity = match order_type {
+                        OrderType::Market => self.config.notional(order.price, order.quantity),
+                        _ => Ok(order.quantity),
+                    };
+                    results.push(quantity.and_then(|quantity| {
+                        self.place_order(
+                            wallets,
+                            market,
+                            &order.owner,
+                            Side::Bid,
+                            order.price,
+                            quantity,
+                            now,
+                            order.expire_ts,
+                            order_type.clone(),
+                            0,
+                            SelfTradeBehavior::default(),
+                            None,
+                        )
+                    }));
+                }
+            }
+            Side::Ask => {
+                let asks: Vec<Order> = self.asks.iter().take(n).cloned().collect();
+                for order in asks.iter() {
+                    results.push(self.place_order(
+                        wallets,
+                        market,
+                        &order.owner,
+                        Side::Ask,
+                        order.price,
+                        order.quantity,
+                        now,
+                        order.expire_ts,
+                        order_type.clone(),
+                        0,
+                        SelfTradeBehavior::default(),
+                        None,
+                    ));
+                }
+            }
+        }
+        results
+    }
+
+    /// 批量撤销指定用户的订单
+    /// ids: 要撤销的订单id列表
+    /// 原子操作：任一订单不存在（或不属于该用户）则返回错误，不撤销任何订单
+    pub fn batch_cancel(
+        &mut self,
+        market: &str,
+        user: &str,
+        ids: &[u64],
+        now: u64,
+    ) -> Result<(), DexError> {
+        for id in ids {
+            let owned = self
+                .bids
+                .get(*id)
+                .or_else(|| self.asks.get(*id))
+                .map(|o| o.owner == user)
+                .unwrap_or(false);
+            if !owned {
+                return Err(DexError::OrderNotFound(*id));
+            }
+        }
+        // 每个挂单写撤单和 Out 两条事件
+        self.check_event_space(2 * ids.len())?;
+        self.make_event_space(2 * ids.len());
+        for id in ids {
+            let Some(order) = self.bids.remove(*id).or_else(|| self.asks.remove(*id)) else {
+                // 重复的订单ID，已在前面撤销
+                continue;
+            };
+            self.release_order(&order)?;
+            self.event_queue.push(Event::order_event(
+                EventType::Cancel,
+                market,
+                &order,
+                order.quantity,
+                now,
+            ))?;
+            self.push_out(market, &order, order.quantity, now)?;
+        }
+        self.debug_check();
+        Ok(())
+    }
+
+    /// 修改挂单的价格和数量（保留订单ID和客户端订单ID），写入改单事件，返回是否保留了时间优先级
+    /// - 价格不变、数量不增加：原地减少数量，保留时间优先级，多冻结的资金解冻
+    /// - 改价或增加数量：重新排队（排在新价位已有挂单之后），按新的价格和数量调整冻结资金，
+    ///   需要多冻结的部分优先使用挂单账户的可用资金，不足部分从钱包转入
+    ///
+    /// 改单不会成交：新价格会与对手方挂单成交时返回 AmendWouldCross（需要立即成交请撤单后重新下单）
+    /// 原子操作：订单不存在（或不属于该用户）/ 价格数量非法 / 余额不足 / 事件队列已满时返回错误，不做任何变更
+    #[allow(clippy::too_many_arguments)]
+    pub fn replace_order(
+        &mut self,
+        wallets: &mut Wallets,
+        market: &str,
+        user: &str,
+        order_id: u64,
+        new_price: u64,
+        new_quantity: u64,
+        now: u64,
+    ) -> Result<bool, DexError> {
+        let order = self
+            .bids
+            .get(order_id)
+            .or_else(|| self.asks.get(order_id))
+            .filter(|o| o.owner == user)
+            .cloned()
+            .ok_or(DexError::OrderNotFound(order_id))?;
+        if new_quantity == 0 {
+            return Err(DexError::InvalidQuantity);
+        }
+        self.config.validate_order(new_price, new_quantity)?;
+        let best_opposite = match order.side {
+            Side::Bid => self.asks.best_price(),
+            Side::Ask => self.bids.best_price(),
+        };
+        if let Some(best_price) = best_opposite
+            && match order.side {
+                Side::Bid => new_price >= best_price,
+                Side::Ask => new_price <= best_price,
+            }
+        {
+            return Err(DexError::AmendWouldCross {
+                price: new_price,
+                best_price,
+            });
+        }
+        self.check_event_space(1)?;
+
+        // 挂单冻结的资金：买单为 价格 × 数量 的报价币，卖单为主币数量
+        let (old_locked, new_locked) = match order.side {
+            Side::Bid => (
+                self.config.notional(order.price, order.quantity)?,
+                self.config.notional(new_price, new_quantity)?,
+            ),
+            Side::Ask => (order.quantity, new_quantity),
+        };
+        if new_locked > old_locked {
+            self.lock_funds(wallets, user, &order.side, new_locked - old_locked)?;
+        } else {
+            let oo = self.open_orders.get_mut(user).unwrap();
+            match order.side {
+                Side::Bid => oo.unlock_quote(old_locked - new_locked)?,
+                Side::Ask => oo.unlock_base(old_locked - new_locked)?,
+            }
+        }
+        self.make_event_space(1);
+
+        let keeps_priority = new_price == order.price && new_quantity <= order.quantity;
+        let book = match order.side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        if keeps_priority {
+            if let Some(resting) = book.get_mut(order_id) {
+                resting.quantity = new_quantity;
+            }
+        } else if let Some(mut resting) = book.remove(order_id) {
+            resting.price = new_price;
+            resting.quantity = new_quantity;
+            // 刚移除了一个挂单，slab 一定有空位，不会挤出其他挂单
+            let bumped = book.insert(resting);
+            debug_assert!(bumped.is_none(), "re-queued order bumped {:?}", bumped);
+        }
+        println!(
+            "改单：订单 {} 价格 {} -> {}，数量 {} -> {}，{}时间优先级",
+            order_id,
+            order.price,
+            new_price,
+            order.quantity,
+            new_quantity,
+            if keeps_priority {
+                "保留"
+            } else {
+                "重新排队，失去"
+            }
+        );
+        self.event_queue.push(Event {
+            price: Some(new_price),
+            ..Event::order_event(EventType::Amend, market, &order, new_quantity, now)
+        })?;
+        self.debug_check();
+        Ok(keeps_priority)
+    }
+
+    /// 用户在本市场的挂单ID（升序，即先下的在前），side 为 Some 时只取该方向，最多 limit 个
+    /// 通过挂单账户记录的挂单ID逐个定位订单，耗时与该用户的挂单数成正比，与订单簿大小无关
+    fn user_order_ids(&self, user: &str, side: Option<&Side>, limit: usize) -> Vec<u64> {
+        let Some(oo) = self.open_orders.get(user) else {
+            return vec![];
+        };
+        oo.orders
+            .keys()
+            .copied()
+            .filter(|&id| match side {
+                None => true,
+                Some(Side::Bid) => self.bids.get(id).is_some(),
+                Some(Side::Ask) => self.asks.get(id).is_some(),
+            })
+            .take(limit)
+            .collect()
+    }
+
+    /// 撤销用户在本市场的全部挂单（side 为 Some 时只撤销该方向），返回被撤销的挂单ID（升序）
+    /// 原子操作：事件队列空间不足时返回 QueueFull，不撤销任何订单
+    pub fn cancel_all(
+        &mut self,
+        market: &str,
+        user: &str,
+        side: Option<Side>,
+        now: u64,
+    ) -> Result<Vec<u64>, DexError> {
+        let ids = self.user_order_ids(user, side.as_ref(), usize::MAX);
+        self.batch_cancel(market, user, &ids, now)?;
+        Ok(ids)
+    }
+
+    /// 撤销用户在本市场最多 limit 个挂单（先下的先撤），返回被撤销的挂单ID（升序）
+    /// 挂单很多时可分多次调用，避免一次写入过多撤单事件
+    pub fn cancel_up_to(
+        &mut self,
+        market: &str,
+        user: &str,
+        limit: usize,
+        now: u64,
+    ) -> Result<Vec<u64>, DexError> {
+        let ids = self.user_order_ids(user, None, limit);
+        self.batch_cancel(market, user, &ids, now)?;
+        Ok(ids)
+    }
+
+    /// 按客户端订单ID查找用户的挂单（client_order_id 为0 或不存在时返回None）
+    pub fn order_by_client_id(&self, user: &str, client_order_id: u64) -> Option<&Order> {
+        let id = self
+            .open_orders
+            .get(user)?
+            .find_by_client_id(client_order_id)?;
+        self.bids.get(id).or_else(|| self.asks.get(id))
+    }
+
+    /// 按客户端订单ID撤单（对齐 Serum 的 CancelOrderByClientIdV2），返回被撤销的挂单ID
+    /// 用户没有该客户端订单ID的挂单时返回 ClientOrderIdNotFound，不做任何变更
+    pub fn cancel_order_by_client_id(
+        &mut self,
+        market: &str,
+        user: &str,
+        client_order_id: u64,
+        now: u64,
+    ) -> Result<u64, DexError> {
+        let order_id = self
+            .order_by_client_id(user, client_order_id)
+            .map(|o| o.id)
+            .ok_or(DexError::ClientOrderIdNotFound(client_order_id))?;
+        self.batch_cancel(market, user, &[order_id], now)?;
+        Ok(order_id)
+    }
+
+    /// crank：处理最多 limit 条事件，把成交事件中 maker 应得的资金入账，并弹出已被所有consumer读过的事件
+    /// 对齐 Serum 的 consume_events 指令；即时结算模式下 maker 已在撮合时入账，只推进消费指针
+    /// 每条事件入账成功后才推进 crank 的消费指针，入账失败时返回错误，失败的事件留待下次 crank 重试
+    /// 返回本次处理的事件数
+    pub fn crank(&mut self, limit: usize) -> Result<usize, DexError> {
+        // 即时结算模式下创建市场时没有注册 crank，第一次 crank 时注册
+        self.event_queue.register_consumer(CRANK_CONSUMER);
+        let start = self
+            .event_queue
+            .consumer_position(CRANK_CONSUMER)
+            .expect("crank consumer 已注册");
+        let end = self
+            .event_queue
+            .next_seq
+            .min(start.saturating_add(limit as u64));
+        for seq in start..end {
+            if self.config.settlement_mode == SettlementMode::Deferred
+                && let Some(event) = self.event_queue.get(seq)
+            {
+                Self::credit_maker(&self.config, &mut self.open_orders, event)?;
+            }
+            self.event_queue.advance_consumer(CRANK_CONSUMER, seq + 1);
+        }
+        self.event_queue.pop_consumed();
+        self.debug_check();
+        Ok((end - start) as usize)
+    }
+
+    /// 延迟结算：按成交事件给 maker 入账（非成交事件不做处理）
+    fn credit_maker(
+        config: &MarketConfig,
+        open_orders: &mut HashMap<String, OpenOrders>,
+        event: &Event,
+    ) -> Result<(), DexError> {
+        if !matches!(event.event_type, EventType::Fill) {
+            return Ok(());
+        }
+        let (Some(maker), Some(price), Some(side)) = (&event.maker, event.price, &event.side)
+        else {
+            return Ok(());
+        };
+        let notional = config.notional(price, event.quantity)?;
+        // 在副本上入账，全部成功才写回，失败时挂单账户保持原样
+        let mut maker_oo = open_orders.get(maker).cloned().unwrap_or_default();
+        // 事件的 side 是 taker 的方向，maker 在另一侧
+        match side {
+            Side::Bid => {
+                maker_oo.debit_locked_base(event.quantity)?;
+                maker_oo.credit_quote(math::add(notional, event.rebate)?)?;
+            }
+            Side::Ask => {
+                maker_oo.debit_locked_quote(notional)?;
+                maker_oo.credit_base(event.quantity)?;
+                maker_oo.credit_quote(event.rebate)?;
+            }
+        }
+        open_orders.insert(maker.clone(), maker_oo);
+        Ok(())
+    }
+
+    /// 用户在本市场的可用余额（钱包 + 挂单账户可用），返回 (主币, 报价币)
+    pub fn free_balance(&self, wallets: &Wallets, user: &str) -> Result<(u64, u64), DexError> {
+        let (oo_base, oo_quote) = self
+            .open_orders
+            .get(user)
+            .map(|oo| (oo.base_free, oo.quote_free))
+            .unwrap_or((0, 0));
+        Ok((
+            math::add(wallets.balance(user, &self.config.base_mint), oo_base)?,
+            math::add(wallets.balance(user, &self.config.quote_mint), oo_quote)?,
+        ))
+    }
+
+    /// 用户提现：先动用挂单账户的可用资金，再动用钱包，被挂单冻结的资金不可提现
+    /// 任一币种超过可用余额返回 InsufficientFreeBalance，不做任何变更；成功时写入一条审计记录
+    pub fn withdraw(
+        &mut self,
+        wallets: &mut Wallets,
+        user: &str,
+        base: u64,
+        quote: u64,
+        now: u64,
+    ) -> Result<(), DexError> {
+        let (free_base, free_quote) = self.free_balance(wallets, user)?;
+        let oo = self.open_orders.entry(user.to_string()).or_default();
+        if base > free_base {
+            return Err(DexError::InsufficientFreeBalance {
+                token: self.config.base_mint.clone(),
+                requested: base,
+                free: free_base,
+                locked: oo.base_locked(),
+            });
+        }
+        if quote > free_quote {
+            return Err(DexError::InsufficientFreeBalance {
+                token: self.config.quote_mint.clone(),
+                requested: quote,
+                free: free_quote,
+                locked: oo.quote_locked(),
+            });
+        }
+
+        // 挂单账户中的可用资金先转回钱包，再从钱包提现
+        let from_oo = base.min(oo.base_free);
+        math::sub_from(&mut oo.base_free, from_oo)?;
+        math::sub_from(&mut oo.base_total, from_oo)?;
+        wallets.credit(user, &self.config.base_mint, from_oo)?;
+        let from_oo = quote.min(oo.quote_free);
+        math::sub_from(&mut oo.quote_free, from_oo)?;
+        math::sub_from(&mut oo.quote_total, from_oo)?;
+        wallets.credit(user, &self.config.quote_mint, from_oo)?;
+        wallets.withdraw(user, &self.config.base_mint, base)?;
+        wallets.withdraw(user, &self.config.quote_mint, quote)?;
+
+        self.withdrawals.push(WithdrawRecord {
+            user: user.to_string(),
+            base,
+            quote,
+            base_remaining: free_base - base,
+            quote_remaining: free_quote - quote,
+            timestamp: now,
+        });
+        println!(
+            "用户 {} 在本市场提现：主币 {}，报价币 {}",
+            user, base, quote
+        );
+        self.debug_check();
+        Ok(())
+    }
+
+    /// 结算：把用户挂单账户中的可用资金转回钱包，返回 (主币, 报价币)
+    /// 对齐 Serum 的 settle_funds 指令；被挂单冻结的资金不受影响
+    pub fn settle_funds(
+        &mut self,
+        wallets: &mut Wallets,
+        user: &str,
+    ) -> Result<(u64, u64), DexError> {
+        let Some(oo) = self.open_orders.get_mut(user) else {
+            return Ok((0, 0));
+        };
+        let (base, quote) = oo.take_free()?;
+        wallets.credit(user, &self.config.base_mint, base)?;
+        wallets.credit(user, &self.config.quote_mint, quote)?;
+        println!("用户 {} 结算：主币 {}，报价币 {}", user, base, quote);
+        self.debug_check();
+        Ok((base, quote))
+    }
+
+    /// 打印订单簿
+    pub fn print_book(&self) {
+        println!("买单簿: {:?}", self.bids);
+        println!("卖单簿: {:?}", self.asks);
+    }
+
+    /// 打印所有用户余额（钱包 + 挂单账户的 可用/总额）
+    pub fn print_balances(&self, wallets: &Wallets) {
+        for user in wallets.balances.keys() {
+            println!(
+                "用户 {} {}:{} {}:{}",
+                user,
+                self.config.base_mint,
+                wallets.balance(user, &self.config.base_mint),
+                self.config.quote_mint,
+                wallets.balance(user, &self.config.quote_mint)
+            );
+        }
+        for (user, oo) in &self.open_orders {
+            println!(
+                "用户 {} 挂单账户 主币:{}/{} 报价币:{}/{} 挂单:{:?}",
+                user,
+                oo.base_free,
+                oo.base_total,
+                oo.quote_free,
+                oo.quote_total,
+                oo.order_ids()
+            );
+        }
+    }
+
+    /// 打印平台手续费余额（手续费账户记账出错时返回 Overflow，不打印）
+    pub fn print_fee_receiver(&self) -> Result<(), DexError> {
+        println!(
+            "平台累计收取 taker 手续费(报价币): {}，支付 maker 返佣: {}，推荐人分成: {}，净收入: {}",
+            self.fee_receiver.taker_fees_collected,
+            self.fee_receiver.maker_rebates_paid,
+            self.fee_receiver.referrer_rebates_paid,
+            self.fee_receiver.net_fees()?
+        );
+        Ok(())
+    }
+
+    /// 打印事件队列
+    pub fn print_events(&self) {
+        println!("=== Event Queue（成交/撤单/过期历史）===");
+        for event in self.event_queue.iter() {
+            println!("{:?}", event);
+        }
+    }
+
+    /// 打印某consumer批量消费到的事件
+    pub fn print_event_consume(&mut self, consumer: &str, max_events: usize) {
+        let events = self.event_queue.consume_events(consumer, max_events);
+        println!("=== {} 消费到的事件 ===", consumer);
+        for event in events {
+            println!("{:?}", event);
+        }
+    }
+}
+
+/// 多市场管理器
+#[derive(Default)]
+pub struct Markets {
+    /// 市场状态集合（market name -> MarketState）
+    pub markets: HashMap<String, MarketState>,
+    /// 所有市场共用的用户钱包
+    pub wallets: Wallets,
+    /// 按币种提现的审计记录（按市场提现记录在各市场的 withdrawals 中）
+    pub token_withdrawals: Vec<TokenWithdrawRecord>,
+}
+
+impl Markets {
+    /// 新建Markets实例
+    pub fn new() -> Self {
+        Self {
+            markets: HashMap::new(),
+            wallets: Wallets::new(),
+            token_withdrawals: Vec::new(),
+        }
+    }
+
+    /// 按配置新建市场（配置非法返回 InvalidMarketConfig；同名市场已存在返回 MarketAlreadyExists，原市场保持不变）
+    pub fn create_market(&mut self, market: &str, config: MarketConfig) -> Result<(), DexError> {
+        if self.markets.contains_key(market) {
+            return Err(DexError::MarketAlreadyExists(market.to_string()));
+        }
+        let state = MarketState::new(config)?;
+        let config = &state.config;
+        println!(
+            "新市场已创建: {}（{}/{}，base_lot {}，quote_lot {}，tick {}，结算模式 {:?}）",
+            market,
+            config.base_mint,
+            config.quote_mint,
+            config.base_lot_size,
+            config.quote_lot_size,
+            config.tick_size,
+            config.settlement_mode
+        );
+        self.markets.insert(market.to_string(), state);
+        Ok(())
+    }
+
+    /// 查找市场（不存在返回 MarketNotFound）
+    pub fn market(&self, market: &str) -> Result<&MarketState, DexError> {
+        self.markets
+            .get(market)
+            .ok_or_else(|| DexError::MarketNotFound(market.to_string()))
+    }
+
+    /// 查找市场（可变引用，不存在返回 MarketNotFound）
+    pub fn market_mut(&mut self, market: &str) -> Result<&mut MarketState, DexError> {
+        self.markets
+            .get_mut(market)
+            .ok_or_else(|| DexError::MarketNotFound(market.to_string()))
+    }
+
+    /// 用户充值（按市场的主币/报价币存入钱包）
+    pub fn deposit(
+        &mut self,
+        market: &str,
+        user: &str,
+        base: u64,
+        quote: u64,
+    ) -> Result<(), DexError> {
+        let (state, wallets) = self.market_and_wallets(market)?;
+        state.deposit(wallets, user, base, quote)?;
+        self.debug_check();
+        Ok(())
+    }
+
+    /// 用户提现（按市场的主币/报价币，超过可用余额返回 InsufficientFreeBalance）
+    pub fn withdraw(
+        &mut self,
+        market: &str,
+        user: &str,
+        base: u64,
+        quote: u64,
+        now: u64,
+    ) -> Result<(), DexError> {
+        let (state, wallets) = self.market_and_wallets(market)?;
+        state.withdraw(wallets, user, base, quote, now)?;
+        self.debug_check();
+        Ok(())
+    }
+
+    /// 按币种充值到用户钱包（所有市场共用，流通总量超出 u64 返回 Overflow）
+    pub fn deposit_token(&mut self, user: &str, token: &str, amount: u64) -> Result<(), DexError> {
+        self.wallets.deposit(user, token, amount)?;
+        self.debug_check();
+        println!("用户 {} 充值 {} {}", user, amount, token);
+        Ok(())
+    }
+
+    /// 按币种从用户钱包提现（余额不足返回 InsufficientFunds）；成功时写入一条审计记录
+    pub fn withdraw_token(
+        &mut self,
+        user: &str,
+        token: &str,
+        amount: u64,
+        now: u64,
+    ) -> Result<(), DexError> {
+        self.wallets.withdraw(user, token, amount)?;
+        self.token_withdrawals.push(TokenWithdrawRecord {
+            user: user.to_string(),
+            token: token.to_string(),
+            amount,
+            remaining: self.wallets.balance(user, token),
+            timestamp: now,
+        });
+        self.debug_check();
+        println!("用户 {} 提现 {} {}", user, amount, token);
+        Ok(())
+    }
+
+    /// 查询用户钱包中某种币的余额
+    pub fn balance(&self, user: &str, token: &str) -> u64 {
+        self.wallets.balance(user, token)
+    }
+
+    /// 同时取得市场（可变）和钱包，供需要动用钱包的操作使用
+    fn market_and_wallets(
+        &mut self,
+        market: &str,
+    ) -> Result<(&mut MarketState, &mut Wallets), DexError> {
+        let state = self
+            .markets
+            .get_mut(market)
+            .ok_or_else(|| DexError::MarketNotFound(market.to_string()))?;
+        Ok((state, &mut self.wallets))
+    }
+
+    /// 下单
+    #[allow(clippy::too_many_arguments)]
+    pub fn place_order(
+        &mut self,
+        market: &str,
+        owner: &str,
+        side: Side,
+        price: u64,
+        quantity: u64,
+        now: u64,
+        expire_ts: Option<u64>,
+        order_type: OrderType,
+        client_order_id: u64,
+        self_trade_behavior: SelfTradeBehavior,
+        referrer: Option<&str>,
+    ) -> Result<PlaceOrderOutcome, DexError> {
+        let (state, wallets) = self.market_and_wallets(market)?;
+        let outcome = state.place_order(
+            wallets,
+            market,
+            owner,
+            side,
+            price,
+            quantity,
+            now,
+            expire_ts,
+            order_type,
+            client_order_id,
+            self_trade_behavior,
+            referrer,
+        );
+        self.debug_check();
+        outcome
+    }
+
+    /// 管理员提取某市场的手续费到自己的钱包
+    pub fn sweep_fees(&mut self, market: &str, authority: &str, now: u64) -> Result<u64, DexError> {
+        let (state, wallets) = self.market_and_wallets(market)?;
+        let swept = state.sweep_fees(wallets, market, authority, now)?;
+        self.debug_check();
+        Ok(swept)
+    }
+
+    /// 跨市场手续费报表（按市场名排序）
+    pub fn fee_report(&self) -> Result<Vec<FeeReport>, DexError> {
+        let mut report = self
+            .markets
+            .iter()
+            .map(|(market, state)| state.fee_report(market))
+            .collect::<Result<Vec<FeeReport>, DexError>>()?;
+        report.sort_by(|a, b| a.market.cmp(&b.market));
+        Ok(report)
+    }
+
+    /// 打印跨市场手续费报表，以及按手续费币种汇总的净收入和未提取金额
+    /// 汇总溢出返回 Overflow
+    pub fn print_fee_report(&self) -> Result<(), DexError> {
+        let report = self.fee_report()?;
+        let mut totals: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+        for row in &report {
+            println!(
+                "[{}] taker 手续费 {}，maker 返佣 {}，推荐人分成 {}，净收入 {}，已提取 {}，未提取 {} ({})",
+                row.market,
+                row.taker_fees_collected,
+                row.maker_rebates_paid,
+                row.referrer_rebates_paid,
+                row.net_fees,
+                row.fees_swept,
+                row.unswept_fees,
+                row.quote_mint
+            );
+            let total = totals.entry(&row.quote_mint).or_default();
+            math::add_to(&mut total.0, row.net_fees)?;
+            math::add_to(&mut total.1, row.unswept_fees)?;
+        }
+        for (token, (net, unswept)) in totals {
+            println!("{} 合计：净收入 {}，未提取 {}", token, net, unswept);
+        }
+        Ok(())
+    }
+
+    /// 校验所有市场的不变量，以及每种币的总量守恒：
+    /// 流通总量（累计充值 - 累计提现）= 钱包余额之和 + 各市场持有的该币（见 MarketState::holdings）
+    pub fn check_all(&self) -> Result<(), DexError> {
+        let mut held: BTreeMap<&str, u64> = BTreeMap::new();
+        for (market, state) in &self.markets {
+            state.check_invariants().map_err(|err| match err {
+                DexError::InvariantViolation(reason) => {
+                    DexError::InvariantViolation(format!("[{}] {}", market, reason))
+                }
+                other => other,
+            })?;
+            let (base, quote) = state.holdings()?;
+            math::add_to(held.entry(&state.config.base_mint).or_default(), base)?;
+            math::add_to(held.entry(&state.config.quote_mint).or_default(), quote)?;
+        }
+        for token in self.wallets.supply.keys() {
+            held.entry(token).or_default();
+        }
+        for (token, held) in held {
+            let supply = self.wallets.supply(token);
+            let total = math::add(self.wallets.total_balance(token), held)?;
+            if total != supply {
+                return Err(DexError::InvariantViolation(format!(
+                    "{} 总量不守恒：钱包 + 市场共 {}，流通总量 {}",
+                    token, total, supply
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// 调试构建下每次变更后校验全部不变量
+    fn debug_check(&self) {
+        debug_assert_eq!(self.check_all(), Ok(()));
+    }
+
+    /// 查询推荐人在某市场累计获得的分成
+    pub fn referrer_rebates(&self, market: &str, referrer: &str) -> Result<u64, DexError> {
+        Ok(self.market(market)?.referrer_rebates(referrer))
+    }
+
+    /// 批量撮合
+    pub fn batch_match(
+        &mut self,
+        market: &str,
+        side: Side,
+        n: usize,
+        now: u64,
+        order_type: OrderType,
+    ) -> Result<Vec<Result<PlaceOrderOutcome, DexError>>, DexError> {
+        let (state, wallets) = self.market_and_wallets(market)?;
+        let results = state.batch_match(wallets, market, side, n, now, order_type);
+        self.debug_check();
+        Ok(results)
+    }
+
+    /// 批量撤销
+    pub fn batch_cancel(
+        &mut self,
+        market: &str,
+        user: &str,
+        ids: &[u64],
+        now: u64,
+    ) -> Result<(), DexError> {
+        let result = self
+            .market_mut(market)?
+            .batch_cancel(market, user, ids, now);
+        self.debug_check();
+        result
+    }
+
+    /// 修改挂单的价格和数量（保留订单ID），返回是否保留了时间优先级
+    pub fn replace_order(
+        &mut self,
+        market: &str,
+        user: &str,
+        order_id: u64,
+        new_price: u64,
+        new_quantity: u64,
+        now: u64,
+    ) -> Result<bool, DexError> {
+        let (state, wallets) = self.market_and_wallets(market)?;
+        let result = state.replace_order(
+            wallets,
+            market,
+            user,
+            order_id,
+            new_price,
+            new_quantity,
+            now,
+        );
+        self.debug_check();
+        result
+    }
+
+    /// 撤销用户在某市场的全部挂单（side 为 Some 时只撤销该方向），返回被撤销的挂单ID
+    pub fn cancel_all(
+        &mut self,
+        market: &str,
+        user: &str,
+        side: Option<Side>,
+        now: u64,
+    ) -> Result<Vec<u64>, DexError> {
+        let result = self.market_mut(market)?.cancel_all(market, user, side, now);
+        self.debug_check();
+        result
+    }
+
+    /// 撤销用户在某市场最多 limit 个挂单（先下的先撤），返回被撤销的挂单ID
+    pub fn cancel_up_to(
+        &mut self,
+        market: &str,
+        user: &str,
+        limit: usize,
+        now: u64,
+    ) -> Result<Vec<u64>, DexError> {
+        let result = self
+            .market_mut(market)?
+            .cancel_up_to(market, user, limit, now);
+        self.debug_check();
+        result
+    }
+
+    /// 按客户端订单ID撤单，返回被撤销的挂单ID
+    pub fn cancel_order_by_client_id(
+        &mut self,
+        market: &str,
+        user: &str,
+        client_order_id: u64,
+        now: u64,
+    ) -> Result<u64, DexError> {
+        let result =
+            self.market_mut(market)?
+                .cancel_order_by_client_id(market, user, client_order_id, now);
+        self.debug_check();
+        result
+    }
+
+    /// 结算用户在市场挂单账户中的可用资金，返回 (主币, 报价币)
+    pub fn settle_funds(&mut self, market: &str, user: &str) -> Result<(u64, u64), DexError> {
+        let (state, wallets) = self.market_and_wallets(market)?;
+        let settled = state.settle_funds(wallets, user)?;
+        self.debug_check();
+        Ok(settled)
+    }
+
+    /// 打印市场订单簿
+    pub fn print_market_book(&self, market: &str) {
+        if let Some(state) = self.markets.get(market) {
+            println!("=== 市场 {} 订单簿 ===", market);
+            state.print_book();
+        } else {
+            println!("市场 {} 不存在", market);
+        }
+    }
+
+    /// 打印市场余额
+    pub fn print_market_balances(&self, market: &str) {
+        if let Some(state) = self.markets.get(market) {
+            println!("=== 市场 {} 用户余额 ===", market);
+            state.print_balances(&self.wallets);
+        } else {
+            println!("市场 {} 不存在", market);
+        }
+    }
+
+    /// 打印市场手续费池
+    pub fn print_market_fee_receiver(&self, market: &str) -> Result<(), DexError> {
+        if let Some(state) = self.markets.get(market) {
+            println!("=== 市场 {} 平台手续费 ===", market);
+            state.print_fee_receiver()?;
+        }
+        Ok(())
+    }
+
+    /// 打印市场事件队列
+    pub fn print_market_events(&self, market: &str) {
+        if let Some(state) = self.markets.get(market) {
+            println!("=== 市场 {} Event Queue ===", market);
+            state.print_events();
+        }
+    }
+
+    /// 设置市场的手续费档位解析器
+    pub fn set_fee_tier_resolver(
+        &mut self,
+        market: &str,
+        resolver: Box<dyn FeeTierResolver>,
+    ) -> Result<(), DexError> {
+        self.market_mut(market)?.set_fee_tier_resolver(resolver);
+        Ok(())
+    }
+
+    /// crank：处理市场事件队列中最多 limit 条事件，返回处理的事件数
+    pub fn crank(&mut self, market: &str, limit: usize) -> Result<usize, DexError> {
+        let processed = self.market_mut(market)?.crank(limit)?;
+        self.debug_check();
+        Ok(processed)
+    }
+
+    /// 在市场事件队列上注册consumer
+    pub fn register_consumer(&mut self, market: &str, consumer: &str) -> Result<(), DexError> {
+        self.market_mut(market)?
+            .event_queue
+            .register_consumer(consumer);
+        Ok(())
+    }
+
+    /// 弹出市场事件队列中所有consumer都已读过的事件，返回弹出的事件数
+    pub fn pop_consumed_events(&mut self, market: &str) -> Result<usize, DexError> {
+        Ok(self.market_mut(market)?.event_queue.pop_consumed())
+    }
+
+    /// 打印市场中某consumer批量消费到的事件
+    pub fn print_market_event_consume(&mut self, market: &str, consumer: &str, max_events: usize) {
+        if let Some(state) = self.markets.get_mut(market) {
+            state.print_event_consume(consumer, max_events);
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use crate::openbook::{Order, Side};
+
+/// 节点句柄（节点在 slab 数组中的下标）
+pub type NodeHandle = u32;
+
+/// Slab 错误类型
+/// 对齐 Serum DEX critbit.rs 中的 SlabError
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlabError {
+    /// 节点空间已用完（slab 容量固定）
+    OutOfSpace,
+}
+
+/// 计算订单在 slab 中的 key：高64位为价格，低64位为入簿序号
+/// 与 Serum 一致，买单的序号取反（!seq），这样同价位时越早的买单 key 越大：
+/// - 买单簿 find_max 即为最优买单（最高价、最早）
+/// - 卖单簿 find_min 即为最优卖单（最低价、最早）
+pub fn order_key(side: &Side, price: u64, seq: u64) -> u128 {
+    let lower = match side {
+        Side::Bid => !seq,
+        Side::Ask => seq,
+    };
+    ((price as u128) << 64) | lower as u128
+}
+
+/// 内部节点：记录左右子树 key 的公共前缀长度（critbit 位置）
+#[derive(Debug, Clone)]
+pub struct InnerNode {
+    /// 公共前缀长度（第 prefix_len 位即为区分左右子树的 critbit）
+    pub prefix_len: u32,
+    /// 子树中任意一个叶子的 key（仅前 prefix_len 位有意义）
+    pub key: u128,
+    /// 左右子节点（children[0] 为 critbit=0 的一侧）
+    pub children: [NodeHandle; 2],
+}
+
+impl InnerNode {
+    /// 按 critbit 决定向左还是向右走
+    fn walk_down(&self, search_key: u128) -> (NodeHandle, bool) {
+        let crit_bit_mask = (1u128 << 127) >> self.prefix_len;
+        let crit_bit = (search_key & crit_bit_mask) != 0;
+        (self.children[crit_bit as usize], crit_bit)
+    }
+}
+
+/// 叶子节点：一个挂单
+#[derive(Debug, Clone)]
+pub struct LeafNode {
+    /// price << 64 | seq_num（见 order_key）
+    pub key: u128,
+    /// 挂单本身
+    pub order: Order,
+}
+
+impl LeafNode {
+    /// 新建叶子节点
+    pub fn new(key: u128, order: Order) -> Self {
+        Self { key, order }
+    }
+
+    /// key 的高64位即价格
+    pub fn price(&self) -> u64 {
+        (self.key >> 64) as u64
+    }
+}
+
+/// slab 中的节点
+#[derive(Debug, Clone)]
+enum Node {
+    /// 从未使用过的节点（位于 bump_index 之后）
+    Uninitialized,
+    /// 内部节点
+    Inner(InnerNode),
+    /// 叶子节点
+    Leaf(LeafNode),
+    /// 已释放的节点（空闲链表，next 为下一个空闲节点）
+    Free { next: Option<NodeHandle> },
+}
+
+impl Node {
+    fn key(&self) -> Option<u128> {
+        match self {
+            Node::Inner(inner) => Some(inner.key),
+            Node::Leaf(leaf) => Some(leaf.key),
+            _ => None,
+        }
+    }
+}
+
+/// Serum 风格的 critbit 树订单簿存储
+/// Slab is a fixed-capacity node arena holding a crit-bit tree keyed by
+/// price << 64 | seq_num, mirroring serum-dex/dex/src/critbit.rs:
+/// - 节点预先分配，容量固定（链上账户大小固定）
+/// - 释放的节点进入空闲链表，分配时优先复用
+/// - n 个挂单需要 2n - 1 个节点（n 个叶子 + n - 1 个内部节点）
+#[derive(Debug, Clone)]
+pub struct Slab {
+    /// 节点数组（长度即容量）
+    nodes: Vec<Node>,
+    /// 下一个从未使用过的节点下标
+    bump_index: u32,
+    /// 空闲链表长度
+    free_list_len: u32,
+    /// 空闲链表头
+    free_list_head: Option<NodeHandle>,
+    /// 根节点
+    root_node: Option<NodeHandle>,
+    /// 叶子（挂单）数量
+    leaf_count: u32,
+}
+
+impl Slab {
+    /// 新建容量为 capacity 个节点的 slab
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            nodes: vec![Node::Uninitialized; capacity],
+            bump_index: 0,
+            free_list_len: 0,
+            free_list_head: None,
+            root_node: None,
+            leaf_count: 0,
+        }
+    }
+
+    /// 节点容量
+    pub fn capacity(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// 叶子（挂单）数量
+    pub fn len(&self) -> usize {
+        self.leaf_count as usize
+    }
+
+    /// 是否为空
+    pub fn is_empty(&self) -> bool {
+        self.leaf_count == 0
+    }
+
+    /// 根节点
+    pub fn root(&self) -> Option<NodeHandle> {
+        self.root_node
+    }
+
+    /// 按句柄读取叶子节点
+    pub fn get_leaf(&self, handle: NodeHandle) -> Option<&LeafNode> {
+        match self.nodes.get(handle as usize)? {
+            Node::Leaf(leaf) => Some(leaf),
+            _ => None,
+        }
+    }
+
+    /// 按句柄读取叶子节点（可变引用，只允许修改订单内容，不允许修改 key）
+    pub fn get_leaf_mut(&mut self, handle: NodeHandle) -> Option<&mut Order> {
+        match self.nodes.get_mut(handle as usize)? {
+            Node::Leaf(leaf) => Some(&mut leaf.order),
+            _ => None,
+        }
+    }
+
+    /// 插入叶子节点
+    /// 返回新叶子的句柄；若 key 已存在则覆盖，并返回旧叶子
+    pub fn insert_leaf(
+        &mut self,
+        new_leaf: &LeafNode,
+    ) -> Result<(NodeHandle, Option<LeafNode>), SlabError> {
+        let mut root = match self.root_node {
+            Some(h) => h,
+            None => {
+                let handle = self.alloc(Node::Leaf(new_leaf.clone()))?;
+                self.root_node = Some(handle);
+                self.leaf_count = 1;
+                return Ok((handle, None));
+            }
+        };
+        loop {
+            let root_contents = self.nodes[root as usize].clone();
+            let root_key = root_contents.key().unwrap();
+            if let Node::Leaf(old_leaf) = &root_contents
+                && old_leaf.key == new_leaf.key
+            {
+                // 相同 key：直接覆盖旧叶子
+                self.nodes[root as usize] = Node::Leaf(new_leaf.clone());
+                return Ok((root, Some(old_leaf.clone())));
+            }
+            let shared_prefix_len = (root_key ^ new_leaf.key).leading_zeros();
+            if let Node::Inner(inner) = &root_contents {
+                // 新 key 与该子树共享全部前缀，继续向下找插入位置
+                if shared_prefix_len >= inner.prefix_len {
+                    root = inner.walk_down(new_leaf.key).0;
+                    continue;
+                }
+            }
+
+            // 在当前位置原地生成新的内部节点，作为新叶子和原子树的最近公共祖先
+            let crit_bit_mask = (1u128 << 127) >> shared_prefix_len;
+            let new_leaf_crit_bit = (crit_bit_mask & new_leaf.key) != 0;
+
+            let new_leaf_handle = self.alloc(Node::Leaf(new_leaf.clone()))?;
+            let moved_root_handle = match self.alloc(root_contents) {
+                Ok(h) => h,
+                Err(err) => {
+                    self.free(new_leaf_handle);
+                    return Err(err);
+                }
+            };
+            let mut children = [0; 2];
+            children[new_leaf_crit_bit as usize] = new_leaf_handle;
+            children[!new_leaf_crit_bit as usize] = moved_root_handle;
+            self.nodes[root as usize] = Node::Inner(InnerNode {
+                prefix_len: shared_prefix_len,
+                key: new_leaf.key,
+                children,
+            });
+            self.leaf_count += 1;
+            return Ok((new_leaf_handle, None));
+        }
+    }
+
+    /// 按 key 删除叶子节点，返回被删除的叶子
+    pub fn remove_by_key(&mut self, search_key: u128) -> Option<LeafNode> {
+        let mut parent_h = self.root_node?;
+        let (mut child_h, mut crit_bit) = match &self.nodes[parent_h as usize] {
+            Node::Leaf(leaf) if leaf.key == search_key => {
+                // 删除的是唯一的根叶子
+                self.root_node = None;
+                self.leaf_count = 0;
+                return match self.free(parent_h) {
+                    Some(Node::Leaf(leaf)) => Some(leaf),
+                    _ => unreachable!(),
+                };
+            }
+            Node::Leaf(_) => return None,
+            Node::Inner(inner) => inner.walk_down(search_key),
+            _ => unreachable!(),
+        };
+        loop {
+            match &self.nodes[child_h as usize] {
+                Node::Inner(inner) => {
+                    let (grandchild_h, grandchild_crit_bit) = inner.walk_down(search_key);
+                    parent_h = child_h;
+                    child_h = grandchild_h;
+                    crit_bit = grandchild_crit_bit;
+                }
+                Node::Leaf(leaf) => {
+                    if leaf.key != search_key {
+                        return None;
+                    }
+                    break;
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        // 用父节点的另一个子节点替换父节点
+        let other_child_h = match &self.nodes[parent_h as usize] {
+            Node::Inner(inner) => inner.children[!crit_bit as usize],
+            _ => unreachable!(),
+        };
+        let other_child = self.free(other_child_h).unwrap();
+        self.nodes[parent_h as usize] = other_child;
+        self.leaf_count -= 1;
+        match self.free(child_h) {
+            Some(Node::Leaf(leaf)) => Some(leaf),
+            _ => unreachable!(),
+        }
+    }
+
+    /// 按 key 查找叶子节点
+    pub fn find_by_key(&self, search_key: u128) -> Option<NodeHandle> {
+        let mut node_handle = self.root_node?;
+        loop {
+            match &self.nodes[node_handle as usize] {
+                Node::Inner(inner) => node_handle = inner.walk_down(search_key).0,
+                Node::Leaf(leaf) => {
+                    return if leaf.key == search_key {
+                        Some(node_handle)
+                    } else {
+                        None
+                    };
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// key 最小的叶子
+    pub fn find_min(&self) -> Option<NodeHandle> {
+        self.find_min_max(false)
+    }
+
+    /// key 最大的叶子
+    pub fn find_max(&self) -> Option<NodeHandle> {
+        self.find_min_max(true)
+    }
+
+    /// 删除 key 最小的叶子
+    pub fn remove_min(&mut self) -> Option<LeafNode> {
+        let key = self.get_leaf(self.find_min()?)?.key;
+        self.remove_by_key(key)
+    }
+
+    /// 删除 key 最大的叶子
+    pub fn remove_max(&mut self) -> Option<LeafNode> {
+        let key = self.get_leaf(self.find_max()?)?.key;
+        self.remove_by_key(key)
+    }
+
+    /// 按 key 升序遍历所有叶子
+    pub fn iter(&self) -> SlabIter<'_> {
+        SlabIter::new(self, true)
+    }
+
+    /// 按 key 降序遍历所有叶子
+    pub fn iter_rev(&self) -> SlabIter<'_> {
+        SlabIter::new(self, false)
+    }
+
+    fn find_min_max(&self, find_max: bool) -> Option<NodeHandle> {
+        let mut root = self.root_node?;
+        loop {
+            match &self.nodes[root as usize] {
+                Node::Inner(inner) => root = inner.children[find_max as usize],
+                Node::Leaf(_) => return Some(root),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// 分配一个节点：优先复用空闲链表，其次使用从未用过的节点
+    fn alloc(&mut self, node: Node) -> Result<NodeHandle, SlabError> {
+        if let Some(handle) = self.free_list_head {
+            let next = match &self.nodes[handle as usize] {
+                Node::Free { next } => *next,
+                _ => unreachable!(),
+            };
+            self.free_list_head = next;
+            self.free_list_len -= 1;
+            self.nodes[handle as usize] = node;
+            return Ok(handle);
+        }
+        if self.bump_index as usize >= self.nodes.len() {
+            return Err(SlabError::OutOfSpace);
+        }
+        let handle = self.bump_index;
+        self.bump_index += 1;
+        self.nodes[handle as usize] = node;
+        Ok(handle)
+    }
+
+    /// 释放一个节点（放入空闲链表），返回节点原内容
+    fn free(&mut self, handle: NodeHandle) -> Option<Node> {
+        match self.nodes.get(handle as usize)? {
+            Node::Inner(_) | Node::Leaf(_) => {}
+            _ => return None,
+        }
+        let node = std::mem::replace(
+            &mut self.nodes[handle as usize],
+            Node::Free {
+                next: self.free_list_head,
+            },
+        );
+        self.free_list_head = Some(handle);
+        self.free_list_len += 1;
+        Some(node)
+    }
+}
+
+/// Slab 有序遍历器（深度优先，按 key 升序或降序）
+pub struct SlabIter<'a> {
+    slab: &'a Slab,
+    stack: Vec<NodeHandle>,
+    ascending: bool,
+}
+
+impl<'a> SlabIter<'a> {
+    fn new(slab: &'a Slab, ascending: bool) -> Self {
+        Self {
+            slab,
+            stack: slab.root_node.into_iter().collect(),
+            ascending,
+        }
+    }
+}
+
+impl<'a> Iterator for SlabIter<'a> {
+    type Item = &'a LeafNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(handle) = self.stack.pop() {
+            match &self.slab.nodes[handle as usize] {
+                Node::Inner(inner) => {
+                    // 先压入后访问的子树
+                    let first = !self.ascending as usize;
+                    self.stack.push(inner.children[1 - first]);
+                    self.stack.push(inner.children[first]);
+                }
+                Node::Leaf(leaf) => return Some(leaf),
+                _ => unreachable!(),
+            }
+        }
+        None
+    }
+}
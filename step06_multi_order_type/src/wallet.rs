@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use crate::error::DexError;
+use crate::math;
+
+/// 用户钱包账本（user -> token -> 数量）
+/// Wallets is the token-keyed ledger shared by every market.
+/// 对应 Serum 中用户的 SPL Token 账户：同一种币（如 SOL）在所有市场共用一份余额，
+/// 下单时从钱包转入对应市场的挂单账户，settle_funds 时转回钱包
+#[derive(Debug, Default, Clone)]
+pub struct Wallets {
+    /// user -> token -> 数量
+    pub balances: HashMap<String, HashMap<String, u64>>,
+    /// token -> 流通总量（累计充值 - 累计提现），用于校验总量守恒
+    pub supply: HashMap<String, u64>,
+}
+
+impl Wallets {
+    /// 新建空账本
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 查询用户某种币的余额
+    pub fn balance(&self, user: &str, token: &str) -> u64 {
+        self.balances
+            .get(user)
+            .and_then(|tokens| tokens.get(token))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// 某种币的流通总量（累计充值 - 累计提现）
+    pub fn supply(&self, token: &str) -> u64 {
+        self.supply.get(token).copied().unwrap_or(0)
+    }
+
+    /// 所有用户钱包中某种币的余额之和
+    pub fn total_balance(&self, token: &str) -> u64 {
+        self.balances
+            .values()
+            .map(|tokens| tokens.get(token).copied().unwrap_or(0))
+            .sum()
+    }
+
+    /// 用户某种币余额的可变引用（不存在则创建为0）
+    pub(crate) fn balance_mut(&mut self, user: &str, token: &str) -> &mut u64 {
+        self.balances
+            .entry(user.to_string())
+            .or_default()
+            .entry(token.to_string())
+            .or_default()
+    }
+
+    /// 充值（资金从外部进入，流通总量增加）
+    /// 流通总量超出 u64 时返回 Overflow，不做任何变更；因此任何余额、挂单账户、手续费都不会超过流通总量
+    pub fn deposit(&mut self, user: &str, token: &str, amount: u64) -> Result<(), DexError> {
+        let supply = math::add(self.supply(token), amount)?;
+        math::add_to(self.balance_mut(user, token), amount)?;
+        self.supply.insert(token.to_string(), supply);
+        Ok(())
+    }
+
+    /// 内部转入钱包（如结算、推荐人分成、手续费提取），资金来自市场，流通总量不变
+    pub(crate) fn credit(&mut self, user: &str, token: &str, amount: u64) -> Result<(), DexError> {
+        math::add_to(self.balance_mut(user, token), amount)
+    }
+
+    /// 提现（资金离开系统，流通总量减少；余额不足返回 InsufficientFunds，不做任何变更）
+    pub fn withdraw(&mut self, user: &str, token: &str, amount: u64) -> Result<(), DexError> {
+        let available = self.balance(user, token);
+        if available < amount {
+            return Err(DexError::InsufficientFunds {
+                token: token.to_string(),
+                needed: amount,
+                available,
+            });
+        }
+        let supply = math::sub(self.supply(token), amount)?;
+        math::sub_from(self.balance_mut(user, token), amount)?;
+        self.supply.insert(token.to_string(), supply);
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+//! 集成测试共用的辅助函数：随机数、市场配置和下单
+//! 每个测试文件用 `mod common;` 引入，用不到的函数不算死代码
+#![allow(dead_code)]
+
+use step06_multi_order_type::error::DexError;
+use step06_multi_order_type::market_config::MarketConfig;
+use step06_multi_order_type::openbook::{
+    Markets, OrderType, PlaceOrderOutcome, SelfTradeBehavior, SettlementMode, Side,
+};
+
+pub const MARKET: &str = "SOL/USDC";
+pub const FEE_BPS: u64 = 30;
+pub const REBATE_BPS: u64 = 10;
+pub const NOW: u64 = 1_000;
+
+/// 简单的线性同余随机数（不引入外部依赖，失败时可按 seed 复现）
+pub struct Lcg(pub u64);
+
+impl Lcg {
+    pub fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// 偏向边界的随机数：小数、2 的幂附近、u32/u64 上限附近
+    pub fn extreme(&mut self) -> u64 {
+        match self.below(5) {
+            0 => self.below(1_000) + 1,
+            1 => 1u64 << self.below(64),
+            2 => u32::MAX as u64 - self.below(1_000),
+            3 => u64::MAX - self.below(1_000),
+            _ => (self.next() << 31) | self.next(),
+        }
+    }
+}
+
+/// SOL/USDC 市场配置：taker 手续费 FEE_BPS，maker 返佣 REBATE_BPS
+pub fn config(settlement_mode: SettlementMode) -> MarketConfig {
+    MarketConfig {
+        taker_fee_bps: FEE_BPS,
+        maker_rebate_bps: REBATE_BPS,
+        settlement_mode,
+        ..MarketConfig::new("SOL", "USDC")
+    }
+}
+
+/// 在 MARKET 下单（时间 NOW，不过期，无客户端订单ID，自成交时减少 taker，无推荐人）
+pub fn place(
+    markets: &mut Markets,
+    owner: &str,
+    side: Side,
+    price: u64,
+    quantity: u64,
+    order_type: OrderType,
+) -> Result<PlaceOrderOutcome, DexError> {
+    place_referred(markets, owner, side, price, quantity, order_type, None)
+}
+
+/// 同 place，但带推荐人
+pub fn place_referred(
+    markets: &mut Markets,
+    owner: &str,
+    side: Side,
+    price: u64,
+    quantity: u64,
+    order_type: OrderType,
+    referrer: Option<&str>,
+) -> Result<PlaceOrderOutcome, DexError> {
+    markets.place_order(
+        MARKET,
+        owner,
+        side,
+        price,
+        quantity,
+        NOW,
+        None,
+        order_type,
+        0,
+        SelfTradeBehavior::DecrementTake,
+        referrer,
+    )
+}
@@ -0,0 +1,305 @@
+use step06_multi_order_type::error::DexError;
+use step06_multi_order_type::event_queue::{
+    DEFAULT_EVENT_QUEUE_CAPACITY, Event, EventQueue, EventType,
+};
+use step06_multi_order_type::market_config::MarketConfig;
+use step06_multi_order_type::openbook::{
+    MarketState, Markets, Order, OrderType, PlaceOrderOutcome, SelfTradeBehavior, Side,
+};
+use step06_multi_order_type::wallet::Wallets;
+
+mod common;
+
+use common::MARKET;
+
+fn event(order_id: u64) -> Event {
+    let order = Order {
+        id: order_id,
+        owner: "Alice".to_string(),
+        side: Side::Bid,
+        price: 10,
+        quantity: 1,
+        expire_ts: None,
+        order_type: OrderType::Limit,
+        client_order_id: 0,
+    };
+    Event::order_event(EventType::Fill, MARKET, &order, 1, 0)
+}
+
+fn place(
+    state: &mut MarketState,
+    wallets: &mut Wallets,
+    owner: &str,
+    side: Side,
+    price: u64,
+    quantity: u64,
+    order_type: OrderType,
+) -> Result<PlaceOrderOutcome, DexError> {
+    state.place_order(
+        wallets,
+        MARKET,
+        owner,
+        side,
+        price,
+        quantity,
+        0,
+        None,
+        order_type,
+        0,
+        SelfTradeBehavior::DecrementTake,
+        None,
+    )
+}
+
+fn order_ids(events: &[Event]) -> Vec<u64> {
+    events.iter().map(|e| e.order_id).collect()
+}
+
+#[test]
+fn test_ring_buffer_wraps_around() {
+    let mut queue = EventQueue::with_capacity(3);
+    for id in 0..3 {
+        assert_eq!(queue.push(event(id)), Ok(id));
+    }
+    assert!(queue.is_full());
+    assert_eq!(queue.push(event(3)), Err(DexError::QueueFull));
+
+    // 弹出队首后 head 前进，新的事件写入被释放的槽位
+    assert_eq!(queue.pop_front().map(|e| e.order_id), Some(0));
+    assert_eq!(queue.push(event(3)), Ok(3));
+    assert_eq!(queue.head_seq(), 1);
+    assert_eq!(queue.len(), 3);
+    let ids: Vec<u64> = queue.iter().map(|e| e.order_id).collect();
+    assert_eq!(ids, vec![1, 2, 3]);
+    assert!(queue.get(0).is_none());
+    assert_eq!(queue.get(3).map(|e| e.order_id), Some(3));
+}
+
+#[test]
+fn test_consumer_positions_survive_head_advance() {
+    let mut queue = EventQueue::with_capacity(4);
+    queue.register_consumer("crank");
+    for id in 0..4 {
+        queue.push(event(id)).unwrap();
+    }
+    assert_eq!(order_ids(&queue.consume_events("crank", 2)), vec![0, 1]);
+    assert_eq!(queue.consumer_positions["crank"], 2);
+
+    // head 前进两格，crank 的指针（序号2）依然指向同一个事件
+    queue.pop_front();
+    queue.pop_front();
+    queue.push(event(4)).unwrap();
+    assert_eq!(order_ids(&queue.consume_events("crank", 10)), vec![2, 3, 4]);
+
+    // 未注册的 consumer 每次都从队首读取，不记录消费指针
+    for _ in 0..2 {
+        assert_eq!(
+            order_ids(&queue.consume_events("frontend", 10)),
+            vec![2, 3, 4]
+        );
+    }
+    assert!(!queue.consumer_positions.contains_key("frontend"));
+    assert_eq!(queue.next_seq, 5);
+}
+
+#[test]
+fn test_place_order_refuses_when_queue_full() {
+    let mut state = MarketState {
+        event_queue: EventQueue::with_capacity(4),
+        ..MarketState::default()
+    };
+    let mut wallets = Wallets::new();
+    // 注册了 consumer 但还没消费，事件不能被腾出
+    state.event_queue.register_consumer("crank");
+    state.deposit(&mut wallets, "Alice", 0, 1000).unwrap();
+    state.deposit(&mut wallets, "Bob", 10, 0).unwrap();
+    for price in [10, 11, 12] {
+        place(
+            &mut state,
+            &mut wallets,
+            "Bob",
+            Side::Ask,
+            price,
+            1,
+            OrderType::Limit,
+        )
+        .unwrap();
+    }
+
+    // 吃掉三个卖单需要3条成交事件和3条 Out 事件，空间不够，整笔拒绝且不改变任何状态
+    let err = place(
+        &mut state,
+        &mut wallets,
+        "Alice",
+        Side::Bid,
+        12,
+        3,
+        OrderType::Limit,
+    )
+    .unwrap_err();
+    assert_eq!(err, DexError::QueueFull);
+    assert_eq!(state.asks.len(), 3);
+    assert_eq!(wallets.balance("Alice", &state.config.quote_mint), 1000);
+
+    // 只吃两个卖单（2条成交 + 2条 Out），可以撮合
+    let outcome = place(
+        &mut state,
+        &mut wallets,
+        "Alice",
+        Side::Bid,
+        12,
+        2,
+        OrderType::IOC,
+    )
+    .unwrap();
+    assert_eq!(outcome.filled_quantity, 2);
+    assert!(state.event_queue.is_full());
+
+    // 不产生事件的挂单不受影响，撤单需要写事件，被拒绝
+    let resting = place(
+        &mut state,
+        &mut wallets,
+        "Bob",
+        Side::Ask,
+        20,
+        1,
+        OrderType::Limit,
+    )
+    .unwrap();
+    let err = state
+        .batch_cancel(MARKET, "Bob", &[resting.order_id], 0)
+        .unwrap_err();
+    assert_eq!(err, DexError::QueueFull);
+    assert_eq!(state.asks.len(), 2);
+}
+
+#[test]
+fn test_pop_consumed_respects_slowest_consumer() {
+    let mut queue = EventQueue::with_capacity(8);
+    queue.register_consumer("crank");
+    queue.register_consumer("frontend");
+    for id in 0..5 {
+        queue.push(event(id)).unwrap();
+    }
+    assert_eq!(queue.low_watermark(), Some(0));
+
+    queue.consume_events("crank", 5);
+    queue.consume_events("frontend", 2);
+    assert_eq!(queue.low_watermark(), Some(2));
+
+    // 只弹出两个consumer都读过的事件
+    assert_eq!(queue.pop_consumed(), 2);
+    assert_eq!(queue.head_seq(), 2);
+    assert_eq!(queue.len(), 3);
+    assert_eq!(queue.pop_consumed(), 0);
+
+    // 弹出后序号保持不变，frontend 继续从序号2消费
+    assert_eq!(queue.get(2).map(|e| e.order_id), Some(2));
+    assert_eq!(
+        order_ids(&queue.consume_events("frontend", 10)),
+        vec![2, 3, 4]
+    );
+    assert_eq!(queue.pop_consumed(), 3);
+    assert!(queue.is_empty());
+    assert_eq!(queue.push(event(5)), Ok(5));
+
+    // 新注册的consumer从当前队首开始，会挡住后续弹出
+    queue.register_consumer("audit");
+    assert_eq!(queue.low_watermark(), Some(5));
+    assert_eq!(queue.pop_consumed(), 0);
+    queue.unregister_consumer("audit");
+    assert_eq!(queue.pop_consumed(), 0);
+    queue.consume_events("crank", 1);
+    queue.consume_events("frontend", 1);
+    assert_eq!(queue.pop_consumed(), 1);
+}
+
+#[test]
+fn test_pop_consumed_without_consumers_keeps_events() {
+    let mut queue = EventQueue::with_capacity(2);
+    queue.push(event(0)).unwrap();
+    assert_eq!(queue.low_watermark(), None);
+    assert_eq!(queue.pop_consumed(), 0);
+    assert_eq!(queue.len(), 1);
+
+    // 一次性读取不会注册 consumer，也就不会挡住已注册 consumer 之后的弹出
+    queue.register_consumer("crank");
+    assert_eq!(order_ids(&queue.consume_events("reader", 10)), vec![0]);
+    queue.consume_events("crank", 10);
+    assert_eq!(queue.low_watermark(), Some(1));
+    assert_eq!(queue.pop_consumed(), 1);
+}
+
+#[test]
+fn test_long_running_market_stays_bounded() {
+    let mut state = MarketState {
+        event_queue: EventQueue::with_capacity(4),
+        ..MarketState::default()
+    };
+    let mut wallets = Wallets::new();
+    state.event_queue.register_consumer("crank");
+    state.deposit(&mut wallets, "Alice", 0, 100_000).unwrap();
+    state.deposit(&mut wallets, "Bob", 1_000, 0).unwrap();
+
+    // 不断成交，crank 消费后弹出，队列永远不会满
+    for _ in 0..100 {
+        place(
+            &mut state,
+            &mut wallets,
+            "Bob",
+            Side::Ask,
+            10,
+            1,
+            OrderType::Limit,
+        )
+        .unwrap();
+        place(
+            &mut state,
+            &mut wallets,
+            "Alice",
+            Side::Bid,
+            10,
+            1,
+            OrderType::IOC,
+        )
+        .unwrap();
+        // 一条成交事件 + 卖单被吃完的 Out 事件
+        assert_eq!(state.event_queue.consume_events("crank", 10).len(), 2);
+        assert_eq!(state.event_queue.pop_consumed(), 2);
+    }
+    assert!(state.event_queue.is_empty());
+    assert_eq!(state.event_queue.next_seq, 200);
+}
+
+#[test]
+fn test_immediate_market_without_consumers_never_fills_up() {
+    let mut markets = Markets::new();
+    markets
+        .create_market(MARKET, MarketConfig::new("SOL", "USDC"))
+        .unwrap();
+    markets.deposit(MARKET, "Alice", 0, 1_000_000).unwrap();
+    markets.deposit(MARKET, "Bob", 2_000, 0).unwrap();
+
+    // 每笔成交写成交和 Out 两条事件，远超默认容量；没有 consumer 时旧事件按需腾出
+    let trades = DEFAULT_EVENT_QUEUE_CAPACITY;
+    for _ in 0..trades {
+        common::place(&mut markets, "Bob", Side::Ask, 10, 1, OrderType::Limit).unwrap();
+        common::place(&mut markets, "Alice", Side::Bid, 10, 1, OrderType::IOC).unwrap();
+    }
+    let queue = &markets.market(MARKET).unwrap().event_queue;
+    assert!(queue.is_full());
+    assert_eq!(queue.next_seq, 2 * trades as u64);
+    assert_eq!(
+        queue.head_seq(),
+        2 * trades as u64 - queue.capacity() as u64
+    );
+
+    // 已注册的 consumer 没读过的事件不会被腾出
+    markets.register_consumer(MARKET, "frontend").unwrap();
+    common::place(&mut markets, "Bob", Side::Ask, 10, 1, OrderType::Limit).unwrap();
+    let err = common::place(&mut markets, "Alice", Side::Bid, 10, 1, OrderType::IOC).unwrap_err();
+    assert_eq!(err, DexError::QueueFull);
+    let state = markets.market_mut(MARKET).unwrap();
+    state.event_queue.consume_events("frontend", 2);
+    common::place(&mut markets, "Alice", Side::Bid, 10, 1, OrderType::IOC).unwrap();
+}
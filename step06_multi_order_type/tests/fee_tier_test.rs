@@ -0,0 +1,155 @@
+use step06_multi_order_type::error::DexError;
+use step06_multi_order_type::event_queue::EventType;
+use step06_multi_order_type::fee_tier::{
+    FeeTier, FeeTierResolver, StakeTierResolver, THIRTY_DAYS, VolumeTierResolver, tier_for,
+};
+use step06_multi_order_type::market_config::MarketConfig;
+use step06_multi_order_type::openbook::{
+    Markets, OrderType, PlaceOrderOutcome, SelfTradeBehavior, Side,
+};
+
+mod common;
+
+use common::MARKET;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// 基础费率 taker 40 / maker 0；成交量（或质押）达到 10_000 降到 30 / 5，达到 100_000 降到 20 / 10
+fn tiered_config() -> MarketConfig {
+    MarketConfig {
+        taker_fee_bps: 40,
+        maker_rebate_bps: 0,
+        fee_tiers: vec![
+            FeeTier {
+                threshold: 10_000,
+                taker_fee_bps: 30,
+                maker_rebate_bps: 5,
+            },
+            FeeTier {
+                threshold: 100_000,
+                taker_fee_bps: 20,
+                maker_rebate_bps: 10,
+            },
+        ],
+        ..MarketConfig::new("SOL", "USDC")
+    }
+}
+
+fn setup(resolver: Box<dyn FeeTierResolver>) -> Markets {
+    let mut markets = Markets::new();
+    markets.create_market(MARKET, tiered_config()).unwrap();
+    markets.set_fee_tier_resolver(MARKET, resolver).unwrap();
+    for user in ["Alice", "Bob"] {
+        markets.deposit(MARKET, user, 10_000, 1_000_000).unwrap();
+    }
+    markets
+}
+
+/// Bob 挂卖单、Alice 吃单，成交金额 = price * quantity
+fn trade(markets: &mut Markets, price: u64, quantity: u64, now: u64) -> PlaceOrderOutcome {
+    markets
+        .place_order(
+            MARKET,
+            "Bob",
+            Side::Ask,
+            price,
+            quantity,
+            now,
+            None,
+            OrderType::Limit,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap();
+    markets
+        .place_order(
+            MARKET,
+            "Alice",
+            Side::Bid,
+            price,
+            quantity,
+            now,
+            None,
+            OrderType::IOC,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap()
+}
+
+#[test]
+fn test_tier_lookup_and_validation() {
+    let config = tiered_config();
+    assert_eq!(tier_for(&config.fee_tiers, 0), 0);
+    assert_eq!(tier_for(&config.fee_tiers, 9_999), 0);
+    assert_eq!(tier_for(&config.fee_tiers, 10_000), 1);
+    assert_eq!(tier_for(&config.fee_tiers, 1_000_000), 2);
+    assert_eq!(config.fee_rates(2), (20, 10));
+    assert_eq!(config.fee_rates(9), (20, 10));
+    assert_eq!(config.taker_fee(10_000, 0), Ok(40));
+    assert_eq!(config.maker_rebate(10_000, 1), Ok(5));
+
+    // 档位表必须升序；任一档返佣不能超过任一档的 taker 费率
+    let mut unsorted = tiered_config();
+    unsorted.fee_tiers.reverse();
+    let mut generous = tiered_config();
+    generous.fee_tiers[1].maker_rebate_bps = 25;
+    for bad in [unsorted, generous] {
+        assert!(matches!(
+            bad.validate(),
+            Err(DexError::InvalidMarketConfig(_))
+        ));
+    }
+    assert_eq!(tiered_config().validate(), Ok(()));
+}
+
+#[test]
+fn test_volume_tier_from_fill_events() {
+    let mut markets = setup(Box::new(VolumeTierResolver::default()));
+    let start = 100 * DAY;
+
+    // 第一笔成交：两人成交量都为0，基础费率
+    let first = trade(&mut markets, 100, 100, start);
+    assert_eq!(first.fees_paid, 10_000 * 40 / 10_000);
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(state.fee_tier("Alice", start), 1);
+    assert_eq!(state.fee_tier("Bob", start), 1);
+
+    // 第二笔成交按第1档收费，档位记录在成交事件上
+    let second = trade(&mut markets, 100, 100, start + DAY);
+    assert_eq!(second.fees_paid, 10_000 * 30 / 10_000);
+    let state = markets.market(MARKET).unwrap();
+    let fills: Vec<(usize, usize, u64)> = state
+        .event_queue
+        .iter()
+        .filter(|e| matches!(e.event_type, EventType::Fill))
+        .map(|e| (e.taker_fee_tier, e.maker_fee_tier, e.rebate))
+        .collect();
+    assert_eq!(fills, vec![(0, 0, 0), (1, 1, 10_000 * 5 / 10_000)]);
+
+    // 超过30天的成交量移出窗口
+    let later = start + THIRTY_DAYS + DAY / 2;
+    assert_eq!(state.fee_tier("Alice", later), 1);
+    assert_eq!(state.fee_tier("Alice", start + DAY + THIRTY_DAYS), 0);
+}
+
+#[test]
+fn test_stake_tier_resolver() {
+    let mut stakes = StakeTierResolver::default();
+    stakes.set_stake("Alice", 100_000);
+    stakes.set_stake("Bob", 10_000);
+    let mut markets = setup(Box::new(stakes));
+
+    // Alice（taker）第2档，Bob（maker）第1档；不受成交量影响
+    let outcome = trade(&mut markets, 100, 100, 0);
+    assert_eq!(outcome.fees_paid, 10_000 * 20 / 10_000);
+    let state = markets.market(MARKET).unwrap();
+    let fill = state.event_queue.iter().next().unwrap();
+    assert_eq!((fill.taker_fee_tier, fill.maker_fee_tier), (2, 1));
+    assert_eq!(fill.rebate, 10_000 * 5 / 10_000);
+    assert_eq!(state.fee_receiver.taker_fees_collected, 20);
+    assert_eq!(state.fee_receiver.maker_rebates_paid, 5);
+    assert_eq!(state.fee_tier("Carol", 0), 0);
+}
@@ -0,0 +1,1851 @@
+use step06_multi_order_type::error::DexError;
+use step06_multi_order_type::event_queue::EventType;
+use step06_multi_order_type::market_config::MarketConfig;
+use step06_multi_order_type::openbook::{
+    Markets, OrderType, PlaceOrderOutcome, SelfTradeBehavior, SettlementMode, Side,
+    TokenWithdrawRecord, WithdrawRecord,
+};
+
+mod common;
+
+use common::{FEE_BPS, MARKET, NOW, REBATE_BPS, config, place};
+
+fn taker_fee(notional: u64) -> u64 {
+    notional * FEE_BPS / 10_000
+}
+
+fn maker_rebate(notional: u64) -> u64 {
+    notional * REBATE_BPS / 10_000
+}
+
+fn setup() -> Markets {
+    setup_with(config(SettlementMode::Immediate))
+}
+
+fn setup_with(config: MarketConfig) -> Markets {
+    let mut markets = Markets::new();
+    markets.create_market(MARKET, config).unwrap();
+    markets.deposit(MARKET, "Alice", 100, 2000).unwrap();
+    markets.deposit(MARKET, "Bob", 50, 1000).unwrap();
+    markets
+}
+
+#[test]
+fn test_place_order_errors() {
+    let mut markets = setup();
+
+    // 报价币不足
+    let err = place(&mut markets, "Alice", Side::Bid, 100, 100, OrderType::Limit).unwrap_err();
+    assert_eq!(
+        err,
+        DexError::InsufficientQuote {
+            needed: 10_000 + taker_fee(10_000),
+            available: 2000
+        }
+    );
+
+    // 主币不足
+    let err = place(&mut markets, "Bob", Side::Ask, 10, 51, OrderType::Limit).unwrap_err();
+    assert_eq!(
+        err,
+        DexError::InsufficientBase {
+            needed: 51,
+            available: 50
+        }
+    );
+
+    // 数量为0
+    let err = place(&mut markets, "Bob", Side::Ask, 10, 0, OrderType::Limit).unwrap_err();
+    assert_eq!(err, DexError::InvalidQuantity);
+
+    // 市场不存在
+    let err = markets
+        .place_order(
+            "SOL/USDT",
+            "Bob",
+            Side::Ask,
+            10,
+            1,
+            NOW,
+            None,
+            OrderType::Limit,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(err, DexError::MarketNotFound("SOL/USDT".to_string()));
+
+    // 失败的下单不改变余额
+    assert_eq!(markets.balance("Alice", "USDC"), 2000);
+    assert_eq!(markets.balance("Bob", "SOL"), 50);
+}
+
+#[test]
+fn test_fok_not_fillable() {
+    let mut markets = setup();
+    place(&mut markets, "Bob", Side::Ask, 10, 5, OrderType::Limit).unwrap();
+
+    let err = place(&mut markets, "Alice", Side::Bid, 10, 8, OrderType::FOK).unwrap_err();
+    assert_eq!(
+        err,
+        DexError::FokNotFillable {
+            requested: 8,
+            fillable: 5
+        }
+    );
+    assert_eq!(markets.balance("Alice", "USDC"), 2000);
+}
+
+#[test]
+fn test_simulate_match_and_atomic_fok() {
+    let mut markets = setup();
+    place(&mut markets, "Bob", Side::Ask, 10, 3, OrderType::Limit).unwrap();
+    place(&mut markets, "Bob", Side::Ask, 11, 4, OrderType::Limit).unwrap();
+    // 在 NOW + 5 过期的挂单
+    markets
+        .place_order(
+            MARKET,
+            "Bob",
+            Side::Ask,
+            9,
+            2,
+            NOW,
+            Some(NOW + 5),
+            OrderType::Limit,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap();
+
+    let state = markets.market(MARKET).unwrap();
+    let sim = state.simulate_match(&Side::Bid, 11, 8, &OrderType::FOK);
+    let fills: Vec<(u64, u64, u64)> = sim
+        .fills
+        .iter()
+        .map(|f| (f.maker_order_id, f.price, f.quantity))
+        .collect();
+    assert_eq!(fills, vec![(2, 9, 2), (0, 10, 3), (1, 11, 3)]);
+    assert_eq!(sim.filled_quantity(), 8);
+    assert!(
+        state
+            .simulate_match(&Side::Bid, 11, 8, &OrderType::PostOnly)
+            .fills
+            .is_empty()
+    );
+    assert_eq!(
+        state
+            .simulate_match(&Side::Ask, 0, 5, &OrderType::Market)
+            .filled_quantity(),
+        0
+    );
+
+    // 过期挂单不计入：可成交 7 < 8，拒绝，市场和钱包都保持原样（过期挂单也不会被清理）
+    let before = format!("{:?}", state);
+    let wallets_before = format!("{:?}", markets.wallets);
+    let err = markets
+        .place_order(
+            MARKET,
+            "Alice",
+            Side::Bid,
+            11,
+            8,
+            NOW + 10,
+            None,
+            OrderType::FOK,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(
+        err,
+        DexError::FokNotFillable {
+            requested: 8,
+            fillable: 7
+        }
+    );
+    assert_eq!(format!("{:?}", markets.market(MARKET).unwrap()), before);
+    assert_eq!(format!("{:?}", markets.wallets), wallets_before);
+
+    // 能全部成交的 FOK 正常成交
+    let outcome = markets
+        .place_order(
+            MARKET,
+            "Alice",
+            Side::Bid,
+            11,
+            7,
+            NOW + 10,
+            None,
+            OrderType::FOK,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap();
+    assert_eq!(outcome.filled_quantity, 7);
+    assert!(markets.market(MARKET).unwrap().asks.is_empty());
+    // 过期挂单被清理时紧跟一条 Out 事件
+    let events: Vec<_> = markets.market(MARKET).unwrap().event_queue.iter().collect();
+    let expire = events
+        .iter()
+        .position(|e| matches!(e.event_type, EventType::Expire))
+        .unwrap();
+    let out = events[expire + 1];
+    assert!(matches!(out.event_type, EventType::Out));
+    assert_eq!((out.order_id, out.quantity), (2, 2));
+}
+
+#[test]
+fn test_rejected_order_leaves_expired_orders_untouched() {
+    fn place_expiring(markets: &mut Markets, owner: &str, side: Side, price: u64, quantity: u64) {
+        markets
+            .place_order(
+                MARKET,
+                owner,
+                side,
+                price,
+                quantity,
+                NOW,
+                Some(NOW + 5),
+                OrderType::Limit,
+                0,
+                SelfTradeBehavior::DecrementTake,
+                None,
+            )
+            .unwrap();
+    }
+    let snapshot = |markets: &Markets| {
+        (
+            format!("{:?}", markets.market(MARKET).unwrap()),
+            format!("{:?}", markets.wallets),
+        )
+    };
+    let mut markets = setup();
+    place(&mut markets, "Bob", Side::Ask, 500, 5, OrderType::Limit).unwrap();
+    place_expiring(&mut markets, "Bob", Side::Ask, 400, 2);
+    place_expiring(&mut markets, "Alice", Side::Bid, 100, 19);
+
+    // 跳过过期挂单后能全部成交、但资金不足的 FOK：拒绝，过期挂单不被清理，事件序号不变
+    let before = snapshot(&markets);
+    let err = markets
+        .place_order(
+            MARKET,
+            "Alice",
+            Side::Bid,
+            500,
+            5,
+            NOW + 10,
+            None,
+            OrderType::FOK,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap_err();
+    assert!(matches!(err, DexError::InsufficientQuote { .. }));
+    assert_eq!(snapshot(&markets), before);
+
+    // 过期买单清理后解冻的资金计入可用余额：Alice 可以用这笔资金重新下单
+    let outcome = markets
+        .place_order(
+            MARKET,
+            "Alice",
+            Side::Bid,
+            100,
+            19,
+            NOW + 10,
+            None,
+            OrderType::Limit,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap();
+    let state = markets.market(MARKET).unwrap();
+    let bids: Vec<u64> = state.bids.iter().map(|o| o.id).collect();
+    assert_eq!(bids, vec![outcome.order_id]);
+    assert_eq!(state.asks.len(), 1);
+}
+
+#[test]
+fn test_place_order_outcome() {
+    let mut markets = setup();
+    let ask = place(&mut markets, "Bob", Side::Ask, 100, 5, OrderType::Limit).unwrap();
+    assert_eq!(ask.filled_quantity, 0);
+    assert_eq!(ask.resting_order_id, Some(ask.order_id));
+
+    let bid = place(&mut markets, "Alice", Side::Bid, 100, 8, OrderType::Limit).unwrap();
+    assert_eq!(bid.filled_quantity, 5);
+    assert_eq!(bid.fees_paid, taker_fee(100 * 5));
+    assert_eq!(bid.resting_order_id, Some(bid.order_id));
+
+    let ioc = place(&mut markets, "Bob", Side::Ask, 100, 5, OrderType::IOC).unwrap();
+    assert_eq!(ioc.filled_quantity, 3);
+    assert_eq!(ioc.resting_order_id, None);
+}
+
+#[test]
+fn test_batch_cancel_is_atomic() {
+    let mut markets = setup();
+    let a = place(&mut markets, "Alice", Side::Bid, 10, 5, OrderType::Limit).unwrap();
+    let b = place(&mut markets, "Bob", Side::Ask, 20, 5, OrderType::Limit).unwrap();
+
+    // Bob 的订单不能被 Alice 撤销，整个批次失败
+    let err = markets
+        .batch_cancel(MARKET, "Alice", &[a.order_id, b.order_id], NOW)
+        .unwrap_err();
+    assert_eq!(err, DexError::OrderNotFound(b.order_id));
+    assert_eq!(markets.market(MARKET).unwrap().bids.len(), 1);
+
+    markets
+        .batch_cancel(MARKET, "Alice", &[a.order_id], NOW)
+        .unwrap();
+    let state = markets.market(MARKET).unwrap();
+    assert!(state.bids.is_empty());
+    assert_eq!(state.asks.len(), 1);
+}
+
+fn place_with_client_id(
+    markets: &mut Markets,
+    owner: &str,
+    side: Side,
+    price: u64,
+    quantity: u64,
+    client_order_id: u64,
+) -> Result<PlaceOrderOutcome, DexError> {
+    markets.place_order(
+        MARKET,
+        owner,
+        side,
+        price,
+        quantity,
+        NOW,
+        None,
+        OrderType::Limit,
+        client_order_id,
+        SelfTradeBehavior::DecrementTake,
+        None,
+    )
+}
+
+#[test]
+fn test_client_order_id() {
+    let mut markets = setup();
+    let a = place_with_client_id(&mut markets, "Alice", Side::Bid, 10, 5, 7).unwrap();
+    // 客户端订单ID 0 表示未指定，可以重复
+    place_with_client_id(&mut markets, "Alice", Side::Bid, 9, 5, 0).unwrap();
+    place_with_client_id(&mut markets, "Alice", Side::Bid, 8, 5, 0).unwrap();
+    // 同一用户的客户端订单ID不能重复，不做任何变更；其他用户可以使用相同的ID
+    assert_eq!(
+        place_with_client_id(&mut markets, "Alice", Side::Bid, 10, 1, 7),
+        Err(DexError::DuplicateClientOrderId(7))
+    );
+    assert_eq!(markets.market(MARKET).unwrap().bids.len(), 3);
+    let b = place_with_client_id(&mut markets, "Bob", Side::Ask, 20, 5, 7).unwrap();
+
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(state.order_by_client_id("Alice", 7).unwrap().id, a.order_id);
+    assert_eq!(state.order_by_client_id("Bob", 7).unwrap().id, b.order_id);
+    assert!(state.order_by_client_id("Alice", 0).is_none());
+    assert!(state.order_by_client_id("Carol", 7).is_none());
+
+    // 成交事件记录 taker 的客户端订单ID
+    place_with_client_id(&mut markets, "Bob", Side::Ask, 10, 2, 42).unwrap();
+    let state = markets.market(MARKET).unwrap();
+    let fill = state.event_queue.iter().last().unwrap();
+    assert!(matches!(fill.event_type, EventType::Fill));
+    assert_eq!(fill.client_order_id, 42);
+
+    // 按客户端订单ID撤单，撤单事件记录被撤挂单的客户端订单ID，撤销后ID可以复用
+    assert_eq!(
+        markets.cancel_order_by_client_id(MARKET, "Alice", 7, NOW),
+        Ok(a.order_id)
+    );
+    let state = markets.market(MARKET).unwrap();
+    let events: Vec<_> = state.event_queue.iter().collect();
+    let (cancel, out) = (events[events.len() - 2], events[events.len() - 1]);
+    assert!(matches!(cancel.event_type, EventType::Cancel));
+    assert_eq!(
+        (cancel.order_id, cancel.client_order_id, cancel.quantity),
+        (a.order_id, 7, 3)
+    );
+    assert!(matches!(out.event_type, EventType::Out));
+    assert_eq!(
+        (out.order_id, out.client_order_id, out.quantity),
+        (a.order_id, 7, 3)
+    );
+    assert!(state.order_by_client_id("Alice", 7).is_none());
+    assert_eq!(
+        markets.cancel_order_by_client_id(MARKET, "Alice", 7, NOW),
+        Err(DexError::ClientOrderIdNotFound(7))
+    );
+    place_with_client_id(&mut markets, "Alice", Side::Bid, 10, 1, 7).unwrap();
+
+    // 客户端订单ID按用户区分：Bob 的 7 是他自己的卖单，不受 Alice 的影响
+    assert_eq!(
+        markets.cancel_order_by_client_id(MARKET, "Bob", 7, NOW),
+        Ok(b.order_id)
+    );
+    assert_eq!(markets.market(MARKET).unwrap().asks.len(), 0);
+}
+
+#[test]
+fn test_cancel_all_and_cancel_up_to() {
+    let mut markets = setup();
+    let bids: Vec<u64> = (0..3)
+        .map(|i| {
+            place(
+                &mut markets,
+                "Alice",
+                Side::Bid,
+                10 - i,
+                5,
+                OrderType::Limit,
+            )
+            .unwrap()
+            .order_id
+        })
+        .collect();
+    let asks: Vec<u64> = (0..3)
+        .map(|i| {
+            place(
+                &mut markets,
+                "Alice",
+                Side::Ask,
+                20 + i,
+                5,
+                OrderType::Limit,
+            )
+            .unwrap()
+            .order_id
+        })
+        .collect();
+    let bob = place(&mut markets, "Bob", Side::Ask, 30, 5, OrderType::Limit).unwrap();
+
+    // 只撤销买单
+    assert_eq!(
+        markets.cancel_all(MARKET, "Alice", Some(Side::Bid), NOW),
+        Ok(bids.clone())
+    );
+    let state = markets.market(MARKET).unwrap();
+    assert!(state.bids.is_empty());
+    assert_eq!(state.asks.len(), 4);
+    assert_eq!(state.open_orders["Alice"].quote_locked(), 0);
+    assert_eq!(
+        markets.cancel_all(MARKET, "Alice", Some(Side::Bid), NOW),
+        Ok(vec![])
+    );
+
+    // 先下的先撤，每个被撤挂单写撤单和 Out 两条事件
+    let events_before = markets.market(MARKET).unwrap().event_queue.len();
+    assert_eq!(
+        markets.cancel_up_to(MARKET, "Alice", 2, NOW),
+        Ok(asks[..2].to_vec())
+    );
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(state.event_queue.len(), events_before + 4);
+    assert_eq!(state.open_orders["Alice"].order_ids(), vec![asks[2]]);
+
+    // 撤销全部挂单，不影响其他用户
+    assert_eq!(
+        markets.cancel_all(MARKET, "Alice", None, NOW),
+        Ok(vec![asks[2]])
+    );
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(state.open_orders["Alice"].base_locked(), 0);
+    assert_eq!(
+        state.asks.iter().map(|o| o.id).collect::<Vec<_>>(),
+        vec![bob.order_id]
+    );
+    assert_eq!(markets.cancel_all(MARKET, "Carol", None, NOW), Ok(vec![]));
+    assert_eq!(
+        markets.cancel_all("BTC/USDC", "Alice", None, NOW),
+        Err(DexError::MarketNotFound("BTC/USDC".to_string()))
+    );
+}
+
+#[test]
+fn test_replace_order() {
+    let mut markets = setup();
+    let alice = place(&mut markets, "Alice", Side::Bid, 10, 5, OrderType::Limit).unwrap();
+    let bob = place(&mut markets, "Bob", Side::Bid, 10, 5, OrderType::Limit).unwrap();
+    let bid_ids = |markets: &Markets| -> Vec<u64> {
+        let state = markets.market(MARKET).unwrap();
+        state.bids.iter().map(|o| o.id).collect()
+    };
+    let alice_quote_locked =
+        |markets: &Markets| markets.market(MARKET).unwrap().open_orders["Alice"].quote_locked();
+
+    // 同价减少数量：保留时间优先级，解冻多余资金
+    assert_eq!(
+        markets.replace_order(MARKET, "Alice", alice.order_id, 10, 3, NOW),
+        Ok(true)
+    );
+    assert_eq!(bid_ids(&markets), vec![alice.order_id, bob.order_id]);
+    assert_eq!(alice_quote_locked(&markets), 30);
+    let state = markets.market(MARKET).unwrap();
+    let amend = state.event_queue.iter().last().unwrap();
+    assert!(matches!(amend.event_type, EventType::Amend));
+    assert_eq!(
+        (amend.order_id, amend.price, amend.quantity),
+        (alice.order_id, Some(10), 3)
+    );
+
+    // 增加数量：重新排队到同价位末尾，先用挂单账户中刚解冻的 20，不足的 10 从钱包转入
+    let wallet_before = markets.balance("Alice", "USDC");
+    assert_eq!(
+        markets.replace_order(MARKET, "Alice", alice.order_id, 10, 6, NOW),
+        Ok(false)
+    );
+    assert_eq!(bid_ids(&markets), vec![bob.order_id, alice.order_id]);
+    assert_eq!(alice_quote_locked(&markets), 60);
+    assert_eq!(markets.balance("Alice", "USDC"), wallet_before - 10);
+
+    // 改价：按新价位排队，订单ID不变
+    assert_eq!(
+        markets.replace_order(MARKET, "Alice", alice.order_id, 11, 6, NOW),
+        Ok(false)
+    );
+    assert_eq!(bid_ids(&markets), vec![alice.order_id, bob.order_id]);
+    assert_eq!(alice_quote_locked(&markets), 66);
+
+    // 失败时不做任何变更
+    place(&mut markets, "Bob", Side::Ask, 20, 5, OrderType::Limit).unwrap();
+    assert_eq!(
+        markets.replace_order(MARKET, "Alice", alice.order_id, 20, 6, NOW),
+        Err(DexError::AmendWouldCross {
+            price: 20,
+            best_price: 20
+        })
+    );
+    assert_eq!(
+        markets.replace_order(MARKET, "Bob", alice.order_id, 10, 1, NOW),
+        Err(DexError::OrderNotFound(alice.order_id))
+    );
+    assert_eq!(
+        markets.replace_order(MARKET, "Alice", alice.order_id, 11, 0, NOW),
+        Err(DexError::InvalidQuantity)
+    );
+    assert!(matches!(
+        markets.replace_order(MARKET, "Alice", alice.order_id, 11, 1_000, NOW),
+        Err(DexError::InsufficientQuote { .. })
+    ));
+    let state = markets.market(MARKET).unwrap();
+    let order = state.bids.get(alice.order_id).unwrap();
+    assert_eq!((order.price, order.quantity), (11, 6));
+    assert_eq!(alice_quote_locked(&markets), 66);
+
+    // 卖单减少数量解冻主币；改单后的挂单可以正常成交
+    let ask = markets.market(MARKET).unwrap().asks.best().unwrap().id;
+    assert_eq!(
+        markets.replace_order(MARKET, "Bob", ask, 20, 2, NOW),
+        Ok(true)
+    );
+    assert_eq!(
+        markets.market(MARKET).unwrap().open_orders["Bob"].base_locked(),
+        2
+    );
+    let outcome = place(&mut markets, "Bob", Side::Ask, 11, 6, OrderType::IOC).unwrap();
+    assert_eq!(outcome.filled_quantity, 6);
+    assert_conserved(&markets, 150, 3000);
+}
+
+#[test]
+fn test_fill_event_maker_and_taker_metadata() {
+    let mut markets = Markets::new();
+    markets
+        .create_market(MARKET, config(SettlementMode::Immediate))
+        .unwrap();
+    markets.deposit(MARKET, "Alice", 20, 0).unwrap();
+    markets.deposit(MARKET, "Bob", 0, 100_000).unwrap();
+    let last_events = |markets: &Markets, n: usize| {
+        let events: Vec<_> = markets
+            .market(MARKET)
+            .unwrap()
+            .event_queue
+            .iter()
+            .cloned()
+            .collect();
+        events[events.len() - n..].to_vec()
+    };
+
+    // 买单 taker 部分吃掉卖单：成交事件同时记录双方的订单ID、客户端订单ID和 native 收付数量
+    let ask = place_with_client_id(&mut markets, "Alice", Side::Ask, 1000, 10, 11).unwrap();
+    let bid = place_with_client_id(&mut markets, "Bob", Side::Bid, 1000, 4, 22).unwrap();
+    let fill = &last_events(&markets, 1)[0];
+    assert!(matches!(fill.event_type, EventType::Fill));
+    assert_eq!(fill.side, Some(Side::Bid));
+    assert_eq!((fill.order_id, fill.client_order_id), (bid.order_id, 22));
+    assert_eq!(
+        (fill.maker_order_id, fill.maker_client_order_id),
+        (Some(ask.order_id), 11)
+    );
+    assert_eq!(
+        (fill.taker_native_paid, fill.taker_native_received),
+        (4000 + taker_fee(4000), 4)
+    );
+    assert_eq!(
+        (fill.maker_native_paid, fill.maker_native_received),
+        (4, 4000 + maker_rebate(4000))
+    );
+
+    // 卖单被吃完：成交事件之后紧跟一条 Out 事件
+    place_with_client_id(&mut markets, "Bob", Side::Bid, 1000, 6, 23).unwrap();
+    let events = last_events(&markets, 2);
+    assert!(matches!(events[0].event_type, EventType::Fill));
+    assert_eq!(events[0].maker_order_id, Some(ask.order_id));
+    assert!(matches!(events[1].event_type, EventType::Out));
+    assert_eq!(
+        (
+            events[1].order_id,
+            events[1].client_order_id,
+            events[1].quantity
+        ),
+        (ask.order_id, 11, 0)
+    );
+    assert_eq!(events[1].side, Some(Side::Ask));
+    assert!(
+        markets.market(MARKET).unwrap().open_orders["Alice"]
+            .order_ids()
+            .is_empty()
+    );
+
+    // 卖单 taker：taker 收到扣除手续费后的报价币，maker 付出扣除返佣后的报价币
+    let resting = place_with_client_id(&mut markets, "Bob", Side::Bid, 900, 5, 24).unwrap();
+    let taker = place_with_client_id(&mut markets, "Alice", Side::Ask, 900, 5, 12).unwrap();
+    let events = last_events(&markets, 2);
+    let fill = &events[0];
+    assert_eq!(fill.side, Some(Side::Ask));
+    assert_eq!((fill.order_id, fill.client_order_id), (taker.order_id, 12));
+    assert_eq!(
+        (fill.maker_order_id, fill.maker_client_order_id),
+        (Some(resting.order_id), 24)
+    );
+    assert_eq!(
+        (fill.taker_native_paid, fill.taker_native_received),
+        (5, 4500 - taker_fee(4500))
+    );
+    assert_eq!(
+        (fill.maker_native_paid, fill.maker_native_received),
+        (4500 - maker_rebate(4500), 5)
+    );
+    assert!(matches!(events[1].event_type, EventType::Out));
+    assert_eq!(
+        (events[1].order_id, events[1].client_order_id),
+        (resting.order_id, 24)
+    );
+
+    // 非成交事件没有 maker 信息
+    let cancelled = place_with_client_id(&mut markets, "Bob", Side::Bid, 800, 1, 25).unwrap();
+    markets
+        .cancel_order_by_client_id(MARKET, "Bob", 25, NOW)
+        .unwrap();
+    let events = last_events(&markets, 2);
+    let cancel = &events[0];
+    assert!(matches!(cancel.event_type, EventType::Cancel));
+    assert!(matches!(events[1].event_type, EventType::Out));
+    assert_eq!(events[1].maker_order_id, None);
+    assert_eq!(cancel.order_id, cancelled.order_id);
+    assert_eq!(cancel.maker_order_id, None);
+    assert_eq!(cancel.taker_native_paid + cancel.maker_native_received, 0);
+}
+
+#[test]
+fn test_price_time_priority() {
+    let mut markets = setup();
+    markets.deposit(MARKET, "Carol", 0, 2000).unwrap();
+    let first = place(&mut markets, "Alice", Side::Bid, 10, 2, OrderType::Limit).unwrap();
+    let better = place(&mut markets, "Carol", Side::Bid, 11, 2, OrderType::Limit).unwrap();
+    let second = place(&mut markets, "Carol", Side::Bid, 10, 2, OrderType::Limit).unwrap();
+
+    let state = markets.market(MARKET).unwrap();
+    let ids: Vec<u64> = state.bids.iter().map(|o| o.id).collect();
+    assert_eq!(ids, vec![better.order_id, first.order_id, second.order_id]);
+
+    // 卖单先吃高价档位，再按时间顺序吃同价位订单
+    place(&mut markets, "Bob", Side::Ask, 10, 3, OrderType::IOC).unwrap();
+    let state = markets.market(MARKET).unwrap();
+    let remaining: Vec<(u64, u64)> = state.bids.iter().map(|o| (o.id, o.quantity)).collect();
+    assert_eq!(remaining, vec![(first.order_id, 1), (second.order_id, 2)]);
+    assert_eq!(state.bids.best_price(), Some(10));
+}
+
+#[test]
+fn test_deferred_settlement_credits_makers_on_crank() {
+    let mut markets = Markets::new();
+    markets
+        .create_market(MARKET, config(SettlementMode::Deferred))
+        .unwrap();
+    markets.deposit(MARKET, "Alice", 0, 20_000).unwrap();
+    markets.deposit(MARKET, "Bob", 50, 0).unwrap();
+    markets.deposit(MARKET, "Carol", 0, 20_000).unwrap();
+
+    // Bob 挂卖单被 Alice 吃掉：Alice（taker）立即拿到主币，Bob（maker）尚未拿到报价币
+    place(&mut markets, "Bob", Side::Ask, 1000, 10, OrderType::Limit).unwrap();
+    let bid = place(&mut markets, "Alice", Side::Bid, 1000, 4, OrderType::IOC).unwrap();
+    assert_eq!(bid.fees_paid, taker_fee(4000));
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(state.open_orders["Alice"].base_free, 4);
+    assert_eq!(
+        markets.balance("Alice", "USDC"),
+        20_000 - 4000 - bid.fees_paid
+    );
+    assert_eq!(state.open_orders["Bob"].quote_total, 0);
+    assert_eq!(state.open_orders["Bob"].base_locked(), 10);
+    assert_eq!(state.asks.best().unwrap().quantity, 6);
+
+    // Carol 挂买单，Bob 卖单吃掉：Bob（taker）立即拿到报价币，Carol（maker）尚未拿到主币
+    place(&mut markets, "Carol", Side::Bid, 900, 5, OrderType::Limit).unwrap();
+    let ask = place(&mut markets, "Bob", Side::Ask, 900, 5, OrderType::IOC).unwrap();
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(state.open_orders["Bob"].quote_free, 4500 - ask.fees_paid);
+    assert_eq!(state.open_orders["Carol"].base_total, 0);
+    assert_eq!(state.open_orders["Carol"].quote_locked(), 4500);
+
+    // crank 分批处理事件，maker 入账到挂单账户（成交金额 + 返佣）
+    assert_eq!(markets.crank(MARKET, 1).unwrap(), 1);
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(
+        state.open_orders["Bob"].quote_free,
+        4500 - ask.fees_paid + 4000 + maker_rebate(4000)
+    );
+    assert_eq!(state.open_orders["Bob"].base_locked(), 6);
+    assert_eq!(state.open_orders["Carol"].base_free, 0);
+    // Carol 的成交事件 + 她的买单被吃完的 Out 事件
+    assert_eq!(markets.crank(MARKET, 10).unwrap(), 2);
+    let state = markets.market(MARKET).unwrap();
+    let carol = &state.open_orders["Carol"];
+    assert_eq!(carol.base_free, 5);
+    assert_eq!(carol.quote_locked(), 0);
+    // 挂单时预留的 taker 手续费已释放，再加上返佣
+    assert_eq!(carol.quote_free, taker_fee(4500) + maker_rebate(4500));
+    assert!(state.event_queue.is_empty());
+
+    let fees = &state.fee_receiver;
+    assert_eq!(fees.taker_fees_collected, taker_fee(4000) + taker_fee(4500));
+    assert_eq!(
+        fees.maker_rebates_paid,
+        maker_rebate(4000) + maker_rebate(4500)
+    );
+    assert_eq!(markets.crank(MARKET, 10).unwrap(), 0);
+}
+
+#[test]
+fn test_crank_failure_keeps_event_for_retry() {
+    let mut markets = Markets::new();
+    markets
+        .create_market(MARKET, config(SettlementMode::Deferred))
+        .unwrap();
+    markets.deposit(MARKET, "Alice", 0, 20_000).unwrap();
+    markets.deposit(MARKET, "Bob", 50, 0).unwrap();
+    place(&mut markets, "Bob", Side::Ask, 1000, 10, OrderType::Limit).unwrap();
+    place(&mut markets, "Alice", Side::Bid, 1000, 4, OrderType::IOC).unwrap();
+
+    // 让 Bob 的报价币入账溢出：crank 返回错误，挂单账户和 crank 指针都不变
+    let state = markets.market_mut(MARKET).unwrap();
+    let bob = state.open_orders["Bob"].clone();
+    let corrupted = state.open_orders.get_mut("Bob").unwrap();
+    corrupted.quote_free = u64::MAX;
+    corrupted.quote_total = u64::MAX;
+    assert_eq!(markets.crank(MARKET, 10), Err(DexError::Overflow));
+    let state = markets.market_mut(MARKET).unwrap();
+    assert_eq!(state.open_orders["Bob"].base_locked(), 10);
+    assert_eq!(state.pending_fills().len(), 1);
+
+    // 恢复后重试，成交事件入账
+    state.open_orders.insert("Bob".to_string(), bob);
+    assert_eq!(markets.crank(MARKET, 10).unwrap(), 1);
+    let state = markets.market(MARKET).unwrap();
+    assert!(state.pending_fills().is_empty());
+    assert_eq!(state.open_orders["Bob"].base_locked(), 6);
+    assert!(state.event_queue.is_empty());
+}
+
+#[test]
+fn test_immediate_settlement_crank_does_not_double_credit() {
+    let mut markets = setup();
+    place(&mut markets, "Bob", Side::Ask, 100, 5, OrderType::Limit).unwrap();
+    place(&mut markets, "Alice", Side::Bid, 100, 5, OrderType::IOC).unwrap();
+    let before = markets.market(MARKET).unwrap().open_orders["Bob"].clone();
+    // 成交事件 + Out 事件
+    assert_eq!(markets.crank(MARKET, 10).unwrap(), 2);
+    assert_eq!(markets.market(MARKET).unwrap().open_orders["Bob"], before);
+}
+
+#[test]
+fn test_open_orders_lock_fill_cancel_and_settle() {
+    // 不收手续费，便于核对资金流转
+    let mut markets = setup_with(MarketConfig::new("SOL", "USDC"));
+
+    // 挂单冻结资金：钱包转入挂单账户，记录挂单ID
+    let ask = place(&mut markets, "Bob", Side::Ask, 100, 10, OrderType::Limit).unwrap();
+    let bid = place(&mut markets, "Alice", Side::Bid, 90, 5, OrderType::Limit).unwrap();
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(markets.balance("Bob", "SOL"), 40);
+    assert_eq!(state.open_orders["Bob"].base_total, 10);
+    assert_eq!(state.open_orders["Bob"].base_locked(), 10);
+    assert_eq!(state.open_orders["Bob"].order_ids(), vec![ask.order_id]);
+    assert_eq!(state.open_orders["Bob"].client_order_ids(), vec![0]);
+    assert_eq!(markets.balance("Alice", "USDC"), 2000 - 450);
+    assert_eq!(state.open_orders["Alice"].quote_locked(), 450);
+
+    // 成交：taker 所得进入挂单账户可用余额，maker 冻结资金换成对手资产
+    let taker = place(&mut markets, "Alice", Side::Bid, 100, 4, OrderType::IOC).unwrap();
+    let state = markets.market(MARKET).unwrap();
+    let alice = &state.open_orders["Alice"];
+    assert_eq!((alice.base_free, alice.base_total), (4, 4));
+    assert_eq!(alice.quote_locked(), 450);
+    let bob = &state.open_orders["Bob"];
+    assert_eq!(bob.base_locked(), 6);
+    assert_eq!(bob.quote_free, 400 - taker.fees_paid);
+
+    // 撤单：剩余冻结资金解冻为可用，挂单ID移除
+    markets
+        .batch_cancel(MARKET, "Alice", &[bid.order_id], NOW)
+        .unwrap();
+    let state = markets.market(MARKET).unwrap();
+    let alice = &state.open_orders["Alice"];
+    assert_eq!(alice.quote_free, 450);
+    assert_eq!(alice.quote_locked(), 0);
+    assert!(alice.order_ids().is_empty());
+
+    // 下单优先使用挂单账户中的可用资金，不足部分才从钱包转入
+    place(&mut markets, "Alice", Side::Bid, 50, 10, OrderType::Limit).unwrap();
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(state.open_orders["Alice"].quote_free, 0);
+    assert_eq!(state.open_orders["Alice"].quote_locked(), 500);
+    assert_eq!(markets.balance("Alice", "USDC"), 2000 - 450 - 400 - 50);
+
+    // settle_funds：可用资金转回钱包，冻结资金不受影响
+    assert_eq!(markets.settle_funds(MARKET, "Alice").unwrap(), (4, 0));
+    assert_eq!(
+        markets.settle_funds(MARKET, "Bob").unwrap(),
+        (0, 400 - taker.fees_paid)
+    );
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(markets.balance("Alice", "SOL"), 104);
+    assert_eq!(state.open_orders["Alice"].base_total, 0);
+    assert_eq!(state.open_orders["Alice"].quote_locked(), 500);
+    assert_eq!(markets.balance("Bob", "USDC"), 1000 + 400 - taker.fees_paid);
+    assert_eq!(state.open_orders["Bob"].base_locked(), 6);
+    assert_eq!(markets.settle_funds(MARKET, "Carol").unwrap(), (0, 0));
+}
+
+#[test]
+fn test_wallet_shared_across_markets() {
+    let mut markets = Markets::new();
+    markets
+        .create_market("SOL/USDC", MarketConfig::new("SOL", "USDC"))
+        .unwrap();
+    markets
+        .create_market("SOL/USDT", MarketConfig::new("SOL", "USDT"))
+        .unwrap();
+    markets.deposit_token("Alice", "SOL", 10).unwrap();
+    markets.deposit_token("Bob", "USDC", 1000).unwrap();
+    markets.deposit_token("Carol", "USDT", 1000).unwrap();
+
+    // 同一份 SOL 余额可以在两个市场卖出
+    for market in ["SOL/USDC", "SOL/USDT"] {
+        markets
+            .place_order(
+                market,
+                "Alice",
+                Side::Ask,
+                100,
+                5,
+                NOW,
+                None,
+                OrderType::Limit,
+                0,
+                SelfTradeBehavior::DecrementTake,
+                None,
+            )
+            .unwrap();
+    }
+    assert_eq!(markets.balance("Alice", "SOL"), 0);
+    let err = markets
+        .place_order(
+            "SOL/USDT",
+            "Alice",
+            Side::Ask,
+            100,
+            1,
+            NOW,
+            None,
+            OrderType::Limit,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(
+        err,
+        DexError::InsufficientBase {
+            needed: 1,
+            available: 0
+        }
+    );
+
+    // 两个市场的成交所得分别按各自报价币结算回同一个钱包
+    markets
+        .place_order(
+            "SOL/USDC",
+            "Bob",
+            Side::Bid,
+            100,
+            5,
+            NOW,
+            None,
+            OrderType::IOC,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap();
+    markets
+        .place_order(
+            "SOL/USDT",
+            "Carol",
+            Side::Bid,
+            100,
+            2,
+            NOW,
+            None,
+            OrderType::IOC,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap();
+    markets.settle_funds("SOL/USDC", "Alice").unwrap();
+    markets.settle_funds("SOL/USDT", "Alice").unwrap();
+    markets.settle_funds("SOL/USDT", "Carol").unwrap();
+    assert_eq!(markets.balance("Alice", "USDC"), 500);
+    assert_eq!(markets.balance("Alice", "USDT"), 200);
+    assert_eq!(markets.balance("Carol", "SOL"), 2);
+
+    // 按币种提现，成功时写入 Markets 上的审计记录，失败时不写
+    markets.withdraw_token("Alice", "USDT", 150, NOW).unwrap();
+    assert_eq!(markets.balance("Alice", "USDT"), 50);
+    let err = markets
+        .withdraw_token("Alice", "USDT", 51, NOW + 1)
+        .unwrap_err();
+    assert_eq!(
+        err,
+        DexError::InsufficientFunds {
+            token: "USDT".to_string(),
+            needed: 51,
+            available: 50
+        }
+    );
+    assert_eq!(
+        markets.token_withdrawals,
+        vec![TokenWithdrawRecord {
+            user: "Alice".to_string(),
+            token: "USDT".to_string(),
+            amount: 150,
+            remaining: 50,
+            timestamp: NOW,
+        }]
+    );
+    assert!(
+        markets
+            .markets
+            .values()
+            .all(|state| state.withdrawals.is_empty())
+    );
+}
+
+#[test]
+fn test_withdraw_respects_locked_funds() {
+    let mut markets = setup();
+    place(&mut markets, "Alice", Side::Bid, 10, 150, OrderType::Limit).unwrap();
+    place(&mut markets, "Bob", Side::Ask, 10, 20, OrderType::IOC).unwrap();
+
+    // Alice 钱包剩 500 USDC，挂单冻结 1300，另有成交所得 20 SOL 在挂单账户
+    let err = markets.withdraw(MARKET, "Alice", 0, 501, NOW).unwrap_err();
+    assert_eq!(
+        err,
+        DexError::InsufficientFreeBalance {
+            token: "USDC".to_string(),
+            requested: 501,
+            free: 500,
+            locked: 1300
+        }
+    );
+    let err = markets.withdraw(MARKET, "Alice", 121, 0, NOW).unwrap_err();
+    assert!(matches!(
+        err,
+        DexError::InsufficientFreeBalance {
+            free: 120,
+            locked: 0,
+            ..
+        }
+    ));
+    assert!(markets.market(MARKET).unwrap().withdrawals.is_empty());
+
+    // 先用挂单账户的可用资金，再用钱包
+    markets.withdraw(MARKET, "Alice", 110, 500, NOW).unwrap();
+    assert_eq!(markets.balance("Alice", "SOL"), 10);
+    assert_eq!(markets.balance("Alice", "USDC"), 0);
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(state.open_orders["Alice"].base_total, 0);
+    assert_eq!(state.open_orders["Alice"].quote_locked(), 1300);
+    assert_eq!(
+        state.withdrawals,
+        vec![WithdrawRecord {
+            user: "Alice".to_string(),
+            base: 110,
+            quote: 500,
+            base_remaining: 10,
+            quote_remaining: 0,
+            timestamp: NOW,
+        }]
+    );
+
+    let err = markets
+        .withdraw("SOL/USDT", "Alice", 1, 0, NOW)
+        .unwrap_err();
+    assert_eq!(err, DexError::MarketNotFound("SOL/USDT".to_string()));
+}
+
+#[test]
+fn test_lot_and_tick_size() {
+    let config = MarketConfig {
+        base_lot_size: 100,
+        quote_lot_size: 10,
+        tick_size: 5,
+        ..config(SettlementMode::Immediate)
+    };
+
+    // lots 换算：价格 5 = 每个 base lot 值 50 个 quote lot
+    assert_eq!(config.base_lots(300), 3);
+    assert_eq!(config.base_native(3), Ok(300));
+    assert_eq!(config.quote_lots(600), 60);
+    assert_eq!(config.quote_native(60), Ok(600));
+    assert_eq!(config.price_lots(5), Ok(50));
+    assert_eq!(config.price_native(50), Ok(5));
+    assert_eq!(config.notional(5, 300), Ok(1500));
+
+    // 非法配置
+    let mut markets = Markets::new();
+    for bad in [
+        MarketConfig {
+            base_lot_size: 0,
+            ..config.clone()
+        },
+        MarketConfig {
+            tick_size: 1,
+            base_lot_size: 3,
+            ..config.clone()
+        },
+        MarketConfig::new("SOL", "SOL"),
+    ] {
+        assert!(matches!(
+            markets.create_market(MARKET, bad),
+            Err(DexError::InvalidMarketConfig(_))
+        ));
+    }
+    assert!(markets.markets.is_empty());
+
+    markets.create_market(MARKET, config.clone()).unwrap();
+    markets.deposit(MARKET, "Alice", 0, 10_000).unwrap();
+    markets.deposit(MARKET, "Bob", 1_000, 0).unwrap();
+
+    // 同名市场已存在：返回错误，原市场保持不变
+    assert_eq!(
+        markets.create_market(MARKET, MarketConfig::new("SOL", "USDC")),
+        Err(DexError::MarketAlreadyExists(MARKET.to_string()))
+    );
+    assert_eq!(markets.market(MARKET).unwrap().config, config);
+
+    // 数量不是 lot 的整数倍、价格不是 tick 的整数倍都被拒绝
+    let err = place(&mut markets, "Bob", Side::Ask, 5, 150, OrderType::Limit).unwrap_err();
+    assert_eq!(
+        err,
+        DexError::InvalidLotSize {
+            quantity: 150,
+            lot_size: 100
+        }
+    );
+    let err = place(&mut markets, "Bob", Side::Ask, 7, 100, OrderType::Limit).unwrap_err();
+    assert_eq!(
+        err,
+        DexError::InvalidTickSize {
+            price: 7,
+            tick_size: 5
+        }
+    );
+    assert_eq!(markets.balance("Bob", "SOL"), 1_000);
+
+    // 成交金额和手续费都按 lots 换算后的金额计算
+    place(&mut markets, "Bob", Side::Ask, 10, 500, OrderType::Limit).unwrap();
+    let outcome = place(&mut markets, "Alice", Side::Bid, 10, 300, OrderType::IOC).unwrap();
+    assert_eq!(outcome.filled_quantity, 300);
+    assert_eq!(outcome.fees_paid, taker_fee(3000));
+    assert_eq!(
+        markets.balance("Alice", "USDC"),
+        10_000 - 3000 - taker_fee(3000)
+    );
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(state.open_orders["Alice"].base_free, 300);
+    assert_eq!(
+        state.open_orders["Bob"].quote_free,
+        3000 + maker_rebate(3000)
+    );
+    assert_eq!(state.open_orders["Bob"].base_locked(), 200);
+}
+
+#[test]
+fn test_maker_taker_fees() {
+    let mut markets = Markets::new();
+    let bad = MarketConfig {
+        maker_rebate_bps: FEE_BPS + 1,
+        ..config(SettlementMode::Immediate)
+    };
+    assert!(matches!(
+        markets.create_market(MARKET, bad),
+        Err(DexError::InvalidMarketConfig(_))
+    ));
+
+    let mut markets = setup();
+    markets.deposit(MARKET, "Alice", 0, 10_000).unwrap();
+    markets.deposit(MARKET, "Carol", 0, 20_000).unwrap();
+
+    // taker 卖单：手续费从卖出所得中扣除，maker 买单获得主币和返佣
+    place(&mut markets, "Carol", Side::Bid, 1000, 5, OrderType::Limit).unwrap();
+    let ask = place(&mut markets, "Bob", Side::Ask, 1000, 2, OrderType::IOC).unwrap();
+    assert_eq!(ask.fees_paid, taker_fee(2000));
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(state.open_orders["Bob"].quote_free, 2000 - taker_fee(2000));
+    let carol = &state.open_orders["Carol"];
+    assert_eq!(carol.base_free, 2);
+    assert_eq!(carol.quote_locked(), 3000);
+    assert_eq!(carol.quote_free, taker_fee(5000) + maker_rebate(2000));
+
+    // taker 买单：冻结成交金额 + 手续费，未成交部分入簿时释放多预留的手续费
+    place(&mut markets, "Bob", Side::Ask, 1100, 2, OrderType::Limit).unwrap();
+    let wallet_before = markets.balance("Alice", "USDC");
+    let bid = place(&mut markets, "Alice", Side::Bid, 1100, 3, OrderType::Limit).unwrap();
+    assert_eq!(bid.filled_quantity, 2);
+    assert_eq!(bid.fees_paid, taker_fee(2200));
+    assert_eq!(
+        markets.balance("Alice", "USDC"),
+        wallet_before - 3300 - taker_fee(3300)
+    );
+    let state = markets.market(MARKET).unwrap();
+    let alice = &state.open_orders["Alice"];
+    assert_eq!(alice.quote_locked(), 1100);
+    assert_eq!(alice.quote_free, taker_fee(3300) - taker_fee(2200));
+
+    let fees = &state.fee_receiver;
+    assert_eq!(fees.taker_fees_collected, taker_fee(2000) + taker_fee(2200));
+    assert_eq!(
+        fees.maker_rebates_paid,
+        maker_rebate(2000) + maker_rebate(2200)
+    );
+    assert_eq!(
+        fees.net_fees().unwrap(),
+        fees.taker_fees_collected - fees.maker_rebates_paid
+    );
+}
+
+#[test]
+fn test_referrer_fee_share() {
+    // 推荐人分成 + maker 返佣不能超过 taker 手续费
+    let mut markets = Markets::new();
+    for share in [8_000, 10_001] {
+        let bad = MarketConfig {
+            referrer_fee_share_bps: share,
+            ..config(SettlementMode::Immediate)
+        };
+        assert!(matches!(
+            markets.create_market(MARKET, bad),
+            Err(DexError::InvalidMarketConfig(_))
+        ));
+    }
+
+    let mut markets = setup_with(MarketConfig {
+        referrer_fee_share_bps: 5_000,
+        ..config(SettlementMode::Immediate)
+    });
+    markets.deposit(MARKET, "Alice", 0, 20_000).unwrap();
+    markets.deposit(MARKET, "Carol", 0, 20_000).unwrap();
+
+    // taker 买单带推荐人：推荐人分得一半手续费，直接进入钱包
+    place(&mut markets, "Bob", Side::Ask, 1000, 10, OrderType::Limit).unwrap();
+    markets
+        .place_order(
+            MARKET,
+            "Alice",
+            Side::Bid,
+            1000,
+            10,
+            NOW,
+            None,
+            OrderType::IOC,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            Some("Dave"),
+        )
+        .unwrap();
+    assert_eq!(markets.balance("Dave", "USDC"), taker_fee(10_000) / 2);
+
+    // taker 卖单带推荐人；不带推荐人的成交手续费全部归平台
+    place(&mut markets, "Carol", Side::Bid, 1000, 6, OrderType::Limit).unwrap();
+    markets
+        .place_order(
+            MARKET,
+            "Bob",
+            Side::Ask,
+            1000,
+            4,
+            NOW,
+            None,
+            OrderType::IOC,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            Some("Dave"),
+        )
+        .unwrap();
+    place(&mut markets, "Bob", Side::Ask, 1000, 2, OrderType::IOC).unwrap();
+
+    let dave = taker_fee(10_000) / 2 + taker_fee(4000) / 2;
+    assert_eq!(markets.balance("Dave", "USDC"), dave);
+    assert_eq!(markets.referrer_rebates(MARKET, "Dave").unwrap(), dave);
+    assert_eq!(markets.referrer_rebates(MARKET, "Alice").unwrap(), 0);
+
+    let state = markets.market(MARKET).unwrap();
+    let referrals: Vec<(Option<&str>, u64)> = state
+        .event_queue
+        .iter()
+        .filter(|e| matches!(e.event_type, EventType::Fill))
+        .map(|e| (e.referrer.as_deref(), e.referrer_rebate))
+        .collect();
+    assert_eq!(
+        referrals,
+        vec![
+            (Some("Dave"), taker_fee(10_000) / 2),
+            (Some("Dave"), taker_fee(4000) / 2),
+            (None, 0),
+        ]
+    );
+    let fees = &state.fee_receiver;
+    assert_eq!(fees.referrer_rebates_paid, dave);
+    assert_eq!(
+        fees.net_fees().unwrap(),
+        taker_fee(10_000) + taker_fee(4000) + taker_fee(2000)
+            - maker_rebate(10_000)
+            - maker_rebate(4000)
+            - maker_rebate(2000)
+            - dave
+    );
+}
+
+#[test]
+fn test_sweep_fees_and_report() {
+    let mut markets = setup_with(MarketConfig {
+        authority: Some("Admin".to_string()),
+        ..config(SettlementMode::Immediate)
+    });
+    markets
+        .create_market("SOL/USDT", MarketConfig::new("SOL", "USDT"))
+        .unwrap();
+    markets.deposit(MARKET, "Alice", 0, 20_000).unwrap();
+    place(&mut markets, "Bob", Side::Ask, 1000, 10, OrderType::Limit).unwrap();
+    place(&mut markets, "Alice", Side::Bid, 1000, 10, OrderType::IOC).unwrap();
+    let net = taker_fee(10_000) - maker_rebate(10_000);
+
+    // 只有市场管理员可以提取；未配置管理员的市场任何人都不能提取
+    assert_eq!(
+        markets.sweep_fees(MARKET, "Alice", NOW),
+        Err(DexError::Unauthorized("Alice".to_string()))
+    );
+    assert_eq!(
+        markets.sweep_fees("SOL/USDT", "Admin", NOW),
+        Err(DexError::Unauthorized("Admin".to_string()))
+    );
+    assert_eq!(markets.balance("Admin", "USDC"), 0);
+
+    assert_eq!(markets.sweep_fees(MARKET, "Admin", NOW), Ok(net));
+    assert_eq!(markets.balance("Admin", "USDC"), net);
+    // 已提取的手续费不会重复提取
+    assert_eq!(markets.sweep_fees(MARKET, "Admin", NOW), Ok(0));
+    assert_eq!(markets.balance("Admin", "USDC"), net);
+
+    let state = markets.market(MARKET).unwrap();
+    let sweeps: Vec<(Option<&str>, u64, Option<Side>)> = state
+        .event_queue
+        .iter()
+        .filter(|e| matches!(e.event_type, EventType::SweepFees))
+        .map(|e| (e.taker.as_deref(), e.quantity, e.side.clone()))
+        .collect();
+    // 提取事件没有方向
+    assert_eq!(
+        sweeps,
+        vec![(Some("Admin"), net, None), (Some("Admin"), 0, None)]
+    );
+
+    let report = markets.fee_report().unwrap();
+    assert_eq!(report.len(), 2);
+    assert_eq!(report[0].market, "SOL/USDC");
+    assert_eq!(report[0].net_fees, net);
+    assert_eq!(report[0].fees_swept, net);
+    assert_eq!(report[0].unswept_fees, 0);
+    assert_eq!(report[1].market, "SOL/USDT");
+    assert_eq!(report[1].net_fees, 0);
+}
+
+#[test]
+fn test_self_trade_prevention() {
+    fn place_stb(
+        markets: &mut Markets,
+        owner: &str,
+        side: Side,
+        quantity: u64,
+        order_type: OrderType,
+        self_trade_behavior: SelfTradeBehavior,
+    ) -> Result<PlaceOrderOutcome, DexError> {
+        markets.place_order(
+            MARKET,
+            owner,
+            side,
+            100,
+            quantity,
+            NOW,
+            None,
+            order_type,
+            0,
+            self_trade_behavior,
+            None,
+        )
+    }
+    // Alice 的卖单在前，Bob 的卖单在后
+    let setup_asks = || {
+        let mut markets = setup_with(MarketConfig::new("SOL", "USDC"));
+        for owner in ["Alice", "Bob"] {
+            place(&mut markets, owner, Side::Ask, 100, 5, OrderType::Limit).unwrap();
+        }
+        markets
+    };
+    let cancels = |markets: &Markets| -> Vec<(u64, u64)> {
+        markets
+            .market(MARKET)
+            .unwrap()
+            .event_queue
+            .iter()
+            .filter(|e| matches!(e.event_type, EventType::Cancel))
+            .map(|e| (e.order_id, e.quantity))
+            .collect()
+    };
+    let outs = |markets: &Markets| -> Vec<(u64, u64)> {
+        markets
+            .market(MARKET)
+            .unwrap()
+            .event_queue
+            .iter()
+            .filter(|e| matches!(e.event_type, EventType::Out))
+            .map(|e| (e.order_id, e.quantity))
+            .collect()
+    };
+
+    // AbortTransaction：拒绝整笔订单，不做任何变更（不清理过期挂单，不写任何事件）
+    let mut markets = setup_asks();
+    markets
+        .place_order(
+            MARKET,
+            "Bob",
+            Side::Ask,
+            100,
+            1,
+            NOW,
+            Some(NOW + 5),
+            OrderType::Limit,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap();
+    let before = format!("{:?}", markets.market(MARKET).unwrap());
+    let wallets_before = format!("{:?}", markets.wallets);
+    let err = markets
+        .place_order(
+            MARKET,
+            "Alice",
+            Side::Bid,
+            100,
+            8,
+            NOW + 10,
+            None,
+            OrderType::Limit,
+            0,
+            SelfTradeBehavior::AbortTransaction,
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(err, DexError::SelfTrade { maker_order_id: 0 });
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(format!("{:?}", state), before);
+    assert_eq!(format!("{:?}", markets.wallets), wallets_before);
+    assert_eq!(state.asks.len(), 3);
+    assert!(state.event_queue.is_empty());
+
+    // CancelProvide：撤掉自己的卖单，继续吃 Bob 的卖单，剩余入簿
+    let mut markets = setup_asks();
+    let outcome = place_stb(
+        &mut markets,
+        "Alice",
+        Side::Bid,
+        8,
+        OrderType::Limit,
+        SelfTradeBehavior::CancelProvide,
+    )
+    .unwrap();
+    assert_eq!(outcome.filled_quantity, 5);
+    assert_eq!(outcome.resting_order_id, Some(2));
+    assert_eq!(cancels(&markets), vec![(0, 5)]);
+    // Alice 的卖单被撤（Out 数量5），Bob 的卖单完全成交（Out 数量0）
+    assert_eq!(outs(&markets), vec![(0, 5), (1, 0)]);
+    let alice = &markets.market(MARKET).unwrap().open_orders["Alice"];
+    assert_eq!((alice.base_free, alice.base_locked()), (10, 0));
+    assert_eq!((alice.quote_free, alice.quote_locked()), (0, 300));
+    assert_eq!(alice.order_ids(), vec![2]);
+
+    // DecrementTake：双方抵消重叠的数量，不成交
+    let mut markets = setup_asks();
+    let outcome = place_stb(
+        &mut markets,
+        "Alice",
+        Side::Bid,
+        8,
+        OrderType::IOC,
+        SelfTradeBehavior::DecrementTake,
+    )
+    .unwrap();
+    assert_eq!(outcome.filled_quantity, 3);
+    assert_eq!(cancels(&markets), vec![(0, 5)]);
+    assert_eq!(outs(&markets), vec![(0, 5)]);
+    let state = markets.market(MARKET).unwrap();
+    let alice = &state.open_orders["Alice"];
+    assert_eq!((alice.base_free, alice.base_locked()), (8, 0));
+    assert_eq!((alice.quote_free, alice.quote_locked()), (500, 0));
+    assert!(alice.order_ids().is_empty());
+    assert_eq!(state.asks.best().map(|o| o.quantity), Some(2));
+
+    // 卖出一侧：Alice 的卖单抵消自己买单的一部分，买单剩余数量继续挂着
+    let mut markets = setup_with(MarketConfig::new("SOL", "USDC"));
+    place(&mut markets, "Alice", Side::Bid, 100, 4, OrderType::Limit).unwrap();
+    let outcome = place_stb(
+        &mut markets,
+        "Alice",
+        Side::Ask,
+        2,
+        OrderType::IOC,
+        SelfTradeBehavior::DecrementTake,
+    )
+    .unwrap();
+    assert_eq!(outcome.filled_quantity, 0);
+    assert_eq!(cancels(&markets), vec![(0, 2)]);
+    // 买单只被部分抵消，仍在簿上，没有 Out
+    assert!(outs(&markets).is_empty());
+    let state = markets.market(MARKET).unwrap();
+    let alice = &state.open_orders["Alice"];
+    assert_eq!((alice.base_free, alice.base_locked()), (2, 0));
+    assert_eq!((alice.quote_free, alice.quote_locked()), (200, 200));
+    assert_eq!(state.bids.best().map(|o| o.quantity), Some(2));
+
+    // FOK：与自己挂单抵消的数量不算成交
+    let mut markets = setup_asks();
+    let err = place_stb(
+        &mut markets,
+        "Alice",
+        Side::Bid,
+        10,
+        OrderType::FOK,
+        SelfTradeBehavior::CancelProvide,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        DexError::FokNotFillable {
+            requested: 10,
+            fillable: 5
+        }
+    );
+}
+
+#[test]
+fn test_post_only() {
+    let mut markets = setup_with(MarketConfig {
+        tick_size: 10,
+        ..config(SettlementMode::Immediate)
+    });
+    place(&mut markets, "Bob", Side::Ask, 100, 5, OrderType::Limit).unwrap();
+
+    // PostOnly 会立即成交则拒绝，不冻结资金
+    let err = place(
+        &mut markets,
+        "Alice",
+        Side::Bid,
+        100,
+        2,
+        OrderType::PostOnly,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        DexError::PostOnlyWouldCross {
+            price: 100,
+            best_price: 100
+        }
+    );
+    assert_eq!(markets.balance("Alice", "USDC"), 2000);
+
+    // 不会成交的 PostOnly 正常挂单，不预留 taker 手续费
+    let outcome = place(&mut markets, "Alice", Side::Bid, 80, 2, OrderType::PostOnly).unwrap();
+    assert_eq!(outcome.resting_order_id, Some(outcome.order_id));
+    assert_eq!(markets.balance("Alice", "USDC"), 2000 - 160);
+
+    // PostOnlySlide 调整到对手方最优价外一个 tick
+    let outcome = place(
+        &mut markets,
+        "Alice",
+        Side::Bid,
+        120,
+        2,
+        OrderType::PostOnlySlide,
+    )
+    .unwrap();
+    assert_eq!(outcome.filled_quantity, 0);
+    let state = markets.market(MARKET).unwrap();
+    assert_eq!(
+        state.bids.best().map(|o| (o.id, o.price)),
+        Some((outcome.order_id, 90))
+    );
+    assert_eq!(state.open_orders["Alice"].quote_locked(), 160 + 180);
+
+    // 卖出一侧
+    let err = place(&mut markets, "Bob", Side::Ask, 90, 1, OrderType::PostOnly).unwrap_err();
+    assert_eq!(
+        err,
+        DexError::PostOnlyWouldCross {
+            price: 90,
+            best_price: 90
+        }
+    );
+    place(
+        &mut markets,
+        "Bob",
+        Side::Ask,
+        50,
+        1,
+        OrderType::PostOnlySlide,
+    )
+    .unwrap();
+    let state = markets.market(MARKET).unwrap();
+    let asks: Vec<(u64, u64)> = state.asks.iter().map(|o| (o.price, o.quantity)).collect();
+    assert_eq!(asks, vec![(100, 5), (100, 1)]);
+    assert!(state.event_queue.is_empty());
+
+    // 买单滑价后价格为0时拒绝
+    let mut markets = setup_with(MarketConfig {
+        tick_size: 10,
+        ..config(SettlementMode::Immediate)
+    });
+    place(&mut markets, "Bob", Side::Ask, 10, 1, OrderType::Limit).unwrap();
+    let err = place(
+        &mut markets,
+        "Alice",
+        Side::Bid,
+        10,
+        1,
+        OrderType::PostOnlySlide,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        DexError::PostOnlyWouldCross {
+            price: 10,
+            best_price: 10
+        }
+    );
+
+    // 对手方最优价跳过已过期的挂单；被拒绝时过期挂单也不会被清理
+    let mut markets = setup_with(MarketConfig {
+        tick_size: 10,
+        ..config(SettlementMode::Immediate)
+    });
+    place(&mut markets, "Bob", Side::Ask, 100, 1, OrderType::Limit).unwrap();
+    markets
+        .place_order(
+            MARKET,
+            "Bob",
+            Side::Ask,
+            90,
+            1,
+            NOW,
+            Some(NOW + 5),
+            OrderType::Limit,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap();
+    let post_only_bid = |markets: &mut Markets, price: u64| {
+        markets.place_order(
+            MARKET,
+            "Alice",
+            Side::Bid,
+            price,
+            1,
+            NOW + 10,
+            None,
+            OrderType::PostOnly,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+    };
+    let before = format!("{:?}", markets.market(MARKET).unwrap());
+    assert_eq!(
+        post_only_bid(&mut markets, 100),
+        Err(DexError::PostOnlyWouldCross {
+            price: 100,
+            best_price: 100
+        })
+    );
+    assert_eq!(format!("{:?}", markets.market(MARKET).unwrap()), before);
+    let outcome = post_only_bid(&mut markets, 90).unwrap();
+    assert_eq!(outcome.resting_order_id, Some(outcome.order_id));
+    let state = markets.market(MARKET).unwrap();
+    let asks: Vec<u64> = state.asks.iter().map(|o| o.price).collect();
+    assert_eq!(asks, vec![100]);
+}
+
+#[test]
+fn test_market_bid_spends_quote_budget() {
+    let mut markets = setup();
+    markets.deposit(MARKET, "Alice", 0, 10_000).unwrap();
+    place(&mut markets, "Bob", Side::Ask, 1000, 3, OrderType::Limit).unwrap();
+    place(&mut markets, "Bob", Side::Ask, 1100, 4, OrderType::Limit).unwrap();
+
+    // 预算 6000（含手续费）：1000 买 3 个花 3009，1100 再买 2 个花 2206，剩余预算不够再买一个
+    let wallet_before = markets.balance("Alice", "USDC");
+    let outcome = place(&mut markets, "Alice", Side::Bid, 0, 6000, OrderType::Market).unwrap();
+    assert_eq!(outcome.filled_quantity, 5);
+    assert_eq!(outcome.fees_paid, taker_fee(3000) + taker_fee(2200));
+    assert_eq!(outcome.resting_order_id, None);
+    assert_eq!(markets.balance("Alice", "USDC"), wallet_before - 6000);
+    let state = markets.market(MARKET).unwrap();
+    let alice = &state.open_orders["Alice"];
+    assert_eq!(alice.base_free, 5);
+    assert_eq!(alice.quote_locked(), 0);
+    assert_eq!(alice.quote_free, 6000 - 3009 - 2206);
+    assert_eq!(
+        state.asks.best().map(|o| (o.price, o.quantity)),
+        Some((1100, 2))
+    );
+
+    // 预算恰好够买 2 个（优先使用挂单账户中的可用资金）
+    let outcome = place(&mut markets, "Alice", Side::Bid, 0, 2206, OrderType::Market).unwrap();
+    assert_eq!(outcome.filled_quantity, 2);
+    let alice = &markets.market(MARKET).unwrap().open_orders["Alice"];
+    assert_eq!(alice.quote_free, 0);
+    assert_eq!(alice.quote_locked(), 0);
+
+    // 订单簿为空：不成交，预算全部解冻
+    let outcome = place(&mut markets, "Alice", Side::Bid, 0, 1000, OrderType::Market).unwrap();
+    assert_eq!(outcome.filled_quantity, 0);
+    let alice = &markets.market(MARKET).unwrap().open_orders["Alice"];
+    assert_eq!(alice.quote_free, 1000);
+    assert_eq!(alice.quote_locked(), 0);
+}
+
+#[test]
+fn test_market_ask_sells_base_quantity() {
+    let mut markets = setup();
+    markets.deposit(MARKET, "Carol", 0, 20_000).unwrap();
+    place(&mut markets, "Carol", Side::Bid, 1000, 2, OrderType::Limit).unwrap();
+    place(&mut markets, "Carol", Side::Bid, 900, 3, OrderType::Limit).unwrap();
+
+    // 卖单忽略价格，按最优买价依次扫单
+    let outcome = place(&mut markets, "Bob", Side::Ask, 5000, 4, OrderType::Market).unwrap();
+    assert_eq!(outcome.filled_quantity, 4);
+    assert_eq!(outcome.fees_paid, taker_fee(2000) + taker_fee(1800));
+    let state = markets.market(MARKET).unwrap();
+    let bob = &state.open_orders["Bob"];
+    assert_eq!(
+        bob.quote_free,
+        2000 - taker_fee(2000) + 1800 - taker_fee(1800)
+    );
+    assert_eq!(bob.base_locked(), 0);
+
+    // 买单不足：成交剩余的 1 个，未成交的主币解冻
+    let outcome = place(&mut markets, "Bob", Side::Ask, 0, 10, OrderType::Market).unwrap();
+    assert_eq!(outcome.filled_quantity, 1);
+    let state = markets.market(MARKET).unwrap();
+    let bob = &state.open_orders["Bob"];
+    assert_eq!(bob.base_free, 9);
+    assert_eq!(bob.base_locked(), 0);
+    assert!(state.bids.is_empty());
+}
+
+/// 总量守恒：钱包 + 挂单账户 + 平台未提取手续费 + 延迟结算中尚未被 crank 处理的成交 = 充值总额
+/// 未处理的成交中，taker 一侧已入账，maker 应得的资金尚未入账，maker 付出的资金仍冻结在挂单账户中
+fn assert_conserved(markets: &Markets, base: u64, quote: u64) {
+    assert_eq!(markets.check_all(), Ok(()));
+    let (market_base, market_quote) = markets.market(MARKET).unwrap().holdings().unwrap();
+    assert_eq!(
+        (
+            markets.wallets.total_balance("SOL") + market_base,
+            markets.wallets.total_balance("USDC") + market_quote
+        ),
+        (base, quote)
+    );
+    assert_eq!(
+        (
+            markets.wallets.supply("SOL"),
+            markets.wallets.supply("USDC")
+        ),
+        (base, quote)
+    );
+}
+
+#[test]
+fn test_price_improvement_refund_and_conservation() {
+    let mut markets = setup();
+    markets.deposit(MARKET, "Alice", 0, 20_000).unwrap();
+    markets.deposit(MARKET, "Carol", 10, 5000).unwrap();
+    let (base, quote) = (160, 28_000);
+    assert_conserved(&markets, base, quote);
+
+    place(&mut markets, "Bob", Side::Ask, 1000, 5, OrderType::Limit).unwrap();
+    assert_conserved(&markets, base, quote);
+
+    // 限价 1200 买 8 个，按 1000 成交 5 个：每个退还 200 的价格改善，剩余 3 个按 1200 冻结
+    let bid = place(&mut markets, "Alice", Side::Bid, 1200, 8, OrderType::Limit).unwrap();
+    assert_eq!(bid.filled_quantity, 5);
+    let alice = &markets.market(MARKET).unwrap().open_orders["Alice"];
+    assert_eq!(alice.quote_locked(), 3 * 1200);
+    assert_eq!(
+        alice.quote_free,
+        5 * 200 + taker_fee(9600) - taker_fee(5000)
+    );
+    assert_conserved(&markets, base, quote);
+
+    // 剩余买单被吃掉一部分，再撤销
+    place(&mut markets, "Carol", Side::Ask, 1100, 2, OrderType::IOC).unwrap();
+    assert_conserved(&markets, base, quote);
+    markets
+        .batch_cancel(MARKET, "Alice", &[bid.order_id], NOW)
+        .unwrap();
+    assert_eq!(
+        markets.market(MARKET).unwrap().open_orders["Alice"].quote_locked(),
+        0
+    );
+    assert_conserved(&markets, base, quote);
+
+    // 市价买单、自成交抵消、结算和提现
+    place(&mut markets, "Bob", Side::Ask, 900, 2, OrderType::Limit).unwrap();
+    place(&mut markets, "Carol", Side::Bid, 0, 3000, OrderType::Market).unwrap();
+    assert_conserved(&markets, base, quote);
+    place(&mut markets, "Bob", Side::Bid, 800, 2, OrderType::Limit).unwrap();
+    place(&mut markets, "Bob", Side::Ask, 800, 1, OrderType::IOC).unwrap();
+    assert_conserved(&markets, base, quote);
+    for user in ["Alice", "Bob", "Carol"] {
+        markets.settle_funds(MARKET, user).unwrap();
+    }
+    markets.withdraw(MARKET, "Alice", 5, 1000, NOW).unwrap();
+    assert_conserved(&markets, base - 5, quote - 1000);
+}
+
+#[test]
+fn test_conservation_with_deferred_settlement() {
+    let mut markets = setup_with(config(SettlementMode::Deferred));
+    markets.deposit(MARKET, "Alice", 0, 20_000).unwrap();
+    let (base, quote) = (150, 23_000);
+
+    place(&mut markets, "Bob", Side::Ask, 1000, 5, OrderType::Limit).unwrap();
+    place(&mut markets, "Alice", Side::Bid, 1000, 3, OrderType::Limit).unwrap();
+    place(&mut markets, "Alice", Side::Bid, 900, 4, OrderType::Limit).unwrap();
+    place(&mut markets, "Bob", Side::Ask, 900, 3, OrderType::IOC).unwrap();
+    // crank 之前 maker 的资金仍冻结在挂单账户中
+    assert_eq!(markets.market(MARKET).unwrap().pending_fills().len(), 2);
+    assert_conserved(&markets, base, quote);
+
+    markets.crank(MARKET, 1).unwrap();
+    assert_eq!(markets.market(MARKET).unwrap().pending_fills().len(), 1);
+    assert_conserved(&markets, base, quote);
+    markets.crank(MARKET, 10).unwrap();
+    assert!(markets.market(MARKET).unwrap().pending_fills().is_empty());
+    assert_conserved(&markets, base, quote);
+}
+
+#[test]
+fn test_check_invariants_detects_corruption() {
+    let mut markets = setup();
+    place(&mut markets, "Bob", Side::Ask, 110, 5, OrderType::Limit).unwrap();
+    place(&mut markets, "Alice", Side::Bid, 100, 5, OrderType::Limit).unwrap();
+    assert_eq!(markets.check_all(), Ok(()));
+
+    // 冻结资金与挂单不一致
+    let mut corrupted = setup();
+    place(&mut corrupted, "Alice", Side::Bid, 100, 5, OrderType::Limit).unwrap();
+    let state = corrupted.market_mut(MARKET).unwrap();
+    state.open_orders.get_mut("Alice").unwrap().quote_free += 1;
+    assert!(matches!(
+        state.check_invariants(),
+        Err(DexError::InvariantViolation(_))
+    ));
+
+    // 凭空多出的资金：市场内部一致，但总量不守恒
+    let mut minted = setup();
+    let state = minted.market_mut(MARKET).unwrap();
+    let alice = state.open_orders.entry("Alice".to_string()).or_default();
+    alice.quote_free += 10;
+    alice.quote_total += 10;
+    assert_eq!(state.check_invariants(), Ok(()));
+    assert!(matches!(
+        minted.check_all(),
+        Err(DexError::InvariantViolation(_))
+    ));
+
+    // 挂单账户总额少于未处理成交中 maker 应付的数量：持有量为负
+    let mut negative = Markets::new();
+    negative
+        .create_market(MARKET, config(SettlementMode::Deferred))
+        .unwrap();
+    negative.deposit(MARKET, "Alice", 0, 20_000).unwrap();
+    negative.deposit(MARKET, "Bob", 50, 0).unwrap();
+    place(&mut negative, "Bob", Side::Ask, 1000, 10, OrderType::Limit).unwrap();
+    place(&mut negative, "Alice", Side::Bid, 1000, 4, OrderType::IOC).unwrap();
+    let state = negative.market_mut(MARKET).unwrap();
+    for oo in state.open_orders.values_mut() {
+        oo.base_total = 0;
+    }
+    assert!(matches!(
+        state.holdings(),
+        Err(DexError::InvariantViolation(_))
+    ));
+
+    // 挂单账户记录的挂单与订单簿不一致
+    let mut orphan = setup();
+    let id = place(&mut orphan, "Bob", Side::Ask, 110, 5, OrderType::Limit)
+        .unwrap()
+        .order_id;
+    orphan
+        .market_mut(MARKET)
+        .unwrap()
+        .open_orders
+        .get_mut("Bob")
+        .unwrap()
+        .orders
+        .remove(&id);
+    assert!(matches!(
+        orphan.check_all(),
+        Err(DexError::InvariantViolation(_))
+    ));
+}
@@ -0,0 +1,290 @@
+use step06_multi_order_type::error::DexError;
+use step06_multi_order_type::market_config::MarketConfig;
+use step06_multi_order_type::math;
+use step06_multi_order_type::openbook::{
+    FeeReceiver, MarketState, Markets, OrderType, SettlementMode, Side,
+};
+
+mod common;
+
+use common::{Lcg, MARKET, place, place_referred};
+
+/// 共用配置加上推荐人分成，覆盖推荐人返佣的溢出路径
+fn config(settlement_mode: SettlementMode) -> MarketConfig {
+    MarketConfig {
+        referrer_fee_share_bps: 2_000,
+        ..common::config(settlement_mode)
+    }
+}
+
+#[test]
+fn test_checked_math_helpers() {
+    assert_eq!(math::add(u64::MAX - 1, 1), Ok(u64::MAX));
+    assert_eq!(math::add(u64::MAX, 1), Err(DexError::Overflow));
+    assert_eq!(math::sub(1, 2), Err(DexError::Overflow));
+    assert_eq!(math::mul(u64::MAX, 2), Err(DexError::Overflow));
+    assert_eq!(math::mul_div(u64::MAX, 3, 3), Ok(u64::MAX));
+    assert_eq!(math::mul_div(u64::MAX, 3, 2), Err(DexError::Overflow));
+    assert_eq!(math::mul_div(1, 1, 0), Err(DexError::Overflow));
+    // 手续费中间结果用 u128：u64::MAX × 30 不会溢出
+    assert_eq!(
+        math::bps(u64::MAX, 30),
+        Ok((u64::MAX as u128 * 30 / 10_000) as u64)
+    );
+
+    let mut balance = u64::MAX;
+    assert_eq!(math::add_to(&mut balance, 1), Err(DexError::Overflow));
+    assert_eq!(balance, u64::MAX);
+    assert_eq!(math::sub_from(&mut balance, u64::MAX), Ok(()));
+    assert_eq!(math::sub_from(&mut balance, 1), Err(DexError::Overflow));
+    assert_eq!(balance, 0);
+
+    let config = MarketConfig {
+        base_lot_size: 100,
+        quote_lot_size: 10,
+        tick_size: 10,
+        ..config(SettlementMode::Immediate)
+    };
+    assert_eq!(config.price_lots(u64::MAX), Err(DexError::Overflow));
+    assert_eq!(config.notional(u64::MAX / 10, 200), Err(DexError::Overflow));
+    assert_eq!(
+        config.notional(u32::MAX as u64, 100),
+        Ok(u32::MAX as u64 * 100)
+    );
+    // 一个 lot 都买不起（成交金额超出 u64）时预算买不到任何数量
+    assert_eq!(config.max_base_for_quote(u64::MAX / 2, u64::MAX, 0), 0);
+    // 超大预算不会溢出，买到的数量不超过主币上限
+    let quantity = config.max_base_for_quote(10, u64::MAX, 0);
+    assert!(quantity > 0 && quantity.is_multiple_of(100));
+    assert!(config.taker_cost(10, quantity, 0).is_ok());
+}
+
+#[test]
+fn test_fee_account_and_market_config_are_checked() {
+    // 手续费账户记账出错（返佣超过手续费）时返回 Overflow，而不是下溢 panic
+    let fees = FeeReceiver {
+        taker_fees_collected: 10,
+        maker_rebates_paid: 20,
+        ..FeeReceiver::default()
+    };
+    assert_eq!(fees.net_fees(), Err(DexError::Overflow));
+    assert_eq!(fees.unswept_fees(), Err(DexError::Overflow));
+    let fees = FeeReceiver {
+        taker_fees_collected: 30,
+        maker_rebates_paid: 10,
+        referrer_rebates_paid: 5,
+        fees_swept: 20,
+    };
+    assert_eq!(fees.net_fees(), Ok(15));
+    assert_eq!(fees.unswept_fees(), Err(DexError::Overflow));
+
+    // 直接构造 MarketState 也要校验配置：返佣高于手续费的市场不能创建
+    let bad = MarketConfig {
+        maker_rebate_bps: 50,
+        ..MarketConfig::new("SOL", "USDC")
+    };
+    assert!(matches!(
+        MarketState::new(bad),
+        Err(DexError::InvalidMarketConfig(_))
+    ));
+    assert!(MarketState::new(config(SettlementMode::Deferred)).is_ok());
+}
+
+#[test]
+fn test_extreme_orders_return_overflow() {
+    let mut markets = Markets::new();
+    markets
+        .create_market(MARKET, config(SettlementMode::Immediate))
+        .unwrap();
+    markets.deposit(MARKET, "Alice", 10, u64::MAX).unwrap();
+    markets.deposit(MARKET, "Bob", u64::MAX - 10, 0).unwrap();
+
+    // 充值使流通总量超出 u64：拒绝，不做任何变更
+    assert_eq!(
+        markets.deposit(MARKET, "Carol", 1, 1),
+        Err(DexError::Overflow)
+    );
+    assert_eq!(
+        markets.deposit_token("Carol", "USDC", 1),
+        Err(DexError::Overflow)
+    );
+    assert_eq!(markets.balance("Carol", "SOL"), 0);
+    assert_eq!(markets.wallets.supply("SOL"), u64::MAX);
+
+    // 买单成交金额 price × quantity 溢出
+    assert_eq!(
+        place(
+            &mut markets,
+            "Alice",
+            Side::Bid,
+            u64::MAX,
+            2,
+            OrderType::Limit
+        ),
+        Err(DexError::Overflow)
+    );
+    // 成交金额不溢出，但加上预留的 taker 手续费后溢出
+    assert_eq!(
+        place(
+            &mut markets,
+            "Alice",
+            Side::Bid,
+            u64::MAX,
+            1,
+            OrderType::Limit
+        ),
+        Err(DexError::Overflow)
+    );
+    assert_eq!(markets.balance("Alice", "USDC"), u64::MAX);
+    assert!(markets.market(MARKET).unwrap().bids.is_empty());
+
+    // 接近上限的价格可以挂单和成交，手续费按 u128 中间结果计算
+    let price = u64::MAX / 2;
+    place(&mut markets, "Bob", Side::Ask, price, 1, OrderType::Limit).unwrap();
+    place(&mut markets, "Alice", Side::Bid, price, 1, OrderType::IOC).unwrap();
+    let fee = (price as u128 * 30 / 10_000) as u64;
+    let fees = &markets.market(MARKET).unwrap().fee_receiver;
+    assert_eq!(fees.taker_fees_collected, fee);
+    assert_eq!(markets.check_all(), Ok(()));
+
+    // 卖单数量很大、价格超出 u64 的成交金额：作为 maker 挂单没问题，吃单时不会溢出成交
+    place(
+        &mut markets,
+        "Bob",
+        Side::Ask,
+        2,
+        u64::MAX - 11,
+        OrderType::Limit,
+    )
+    .unwrap();
+    assert_eq!(markets.check_all(), Ok(()));
+    assert_eq!(
+        place(
+            &mut markets,
+            "Alice",
+            Side::Bid,
+            2,
+            u64::MAX / 2 + 1,
+            OrderType::IOC
+        ),
+        Err(DexError::Overflow)
+    );
+    // 市价买单按预算吃单，不会超出预算
+    let budget = markets.balance("Alice", "USDC");
+    place(
+        &mut markets,
+        "Alice",
+        Side::Bid,
+        0,
+        budget,
+        OrderType::Market,
+    )
+    .unwrap();
+    assert_eq!(markets.check_all(), Ok(()));
+}
+
+/// 随机下单/改单/撤单/结算/提现，价格和数量偏向边界值：
+/// 任何操作要么成功，要么返回 DexError，不能 panic；每一步之后全部不变量和总量守恒都成立
+#[test]
+fn test_fuzz_extreme_prices_and_quantities() {
+    let users = ["Alice", "Bob", "Carol"];
+    let order_types = [
+        OrderType::Limit,
+        OrderType::Market,
+        OrderType::IOC,
+        OrderType::FOK,
+        OrderType::PostOnly,
+        OrderType::PostOnlySlide,
+    ];
+    for seed in 0..16 {
+        let mut rng = Lcg(seed);
+        let settlement_mode = match seed % 2 {
+            0 => SettlementMode::Immediate,
+            _ => SettlementMode::Deferred,
+        };
+        let mut markets = Markets::new();
+        markets
+            .create_market(MARKET, config(settlement_mode))
+            .unwrap();
+        for user in users {
+            markets
+                .deposit(MARKET, user, u64::MAX / 4, u64::MAX / 4)
+                .unwrap();
+        }
+
+        for step in 0..300 {
+            let user = users[rng.below(3) as usize];
+            let result = match rng.below(10) {
+                0 => {
+                    let state = markets.market(MARKET).unwrap();
+                    let ids = state
+                        .open_orders
+                        .get(user)
+                        .map(|oo| oo.order_ids())
+                        .unwrap_or_default();
+                    match ids.first() {
+                        Some(&id) => markets.batch_cancel(MARKET, user, &[id], 0),
+                        None => Ok(()),
+                    }
+                }
+                1 => markets.settle_funds(MARKET, user).map(|_| ()),
+                2 => markets.crank(MARKET, 8).map(|_| ()),
+                3 => markets.withdraw(MARKET, user, rng.extreme(), 0, 0),
+                4 => markets.deposit(MARKET, user, rng.extreme(), rng.extreme()),
+                5 => {
+                    let state = markets.market(MARKET).unwrap();
+                    let id = state
+                        .open_orders
+                        .get(user)
+                        .and_then(|oo| oo.order_ids().last().copied());
+                    let (price, quantity) = (rng.extreme(), rng.extreme());
+                    match id {
+                        Some(id) => markets
+                            .replace_order(MARKET, user, id, price, quantity, 0)
+                            .map(|_| ()),
+                        None => Ok(()),
+                    }
+                }
+                _ => {
+                    let side = match rng.below(2) {
+                        0 => Side::Bid,
+                        _ => Side::Ask,
+                    };
+                    let order_type = order_types[rng.below(6) as usize].clone();
+                    let (price, quantity) = (rng.extreme(), rng.extreme());
+                    let referrer = Some("Referrer");
+                    place_referred(
+                        &mut markets,
+                        user,
+                        side,
+                        price,
+                        quantity,
+                        order_type,
+                        referrer,
+                    )
+                    .map(|_| ())
+                }
+            };
+            if let Err(err) = result {
+                assert!(
+                    matches!(
+                        err,
+                        DexError::Overflow
+                            | DexError::InsufficientBase { .. }
+                            | DexError::InsufficientQuote { .. }
+                            | DexError::InsufficientFreeBalance { .. }
+                            | DexError::FokNotFillable { .. }
+                            | DexError::PostOnlyWouldCross { .. }
+                            | DexError::AmendWouldCross { .. }
+                            | DexError::QueueFull
+                    ),
+                    "seed {} step {}: {:?}",
+                    seed,
+                    step,
+                    err
+                );
+            }
+            assert_eq!(markets.check_all(), Ok(()), "seed {} step {}", seed, step);
+        }
+    }
+}
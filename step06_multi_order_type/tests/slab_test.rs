@@ -0,0 +1,334 @@
+use step06_multi_order_type::book_side::BookSide;
+use step06_multi_order_type::error::DexError;
+use step06_multi_order_type::event_queue::EventType;
+use step06_multi_order_type::market_config::MarketConfig;
+use step06_multi_order_type::openbook::{
+    MarketState, Markets, Order, OrderType, SelfTradeBehavior, Side,
+};
+use step06_multi_order_type::slab::{LeafNode, Slab, SlabError, order_key};
+use step06_multi_order_type::wallet::Wallets;
+
+mod common;
+
+use common::{Lcg, MARKET, place};
+
+fn new_order(id: u64, side: &Side, price: u64, quantity: u64) -> Order {
+    Order {
+        id,
+        owner: format!("user{}", id % 7),
+        side: side.clone(),
+        price,
+        quantity,
+        expire_ts: None,
+        order_type: OrderType::Limit,
+        client_order_id: 0,
+    }
+}
+
+/// step06 原版订单簿的一侧：push 后按价格稳定排序，最优订单在 [0]
+struct VecModel {
+    side: Side,
+    orders: Vec<Order>,
+}
+
+impl VecModel {
+    fn insert(&mut self, order: Order) {
+        self.orders.push(order);
+        match self.side {
+            Side::Bid => self.orders.sort_by_key(|o| std::cmp::Reverse(o.price)),
+            Side::Ask => self.orders.sort_by_key(|o| o.price),
+        }
+    }
+
+    fn remove(&mut self, id: u64) -> Option<Order> {
+        let pos = self.orders.iter().position(|o| o.id == id)?;
+        Some(self.orders.remove(pos))
+    }
+}
+
+fn snapshot<'a>(orders: impl Iterator<Item = &'a Order>) -> Vec<(u64, u64, u64)> {
+    orders.map(|o| (o.id, o.price, o.quantity)).collect()
+}
+
+/// 随机执行挂单/撤单/吃单/部分成交，slab 与价格档位两种存储都必须与原版 Vec 行为一致
+fn check_against_vec_model(seed: u64, side: Side) {
+    let mut rng = Lcg(seed);
+    let mut model = VecModel {
+        side: side.clone(),
+        orders: vec![],
+    };
+    let mut slab_book = BookSide::with_slab(side.clone(), 10_000);
+    let mut level_book = BookSide::new(side.clone());
+    let mut next_id = 0;
+
+    for _ in 0..500 {
+        match rng.below(10) {
+            // 挂单（价格范围很小，制造大量同价位订单）
+            0..=4 => {
+                let order = new_order(next_id, &side, 1 + rng.below(20), 1 + rng.below(50));
+                next_id += 1;
+                model.insert(order.clone());
+                assert!(slab_book.insert(order.clone()).is_none());
+                assert!(level_book.insert(order).is_none());
+            }
+            // 撤单（可能撤一个不存在的ID）
+            5 | 6 => {
+                let id = rng.below(next_id + 1);
+                let expected = model.remove(id).map(|o| o.id);
+                assert_eq!(slab_book.remove(id).map(|o| o.id), expected);
+                assert_eq!(level_book.remove(id).map(|o| o.id), expected);
+            }
+            // 吃掉最优订单
+            7 => {
+                let expected = if model.orders.is_empty() {
+                    None
+                } else {
+                    Some(model.orders.remove(0).id)
+                };
+                assert_eq!(slab_book.pop_best().map(|o| o.id), expected);
+                assert_eq!(level_book.pop_best().map(|o| o.id), expected);
+            }
+            // 部分成交最优订单
+            _ => {
+                if let Some(best) = model.orders.first_mut() {
+                    let deal = 1 + rng.below(best.quantity);
+                    best.quantity -= deal;
+                    slab_book.best_mut().unwrap().quantity -= deal;
+                    level_book.best_mut().unwrap().quantity -= deal;
+                    if best.quantity == 0 {
+                        model.orders.remove(0);
+                        slab_book.pop_best();
+                        level_book.pop_best();
+                    }
+                }
+            }
+        }
+
+        let expected = snapshot(model.orders.iter());
+        assert_eq!(snapshot(slab_book.iter()), expected, "seed {}", seed);
+        assert_eq!(snapshot(level_book.iter()), expected, "seed {}", seed);
+        assert_eq!(slab_book.len(), model.orders.len());
+        assert_eq!(
+            slab_book.best_price(),
+            model.orders.first().map(|o| o.price)
+        );
+        for order in &model.orders {
+            assert_eq!(
+                slab_book.get(order.id).map(|o| o.quantity),
+                Some(order.quantity)
+            );
+        }
+    }
+}
+
+#[test]
+fn test_slab_book_matches_vec_model() {
+    for seed in 0..50 {
+        check_against_vec_model(seed, Side::Bid);
+        check_against_vec_model(seed, Side::Ask);
+    }
+}
+
+#[test]
+fn test_slab_raw_operations() {
+    let mut rng = Lcg(2024);
+    let mut slab = Slab::with_capacity(2 * 300 - 1);
+    let mut keys: Vec<u128> = vec![];
+
+    for seq in 0..2_000u64 {
+        if keys.len() < 300 && rng.below(3) != 0 {
+            let key = order_key(&Side::Ask, 1 + rng.below(100), seq);
+            let leaf = LeafNode::new(key, new_order(seq, &Side::Ask, (key >> 64) as u64, 1));
+            let (handle, old) = slab.insert_leaf(&leaf).unwrap();
+            assert!(old.is_none());
+            assert_eq!(slab.get_leaf(handle).unwrap().key, key);
+            keys.push(key);
+            keys.sort();
+        } else if !keys.is_empty() {
+            let key = keys.remove(rng.below(keys.len() as u64) as usize);
+            assert_eq!(slab.remove_by_key(key).map(|l| l.key), Some(key));
+            assert!(slab.remove_by_key(key).is_none());
+        }
+
+        assert_eq!(slab.len(), keys.len());
+        let min = slab.find_min().map(|h| slab.get_leaf(h).unwrap().key);
+        let max = slab.find_max().map(|h| slab.get_leaf(h).unwrap().key);
+        assert_eq!(min, keys.first().copied());
+        assert_eq!(max, keys.last().copied());
+        let ascending: Vec<u128> = slab.iter().map(|l| l.key).collect();
+        assert_eq!(ascending, keys);
+        let descending: Vec<u128> = slab.iter_rev().map(|l| l.key).collect();
+        assert!(descending.iter().eq(keys.iter().rev()));
+    }
+}
+
+#[test]
+fn test_slab_out_of_space_and_overwrite() {
+    // 3个节点：最多容纳2个叶子
+    let mut slab = Slab::with_capacity(3);
+    let leaf = |seq: u64, price: u64| {
+        LeafNode::new(
+            order_key(&Side::Bid, price, seq),
+            new_order(seq, &Side::Bid, price, 1),
+        )
+    };
+    slab.insert_leaf(&leaf(0, 10)).unwrap();
+    slab.insert_leaf(&leaf(1, 11)).unwrap();
+    assert!(matches!(
+        slab.insert_leaf(&leaf(2, 12)),
+        Err(SlabError::OutOfSpace)
+    ));
+    assert_eq!(slab.len(), 2);
+
+    // 相同 key 直接覆盖
+    let (_, old) = slab.insert_leaf(&leaf(1, 11)).unwrap();
+    assert_eq!(old.map(|l| l.price()), Some(11));
+
+    // 释放的节点可被复用
+    slab.remove_max().unwrap();
+    slab.insert_leaf(&leaf(2, 12)).unwrap();
+    assert_eq!(slab.get_leaf(slab.find_max().unwrap()).unwrap().price(), 12);
+}
+
+#[test]
+fn test_full_slab_book_bumps_worst_order() {
+    let mut bids = BookSide::with_slab(Side::Bid, 2);
+    assert!(bids.insert(new_order(0, &Side::Bid, 10, 1)).is_none());
+    assert!(bids.insert(new_order(1, &Side::Bid, 12, 1)).is_none());
+
+    // 更优的买单挤出最低价买单
+    let bumped = bids.insert(new_order(2, &Side::Bid, 11, 1)).unwrap();
+    assert_eq!(bumped.id, 0);
+    // 更差的买单自身不入簿
+    let bumped = bids.insert(new_order(3, &Side::Bid, 9, 1)).unwrap();
+    assert_eq!(bumped.id, 3);
+
+    let ids: Vec<u64> = bids.iter().map(|o| o.id).collect();
+    assert_eq!(ids, vec![1, 2]);
+    assert!(bids.get(0).is_none());
+}
+
+#[test]
+fn test_slab_backed_market() {
+    let mut state = MarketState::with_slab(MarketConfig::new("SOL", "USDC"), 2).unwrap();
+    let mut wallets = Wallets::new();
+    state.deposit(&mut wallets, "Alice", 0, 1000).unwrap();
+    state.deposit(&mut wallets, "Bob", 10, 0).unwrap();
+    for price in [10, 12, 11] {
+        state
+            .place_order(
+                &mut wallets,
+                "SOL/USDC",
+                "Alice",
+                Side::Bid,
+                price,
+                1,
+                0,
+                None,
+                OrderType::Limit,
+                0,
+                SelfTradeBehavior::DecrementTake,
+                None,
+            )
+            .unwrap();
+    }
+    // 容量为2：最低价的买单被挤出
+    let prices: Vec<u64> = state.bids.iter().map(|o| o.price).collect();
+    assert_eq!(prices, vec![12, 11]);
+    // 被挤出的挂单写 Out 事件，数量为撤销的未成交数量
+    let out = state.event_queue.iter().last().unwrap();
+    assert!(matches!(out.event_type, EventType::Out));
+    assert_eq!((out.order_id, out.price, out.quantity), (0, Some(10), 1));
+
+    let outcome = state
+        .place_order(
+            &mut wallets,
+            "SOL/USDC",
+            "Bob",
+            Side::Ask,
+            11,
+            3,
+            0,
+            None,
+            OrderType::IOC,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap();
+    assert_eq!(outcome.filled_quantity, 2);
+    assert!(state.bids.is_empty());
+}
+
+#[test]
+fn test_slab_replace_order_when_full() {
+    let mut state = MarketState::with_slab(MarketConfig::new("SOL", "USDC"), 2).unwrap();
+    let mut wallets = Wallets::new();
+    state.deposit(&mut wallets, "Alice", 0, 1000).unwrap();
+    let ids: Vec<u64> = [10, 12]
+        .into_iter()
+        .map(|price| {
+            state
+                .place_order(
+                    &mut wallets,
+                    "SOL/USDC",
+                    "Alice",
+                    Side::Bid,
+                    price,
+                    2,
+                    0,
+                    None,
+                    OrderType::Limit,
+                    0,
+                    SelfTradeBehavior::DecrementTake,
+                    None,
+                )
+                .unwrap()
+                .order_id
+        })
+        .collect();
+
+    // 原地减少数量
+    assert_eq!(
+        state.replace_order(&mut wallets, "SOL/USDC", "Alice", ids[1], 12, 1, 0),
+        Ok(true)
+    );
+    assert_eq!(state.bids.get(ids[1]).unwrap().quantity, 1);
+    // 订单簿已满时改价重新排队，不会挤出其他挂单
+    assert_eq!(
+        state.replace_order(&mut wallets, "SOL/USDC", "Alice", ids[0], 13, 2, 0),
+        Ok(false)
+    );
+    let bids: Vec<(u64, u64)> = state.bids.iter().map(|o| (o.id, o.price)).collect();
+    assert_eq!(bids, vec![(ids[0], 13), (ids[1], 12)]);
+}
+
+#[test]
+fn test_create_slab_market_from_config() {
+    let mut markets = Markets::new();
+    let bad = MarketConfig {
+        slab_capacity: Some(0),
+        ..MarketConfig::new("SOL", "USDC")
+    };
+    assert!(matches!(
+        markets.create_market(MARKET, bad),
+        Err(DexError::InvalidMarketConfig(_))
+    ));
+
+    // create_market 按 slab_capacity 建出 slab 订单簿，满了之后挤出最差的挂单
+    let config = MarketConfig {
+        slab_capacity: Some(2),
+        ..MarketConfig::new("SOL", "USDC")
+    };
+    markets.create_market(MARKET, config).unwrap();
+    markets.deposit(MARKET, "Alice", 0, 1000).unwrap();
+    for price in [10, 12, 11] {
+        place(&mut markets, "Alice", Side::Bid, price, 1, OrderType::Limit).unwrap();
+    }
+    let state = markets.market(MARKET).unwrap();
+    let prices: Vec<u64> = state.bids.iter().map(|o| o.price).collect();
+    assert_eq!(prices, vec![12, 11]);
+    // 被挤出挂单冻结的资金退回挂单账户
+    let alice = &state.open_orders["Alice"];
+    assert_eq!((alice.quote_locked(), alice.quote_free), (12 + 11, 10));
+}